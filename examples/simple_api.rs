@@ -1,7 +1,7 @@
 use std::sync::{Mutex, RwLock};
 
 use lock_ordering::{
-    lock::{MutexLockLevel, RwLockLevel}, relation::LockAfter, LockLevel, LockedAt, MutualExclusion, ReadWrite,
+    lock::{MutexLockLevel, RwLockLevel}, lock_path, relation::LockAfter, LockLevel, LockedAt, MutualExclusion, ReadWrite,
     Unlocked,
 };
 
@@ -79,16 +79,14 @@ fn main() {
             scope.spawn(|| {
                 let mut locked = LockedAt::new();
 
-                let (mut locked, mut a_guard) =
-                    locked.with_lock::<LockA>(&state.sibling.a).unwrap();
+                lock_path!(
+                    let (mut locked, (mut a_guard, mut b_guard, c_guard)) = locked;
+                    (LockA, &state.sibling.a),
+                    (LockB, &state.sibling.b),
+                    (LockC, read &state.nested.c),
+                );
 
-                let (mut locked, mut b_guard) =
-                    locked.with_lock::<LockB>(&state.sibling.b).unwrap();
-
-                let (mut locked, c_guard) =
-                    locked.with_read_lock::<LockC>(&state.nested.c).unwrap();
-
-                let mut d_guard = locked.lock::<LockD>(&(*c_guard).d).unwrap();
+                let mut d_guard = locked.lock::<LockD>(&c_guard.d).unwrap();
 
                 // Perform some work with the locked state.
                 *d_guard = d_guard.wrapping_add(*a_guard as u8);
@@ -0,0 +1,170 @@
+//! Procedural macros for the [`lock_ordering`] crate.
+//!
+//! These are re-exported from `lock_ordering` when its `macros` feature is
+//! enabled and shouldn't need to be depended on directly.
+//!
+//! [`lock_ordering`]: https://docs.rs/lock_ordering
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{parse_macro_input, DeriveInput, Ident, ItemFn, LitStr, Type};
+
+/// Marks a function as one that is allowed to acquire locks.
+///
+/// The body of the annotated function gets access to a local variable named
+/// `lock_section` of type `lock_ordering::LockSection`. The token is branded
+/// with a lifetime local to the function, so it can be passed down to helpers
+/// but can't escape the call. See `LockedAt::with_lock_in_section`, which is
+/// the only acquisition method that requires the token; the others can still
+/// be called from functions without the attribute.
+#[proc_macro_attribute]
+pub fn lock_section(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        return syn::Error::new_spanned(
+            proc_macro2::TokenStream::from(attr),
+            "#[lock_section] does not take any arguments",
+        )
+        .into_compile_error()
+        .into();
+    }
+
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = parse_macro_input!(item as ItemFn);
+
+    let brand = Ident::new("__lock_section_brand", Span::mixed_site());
+    let token = Ident::new("lock_section", Span::call_site());
+
+    quote! {
+        #(#attrs)* #vis #sig {
+            let #brand = ();
+            #[allow(unused_variables, unsafe_code)]
+            // SAFETY: this is the body of a function annotated with
+            // `#[lock_section]`, and the token can't outlive it.
+            let #token = unsafe { ::lock_ordering::LockSection::new_unchecked(&#brand) };
+            #block
+        }
+    }
+    .into()
+}
+
+/// Derives `LockLevel` and the matching lock level traits for a marker type.
+///
+/// The lock type for the level is given with a `#[lock(...)]` attribute that
+/// names it as a string:
+///
+/// - `mutex = "..."` implements `MutexLockLevel`,
+/// - `rwlock = "..."` implements `RwLockLevel`,
+/// - `async_mutex = "..."` implements `AsyncMutexLockLevel`, and
+/// - `async_rwlock = "..."` implements `AsyncRwLockLevel`.
+///
+/// A level can have both a synchronous and an asynchronous lock type, but its
+/// locks either all provide mutual exclusion or all provide shared/exclusive
+/// access, so mutex and rwlock keys can't be mixed.
+///
+/// ```ignore
+/// #[derive(LockLevel)]
+/// #[lock(mutex = "std::sync::Mutex<usize>")]
+/// enum Counter {}
+/// ```
+#[proc_macro_derive(LockLevel, attributes(lock))]
+pub fn derive_lock_level(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as DeriveInput);
+    lock_level_impls(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn lock_level_impls(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let mut mutex = None;
+    let mut rwlock = None;
+    let mut async_mutex = None;
+    let mut async_rwlock = None;
+
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("lock")) {
+        attr.parse_nested_meta(|meta| {
+            let slot = if meta.path.is_ident("mutex") {
+                &mut mutex
+            } else if meta.path.is_ident("rwlock") {
+                &mut rwlock
+            } else if meta.path.is_ident("async_mutex") {
+                &mut async_mutex
+            } else if meta.path.is_ident("async_rwlock") {
+                &mut async_rwlock
+            } else {
+                return Err(meta
+                    .error("expected one of `mutex`, `rwlock`, `async_mutex`, or `async_rwlock`"));
+            };
+            if slot.is_some() {
+                return Err(meta.error("lock type specified more than once"));
+            }
+            *slot = Some(meta.value()?.parse::<LitStr>()?.parse::<Type>()?);
+            Ok(())
+        })?;
+    }
+
+    let exclusive = mutex.is_some() || async_mutex.is_some();
+    let shared = rwlock.is_some() || async_rwlock.is_some();
+    let method = match (exclusive, shared) {
+        (true, false) => quote!(::lock_ordering::MutualExclusion),
+        (false, true) => quote!(::lock_ordering::ReadWrite),
+        (true, true) => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "a lock level can't have both a mutex and an rwlock",
+            ))
+        }
+        (false, false) => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "missing #[lock(mutex = \"...\")] or #[lock(rwlock = \"...\")] attribute",
+            ))
+        }
+    };
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let level_impl = |trait_name: &str, assoc: &str, ty: Option<Type>| {
+        ty.map(|ty| {
+            let trait_name = Ident::new(trait_name, Span::call_site());
+            let assoc = Ident::new(assoc, Span::call_site());
+            quote! {
+                impl #impl_generics ::lock_ordering::lock::#trait_name for #name #ty_generics #where_clause {
+                    type #assoc = #ty;
+                }
+            }
+        })
+    };
+    let impls = [
+        level_impl("MutexLockLevel", "Mutex", mutex),
+        level_impl("RwLockLevel", "RwLock", rwlock),
+        level_impl("AsyncMutexLockLevel", "Mutex", async_mutex),
+        level_impl("AsyncRwLockLevel", "RwLock", async_rwlock),
+    ];
+
+    Ok(quote! {
+        impl #impl_generics ::lock_ordering::LockLevel for #name #ty_generics #where_clause {
+            type Method = #method;
+        }
+        #(#impls)*
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn compile_pass() {
+        let t = trybuild::TestCases::new();
+        t.pass("tests/pass/*.rs");
+    }
+
+    #[test]
+    fn compile_fail() {
+        let t = trybuild::TestCases::new();
+        t.compile_fail("tests/fail/*.rs");
+    }
+}
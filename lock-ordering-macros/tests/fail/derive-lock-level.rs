@@ -0,0 +1,19 @@
+use lock_ordering::LockLevel;
+
+#[derive(LockLevel)]
+#[lock(mutex = "Mutex<usize>", rwlock = "RwLock<usize>")]
+enum Both {}
+
+#[derive(LockLevel)]
+enum Neither {}
+
+#[derive(LockLevel)]
+#[lock(mutex = "Mutex<usize>")]
+#[lock(mutex = "Mutex<u8>")]
+enum Twice {}
+
+#[derive(LockLevel)]
+#[lock(semaphore = "Mutex<usize>")]
+enum Unknown {}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use lock_ordering::LockSection;
+
+// The constructor is only for the code generated by `#[lock_section]`.
+fn forge() -> LockSection<'static> {
+    LockSection::new_unchecked(&())
+}
+
+fn main() {
+    let _ = forge();
+}
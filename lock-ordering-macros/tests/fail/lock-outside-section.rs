@@ -0,0 +1,29 @@
+use std::sync::Mutex;
+
+use lock_ordering::{
+    lock::MutexLockLevel, relation::LockAfter, LockLevel, LockedAt, MutualExclusion, Unlocked,
+};
+
+struct FirstLock;
+
+impl LockAfter<Unlocked> for FirstLock {}
+
+impl LockLevel for FirstLock {
+    type Method = MutualExclusion;
+}
+impl MutexLockLevel for FirstLock {
+    type Mutex = Mutex<usize>;
+}
+
+// Not annotated with `#[lock_section]`, so there's no token to pass.
+fn update(first: &Mutex<usize>) {
+    let mut locked = LockedAt::new();
+    let (_locked, mut guard) = locked
+        .with_lock_in_section::<FirstLock>(&lock_section, first)
+        .unwrap();
+    *guard += 1;
+}
+
+fn main() {
+    update(&Mutex::new(1));
+}
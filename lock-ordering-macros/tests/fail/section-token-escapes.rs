@@ -0,0 +1,24 @@
+use std::cell::Cell;
+
+use lock_ordering::{lock_section, LockSection};
+
+// The token is tied to the annotated call, so it can't be handed to code
+// outside of it.
+#[lock_section]
+fn leak_by_return() -> LockSection<'static> {
+    lock_section
+}
+
+thread_local! {
+    static STASHED: Cell<Option<LockSection<'static>>> = const { Cell::new(None) };
+}
+
+#[lock_section]
+fn leak_by_stash() {
+    STASHED.set(Some(lock_section));
+}
+
+fn main() {
+    let _ = leak_by_return();
+    leak_by_stash();
+}
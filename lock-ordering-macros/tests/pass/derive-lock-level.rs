@@ -0,0 +1,28 @@
+use std::sync::{Mutex, RwLock};
+
+use lock_ordering::{relation::LockAfter, LockLevel, LockedAt, Unlocked};
+
+#[derive(LockLevel)]
+#[lock(mutex = "Mutex<usize>", async_mutex = "tokio::sync::Mutex<usize>")]
+enum Counter {}
+
+#[derive(LockLevel)]
+#[lock(rwlock = "RwLock<String>")]
+enum Name {}
+
+impl LockAfter<Unlocked> for Counter {}
+impl LockAfter<Counter> for Name {}
+
+fn main() {
+    let counter = Mutex::new(1);
+    let name = RwLock::new("a".to_owned());
+
+    let mut locked = LockedAt::new();
+    let (mut locked, mut guard) = locked.with_lock::<Counter>(&counter).unwrap();
+    *guard += 1;
+    locked.write_lock::<Name>(&name).unwrap().push('b');
+    drop(guard);
+
+    assert_eq!(*counter.lock().unwrap(), 2);
+    assert_eq!(*name.read().unwrap(), "ab");
+}
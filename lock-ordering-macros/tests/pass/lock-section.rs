@@ -0,0 +1,57 @@
+use std::sync::Mutex;
+
+use lock_ordering::{
+    lock::MutexLockLevel, lock_section, relation::LockAfter, LockLevel, LockSection, LockedAt,
+    MutualExclusion, Unlocked,
+};
+
+struct FirstLock;
+struct SecondLock;
+
+impl LockAfter<Unlocked> for FirstLock {}
+impl LockAfter<FirstLock> for SecondLock {}
+
+impl LockLevel for FirstLock {
+    type Method = MutualExclusion;
+}
+impl MutexLockLevel for FirstLock {
+    type Mutex = Mutex<usize>;
+}
+
+impl LockLevel for SecondLock {
+    type Method = MutualExclusion;
+}
+impl MutexLockLevel for SecondLock {
+    type Mutex = Mutex<char>;
+}
+
+// Helpers can receive the token from an annotated caller.
+fn increment(locked: &mut LockedAt<'_, Unlocked>, section: &LockSection<'_>, first: &Mutex<usize>) {
+    let (_locked, mut guard) = locked
+        .with_lock_in_section::<FirstLock>(section, first)
+        .unwrap();
+    *guard += 1;
+}
+
+#[lock_section]
+fn update(first: &Mutex<usize>, second: &Mutex<char>) -> usize {
+    let mut locked = LockedAt::new();
+    increment(&mut locked, &lock_section, first);
+
+    let (mut locked, guard) = locked
+        .with_lock_in_section::<FirstLock>(&lock_section, first)
+        .unwrap();
+    let (_locked, mut second_guard) = locked
+        .with_lock_in_section::<SecondLock>(&lock_section, second)
+        .unwrap();
+    *second_guard = 'b';
+    *guard
+}
+
+fn main() {
+    let first = Mutex::new(1);
+    let second = Mutex::new('a');
+
+    assert_eq!(update(&first, &second), 2);
+    assert_eq!(*second.lock().unwrap(), 'b');
+}
@@ -0,0 +1,107 @@
+//! Lock types selected by the enabled backend features.
+//!
+//! Crates that support several lock implementations can refer to the types
+//! here (usually via [`crate_lock_aliases!`](crate::crate_lock_aliases))
+//! instead of writing `#[cfg]` attributes around every lock type. The
+//! backend is chosen by this crate's features, in order of preference:
+//!
+//! 1. `parking_lot`: [`parking_lot::Mutex`] and [`parking_lot::RwLock`]
+//! 2. `std`: [`std::sync::Mutex`] and [`std::sync::RwLock`]
+
+#[cfg(feature = "parking_lot")]
+pub use parking_lot::{Mutex, RwLock};
+#[cfg(all(feature = "std", not(feature = "parking_lot")))]
+pub use std::sync::{Mutex, RwLock};
+
+/// Defines `Mutex` and `RwLock` type aliases for the selected backend.
+///
+/// Expands to type aliases pointing at the types in [`backend`](crate::backend),
+/// with the given visibility, along with a compile-time check that they
+/// implement [`MutexLock`](crate::lock::MutexLock) and
+/// [`RwLock`](crate::lock::RwLock). Lock level declarations can then use the
+/// aliases and switch backends by changing only which features are enabled.
+///
+/// ```
+/// use lock_ordering::{crate_lock_aliases, lock::MutexLockLevel, LockLevel, MutualExclusion};
+///
+/// crate_lock_aliases!(pub(crate));
+///
+/// struct Counter;
+/// impl LockLevel for Counter {
+///     type Method = MutualExclusion;
+/// }
+/// impl MutexLockLevel for Counter {
+///     type Mutex = Mutex<usize>;
+/// }
+/// ```
+#[macro_export]
+macro_rules! crate_lock_aliases {
+    ($($vis:tt)*) => {
+        $($vis)* type Mutex<T> = $crate::backend::Mutex<T>;
+        $($vis)* type RwLock<T> = $crate::backend::RwLock<T>;
+
+        const _: () = {
+            fn assert_lock_traits<T>()
+            where
+                Mutex<T>: $crate::lock::MutexLock,
+                RwLock<T>: $crate::lock::RwLock,
+            {
+            }
+            let _ = assert_lock_traits::<()>;
+        };
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        lock::{MutexLockLevel, RwLockLevel},
+        relation::LockAfter,
+        LockLevel, LockedAt, MutualExclusion, ReadWrite, Unlocked,
+    };
+
+    crate_lock_aliases!();
+
+    struct Config;
+    struct Counter;
+
+    impl LockAfter<Unlocked> for Config {}
+    impl LockAfter<Config> for Counter {}
+
+    impl LockLevel for Config {
+        type Method = ReadWrite;
+    }
+    impl RwLockLevel for Config {
+        type RwLock = RwLock<&'static str>;
+    }
+
+    impl LockLevel for Counter {
+        type Method = MutualExclusion;
+    }
+    impl MutexLockLevel for Counter {
+        type Mutex = Mutex<usize>;
+    }
+
+    #[test]
+    fn hierarchy_uses_selected_backend() {
+        let config = RwLock::new("name");
+        let counter = Mutex::new(0);
+
+        let mut locked = LockedAt::new();
+        let (mut locked, config) = locked.with_read_lock::<Config>(&config).unwrap();
+        let mut counter = locked.lock::<Counter>(&counter).unwrap();
+        *counter += config.len();
+        assert_eq!(*counter, 4);
+
+        let expected = if cfg!(feature = "parking_lot") {
+            "parking_lot"
+        } else {
+            "std"
+        };
+        assert!(
+            core::any::type_name::<Mutex<usize>>().contains(expected),
+            "{}",
+            core::any::type_name::<Mutex<usize>>()
+        );
+    }
+}
@@ -0,0 +1,290 @@
+//! Runtime-checked lock ordering through shared references.
+
+use core::{
+    cell::Cell,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
+
+use crate::{
+    lock::{MutexLock, MutexLockLevel, RwLock, RwLockLevel},
+    lockedat::{acquire_level, lock_level},
+    relation::LockAfter,
+    LevelGuard, Unlocked,
+};
+
+/// Lock-level token like [`LockedAt`](crate::LockedAt) that can be used
+/// through `&self`.
+///
+/// `LockedAt` relies on exclusive `&mut` borrows to guarantee that while a
+/// lock for some level is held, the token for a previous level can't be used to
+/// acquire another lock. That doesn't work when the token has to be stored
+/// somewhere only reachable by shared reference, like a struct whose methods
+/// take `&self`.
+///
+/// `CellLockedAt` provides the same acquisition methods but takes `&self`,
+/// and instead enforces exclusivity at runtime: while a guard produced by one
+/// of its methods is alive, any other acquisition through the same
+/// `CellLockedAt` panics. The ordering between levels is still checked at
+/// compile time. Prefer `LockedAt` where possible since it turns the runtime
+/// panic into a compile error.
+///
+/// This type is deliberately not [`Sync`]; like `LockedAt`, each thread
+/// should have its own root.
+pub struct CellLockedAt<'a, L> {
+    in_use: Cell<bool>,
+    _level: PhantomData<&'a mut L>,
+}
+
+/// Guard produced by acquiring a lock through a [`CellLockedAt`].
+///
+/// Dereferences to the wrapped guard's target. The token for the acquired
+/// level is available via [`CellGuard::locked`] for acquiring further locks.
+/// Until this is dropped, the `CellLockedAt` that produced it can't be used
+/// for another acquisition.
+pub struct CellGuard<'a, L, G> {
+    guard: G,
+    locked: CellLockedAt<'a, L>,
+    parent_in_use: &'a Cell<bool>,
+}
+
+impl CellLockedAt<'static, Unlocked> {
+    /// Creates a new `CellLockedAt` without any locks held.
+    ///
+    /// # Panics
+    ///
+    /// Like [`LockedAt::new`](crate::LockedAt::new), with `std`, panics if the
+    /// current thread's [`ThreadToken`] is checked out.
+    ///
+    /// [`ThreadToken`]: crate::ThreadToken
+    pub fn new() -> Self {
+        #[cfg(feature = "std")]
+        assert!(
+            !crate::ThreadToken::is_checked_out(),
+            "CellLockedAt::new called while this thread's ThreadToken is checked out"
+        );
+        Self::at_level()
+    }
+}
+
+impl Default for CellLockedAt<'static, Unlocked> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<L> core::fmt::Debug for CellLockedAt<'_, L> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("CellLockedAt")
+            .field("level", &format_args!("{}", core::any::type_name::<L>()))
+            .field("in_use", &self.in_use.get())
+            .finish()
+    }
+}
+
+impl<'a, L> CellLockedAt<'a, L> {
+    fn at_level() -> Self {
+        Self {
+            in_use: Cell::new(false),
+            _level: PhantomData,
+        }
+    }
+
+    /// Marks `self` as in use, panicking if it already was.
+    fn enter(&self) -> &Cell<bool> {
+        if self.in_use.replace(true) {
+            panic!(
+                "CellLockedAt at level {} used while a previous acquisition is still held",
+                core::any::type_name::<L>()
+            );
+        }
+        &self.in_use
+    }
+
+    /// Attempts to acquire a lock on `NewLock` state.
+    ///
+    /// Like [`LockedAt::with_lock`](crate::LockedAt::with_lock), but panics if
+    /// a guard previously produced by `self` is still alive.
+    #[allow(clippy::type_complexity)]
+    pub fn lock<'b, NewLock: LockAfter<L> + MutexLockLevel>(
+        &'b self,
+        t: &'b NewLock::Mutex,
+    ) -> Result<
+        CellGuard<'b, NewLock, LevelGuard<<NewLock::Mutex as MutexLock>::Guard<'b>>>,
+        <NewLock::Mutex as MutexLock>::Error<'b>,
+    > {
+        let in_use = self.enter();
+        match lock_level::<NewLock, _>(t) {
+            Ok(guard) => Ok(CellGuard::new(guard, in_use)),
+            Err(e) => {
+                in_use.set(false);
+                Err(e)
+            }
+        }
+    }
+
+    /// Attempts to acquire a shared lock on `NewLock` state.
+    ///
+    /// Like [`LockedAt::with_read_lock`](crate::LockedAt::with_read_lock), but
+    /// panics if a guard previously produced by `self` is still alive.
+    #[allow(clippy::type_complexity)]
+    pub fn read_lock<'b, NewLock: LockAfter<L> + RwLockLevel>(
+        &'b self,
+        t: &'b NewLock::RwLock,
+    ) -> Result<
+        CellGuard<'b, NewLock, LevelGuard<<NewLock::RwLock as RwLock>::ReadGuard<'b>>>,
+        <NewLock::RwLock as RwLock>::ReadError<'b>,
+    > {
+        let in_use = self.enter();
+        match acquire_level::<NewLock, _, _>(|| t.read()) {
+            Ok(guard) => Ok(CellGuard::new(guard, in_use)),
+            Err(e) => {
+                in_use.set(false);
+                Err(e)
+            }
+        }
+    }
+
+    /// Attempts to acquire an exclusive lock on `NewLock` state.
+    ///
+    /// Like [`LockedAt::with_write_lock`](crate::LockedAt::with_write_lock),
+    /// but panics if a guard previously produced by `self` is still alive.
+    #[allow(clippy::type_complexity)]
+    pub fn write_lock<'b, NewLock: LockAfter<L> + RwLockLevel>(
+        &'b self,
+        t: &'b NewLock::RwLock,
+    ) -> Result<
+        CellGuard<'b, NewLock, LevelGuard<<NewLock::RwLock as RwLock>::WriteGuard<'b>>>,
+        <NewLock::RwLock as RwLock>::WriteError<'b>,
+    > {
+        let in_use = self.enter();
+        match acquire_level::<NewLock, _, _>(|| t.write()) {
+            Ok(guard) => Ok(CellGuard::new(guard, in_use)),
+            Err(e) => {
+                in_use.set(false);
+                Err(e)
+            }
+        }
+    }
+}
+
+impl<'a, L, G> CellGuard<'a, L, G> {
+    fn new(guard: G, parent_in_use: &'a Cell<bool>) -> Self {
+        Self {
+            guard,
+            locked: CellLockedAt::at_level(),
+            parent_in_use,
+        }
+    }
+
+    /// Returns the token for the acquired level.
+    ///
+    /// This can be used to acquire locks for levels after `L` while the
+    /// guard is held.
+    pub fn locked(&self) -> &CellLockedAt<'a, L> {
+        &self.locked
+    }
+}
+
+impl<L, G: Deref> Deref for CellGuard<'_, L, G> {
+    type Target = G::Target;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<L, G: DerefMut> DerefMut for CellGuard<'_, L, G> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+impl<L, G> Drop for CellGuard<'_, L, G> {
+    fn drop(&mut self) {
+        self.parent_in_use.set(false);
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::sync::{Mutex, RwLock};
+
+    use crate::{
+        lock::{MutexLockLevel, RwLockLevel},
+        LockLevel, MutualExclusion, ReadWrite,
+    };
+
+    use super::*;
+
+    struct LockA;
+    struct LockB;
+
+    impl LockAfter<Unlocked> for LockA {}
+    impl LockAfter<LockA> for LockB {}
+
+    impl LockLevel for LockA {
+        type Method = MutualExclusion;
+    }
+    impl MutexLockLevel for LockA {
+        type Mutex = Mutex<u32>;
+    }
+
+    impl LockLevel for LockB {
+        type Method = ReadWrite;
+    }
+    impl RwLockLevel for LockB {
+        type RwLock = RwLock<String>;
+    }
+
+    /// Holds the root token so that methods can use it via `&self`.
+    struct Service {
+        locked: CellLockedAt<'static, Unlocked>,
+        count: Mutex<u32>,
+        name: RwLock<String>,
+    }
+
+    impl Service {
+        fn rename(&self, name: &str) -> u32 {
+            let mut count = self.locked.lock::<LockA>(&self.count).unwrap();
+            let mut current = count.locked().write_lock::<LockB>(&self.name).unwrap();
+            *current = name.to_owned();
+            drop(current);
+
+            *count += 1;
+            *count
+        }
+    }
+
+    #[test]
+    fn shared_acquisition() {
+        let service = Service {
+            locked: CellLockedAt::new(),
+            count: Mutex::new(0),
+            name: RwLock::new(String::new()),
+        };
+
+        assert_eq!(service.rename("first"), 1);
+        assert_eq!(service.rename("second"), 2);
+        assert_eq!(*service.name.read().unwrap(), "second");
+    }
+
+    #[test]
+    #[should_panic(expected = "still held")]
+    fn overlapping_acquisition_panics() {
+        let locked = CellLockedAt::new();
+        let a = Mutex::new(0);
+
+        let _first = locked.lock::<LockA>(&a).unwrap();
+        let _second = locked.lock::<LockA>(&a);
+    }
+
+    #[test]
+    fn new_checks_thread_token() {
+        let token = crate::ThreadToken::take().unwrap();
+        assert!(std::panic::catch_unwind(CellLockedAt::new).is_err());
+
+        drop(token);
+        let _locked = CellLockedAt::new();
+    }
+}
@@ -0,0 +1,246 @@
+//! Lightweight per-level acquisition counters.
+//!
+//! With the `counters` feature enabled, every successful acquisition through
+//! [`LockedAt`](crate::LockedAt), synchronous or not, increments a pair of
+//! atomic counters for the acquired level. Failed acquisitions aren't
+//! counted. This gives cheap contention numbers without a tracing
+//! subscriber; see [`snapshot`].
+//!
+//! Contention is detected by first trying to acquire a
+//! [`MutexLock`](crate::lock::MutexLock) without blocking, and falling back
+//! to a blocking acquisition if that fails because the lock is held elsewhere,
+//! as reported by
+//! [`MutexLock::try_error_would_block`](crate::lock::MutexLock::try_error_would_block).
+//! Async acquisitions are contended if they didn't complete on their first
+//! poll. Other synchronous acquisitions, like read/write ones, are counted
+//! but never reported as contended.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+/// Counts of acquisitions for a single lock level.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LevelCounters {
+    /// Total number of acquisitions.
+    pub acquisitions: u64,
+    /// Number of acquisitions where the lock was already held elsewhere.
+    pub contended_acquisitions: u64,
+}
+
+#[derive(Default)]
+struct AtomicLevelCounters {
+    acquisitions: AtomicU64,
+    contended_acquisitions: AtomicU64,
+}
+
+/// Counters for each level, keyed by the name of the level's type.
+///
+/// Entries are leaked so that recording only needs a read lock and atomic
+/// increments once a level has been seen.
+static COUNTERS: RwLock<BTreeMap<&'static str, &'static AtomicLevelCounters>> =
+    RwLock::new(BTreeMap::new());
+
+fn counters_for(level: &'static str) -> &'static AtomicLevelCounters {
+    if let Some(counters) = COUNTERS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(level)
+    {
+        return counters;
+    }
+    COUNTERS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .entry(level)
+        .or_insert_with(|| Box::leak(Box::default()))
+}
+
+/// Records an acquisition for the level named `level`.
+pub(crate) fn record(level: &'static str, contended: bool) {
+    let counters = counters_for(level);
+    let _: u64 = counters.acquisitions.fetch_add(1, Ordering::Relaxed);
+    if contended {
+        let _: u64 = counters
+            .contended_acquisitions
+            .fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Returns the current counts for every level acquired so far.
+///
+/// Levels are keyed by the name of their marker type, as produced by
+/// [`core::any::type_name`].
+pub fn snapshot() -> BTreeMap<&'static str, LevelCounters> {
+    COUNTERS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .map(|(level, counters)| {
+            (
+                *level,
+                LevelCounters {
+                    acquisitions: counters.acquisitions.load(Ordering::Relaxed),
+                    contended_acquisitions: counters.contended_acquisitions.load(Ordering::Relaxed),
+                },
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{mpsc, Mutex};
+    use std::time::Duration;
+
+    use crate::{
+        lock::MutexLockLevel, relation::LockAfter, LockLevel, LockedAt, MutualExclusion, Unlocked,
+    };
+
+    use super::*;
+
+    struct Contended;
+    struct Uncontended;
+    struct Poisoned;
+    struct Batched;
+    struct Paired;
+
+    impl LockAfter<Unlocked> for Contended {}
+    impl LockAfter<Unlocked> for Uncontended {}
+    impl LockAfter<Unlocked> for Poisoned {}
+    impl LockAfter<Unlocked> for Batched {}
+    impl LockAfter<Unlocked> for Paired {}
+
+    impl LockLevel for Contended {
+        type Method = MutualExclusion;
+    }
+    impl MutexLockLevel for Contended {
+        type Mutex = Mutex<u32>;
+    }
+
+    impl LockLevel for Uncontended {
+        type Method = MutualExclusion;
+    }
+    impl MutexLockLevel for Uncontended {
+        type Mutex = Mutex<u32>;
+    }
+
+    impl LockLevel for Poisoned {
+        type Method = MutualExclusion;
+    }
+    impl MutexLockLevel for Poisoned {
+        type Mutex = Mutex<u32>;
+    }
+
+    impl LockLevel for Batched {
+        type Method = MutualExclusion;
+    }
+    impl MutexLockLevel for Batched {
+        type Mutex = Mutex<u32>;
+    }
+
+    impl LockLevel for Paired {
+        type Method = MutualExclusion;
+    }
+    impl MutexLockLevel for Paired {
+        type Mutex = Mutex<u32>;
+    }
+
+    #[test]
+    fn contended_level_counted() {
+        let contended = Mutex::new(0);
+        let uncontended = Mutex::new(0);
+
+        for _ in 0..3 {
+            *LockedAt::new().lock::<Uncontended>(&uncontended).unwrap() += 1;
+        }
+
+        let (started, wait_started) = mpsc::channel();
+        std::thread::scope(|s| {
+            let mut locked = LockedAt::new();
+            let held = locked.lock::<Contended>(&contended).unwrap();
+            let contended = &contended;
+
+            s.spawn(move || {
+                let mut locked = LockedAt::new();
+                started.send(()).unwrap();
+                *locked.lock::<Contended>(contended).unwrap() += 1;
+            });
+
+            wait_started.recv().unwrap();
+            std::thread::sleep(Duration::from_millis(50));
+            drop(held);
+        });
+
+        let snapshot = snapshot();
+        let contended = snapshot[core::any::type_name::<Contended>()];
+        let uncontended = snapshot[core::any::type_name::<Uncontended>()];
+        assert_eq!(
+            contended,
+            LevelCounters {
+                acquisitions: 2,
+                contended_acquisitions: 1
+            }
+        );
+        assert_eq!(
+            uncontended,
+            LevelCounters {
+                acquisitions: 3,
+                contended_acquisitions: 0
+            }
+        );
+        assert!(contended.contended_acquisitions > uncontended.contended_acquisitions);
+    }
+
+    #[test]
+    fn poisoned_level_not_counted() {
+        let poisoned = Mutex::new(0);
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let _guard = poisoned.lock().unwrap();
+                panic!("poison the lock");
+            })
+            .join()
+            .unwrap_err();
+        });
+
+        assert!(LockedAt::new().lock::<Poisoned>(&poisoned).is_err());
+
+        assert_eq!(snapshot().get(core::any::type_name::<Poisoned>()), None);
+    }
+
+    #[test]
+    fn lock_all_counted() {
+        let locks = [Mutex::new(0), Mutex::new(1), Mutex::new(2)];
+
+        let mut locked = LockedAt::new();
+        let (_locked, guards) = locked.lock_all::<Batched>(&locks).unwrap();
+        assert_eq!(guards.len(), 3);
+
+        assert_eq!(
+            snapshot()[core::any::type_name::<Batched>()],
+            LevelCounters {
+                acquisitions: 3,
+                contended_acquisitions: 0
+            }
+        );
+    }
+
+    #[test]
+    fn pair_ordered_counted() {
+        let (a, b) = (Mutex::new(1), Mutex::new(2));
+
+        let sum = LockedAt::new()
+            .with_pair_ordered::<Paired, _>(&a, &b, |a, b| *a + *b)
+            .unwrap();
+        assert_eq!(sum, 3);
+
+        assert_eq!(
+            snapshot()[core::any::type_name::<Paired>()],
+            LevelCounters {
+                acquisitions: 2,
+                contended_acquisitions: 0
+            }
+        );
+    }
+}
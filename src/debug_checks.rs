@@ -0,0 +1,761 @@
+//! Runtime checks for misuse that the type system can't catch.
+//!
+//! The ordering enforced by [`LockedAt`](crate::LockedAt) is between lock
+//! *levels*; nothing prevents the same lock object from being reachable at
+//! two different levels. The checks here catch some of the resulting problems
+//! at runtime, at the cost of some bookkeeping on each acquisition.
+//!
+//! Levels are tracked through the guards returned by synchronous
+//! acquisitions, which are [`HeldGuard`]s when the checks are enabled: a level
+//! is held by the thread that acquired it for as long as the guard is live.
+//! Async acquisitions aren't tracked, since their guards can be held across
+//! `.await`s while the task moves between threads.
+//!
+//! Acquiring a level that the current thread already holds panics instead
+//! of risking a deadlock; outside of same-level methods like
+//! [`LockedAt::with_lock_child`](crate::LockedAt::with_lock_child), that
+//! can only happen through a second root `LockedAt`. A level held by another
+//! thread doesn't count, even if the `LockedAt` it was acquired through has
+//! been moved to the current thread.
+//!
+//! The checks are only performed when the `debug_checks` feature is enabled.
+//! The diagnostic functions in this module are always available with `std`,
+//! but do nothing without the feature.
+
+#[cfg(feature = "debug_checks")]
+use core::{
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, Ordering},
+};
+#[cfg(feature = "debug_checks")]
+use std::{cell::RefCell, sync::Arc};
+
+#[cfg(feature = "debug_checks")]
+std::thread_local! {
+    /// Levels held by live guards acquired on this thread, in acquisition
+    /// order.
+    static HELD: RefCell<Vec<HeldEntry>> = const { RefCell::new(Vec::new()) };
+
+    /// Addresses of the locks currently held for reading by this thread.
+    static HELD_READS: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+
+    /// Levels held by this thread when it last panicked.
+    static HELD_AT_PANIC: RefCell<Vec<&'static str>> = const { RefCell::new(Vec::new()) };
+
+    /// Levels released so far in each active teardown scope, outermost scope
+    /// first.
+    static TEARDOWNS: RefCell<Vec<Vec<&'static str>>> = const { RefCell::new(Vec::new()) };
+
+    /// Levels released during the last teardown scope that exited.
+    static LAST_TEARDOWN: RefCell<Vec<&'static str>> = const { RefCell::new(Vec::new()) };
+
+    /// Orderings allowed by the enforced [`ExpectedLockOrder`], if any.
+    static EXPECTED_ORDER: RefCell<Option<Vec<(&'static str, &'static str)>>> =
+        const { RefCell::new(None) };
+}
+
+/// A level held by a guard acquired on this thread.
+#[cfg(feature = "debug_checks")]
+struct HeldEntry {
+    name: &'static str,
+    /// Set when the guard is dropped, which might happen on another thread.
+    released: Arc<AtomicBool>,
+}
+
+/// Calls `f` with the levels held by this thread.
+///
+/// Entries for guards that were dropped on other threads are removed first.
+#[cfg(feature = "debug_checks")]
+fn with_held<R>(f: impl FnOnce(&mut Vec<HeldEntry>) -> R) -> R {
+    HELD.with_borrow_mut(|held| {
+        remove_released(held);
+        f(held)
+    })
+}
+
+#[cfg(feature = "debug_checks")]
+fn remove_released(held: &mut Vec<HeldEntry>) {
+    held.retain(|entry| !entry.released.load(Ordering::Acquire));
+}
+
+/// Checks that the current thread can acquire a lock for level `name`.
+///
+/// This must be called *before* blocking on the acquisition, so that a
+/// thread about to deadlock on a lock it already holds panics instead.
+/// Panics if an [`ExpectedLockOrder`] is enforced on this thread and doesn't
+/// allow acquiring `name` while the innermost held level is held, or if
+/// `name` is already held by this thread.
+#[cfg(feature = "debug_checks")]
+pub(crate) fn check_acquire(name: &'static str) {
+    let (before, already_held) = with_held(|held| {
+        (
+            held.last().map(|entry| entry.name),
+            held.iter().any(|entry| entry.name == name),
+        )
+    });
+    let before = before.unwrap_or(core::any::type_name::<crate::Unlocked>());
+
+    let allowed = EXPECTED_ORDER.with_borrow(|expected| {
+        expected
+            .as_ref()
+            .is_none_or(|edges| edges.contains(&(before, name)))
+    });
+    if !allowed {
+        panic!(
+            "lock level {name} acquired while holding {before}, \
+            which the expected lock order doesn't allow"
+        );
+    }
+    if already_held {
+        panic!("lock level {name} acquired while it is already held by this thread");
+    }
+}
+
+/// Guard for a lock acquired synchronously with the `debug_checks` feature.
+///
+/// This is what [`LevelGuard`](crate::LevelGuard) is when the feature is
+/// enabled. It wraps the backend's guard `G` and dereferences to the same
+/// target. The lock's level is reported as held by the thread that acquired
+/// it until this is dropped, even if that happens on another thread.
+///
+/// Like the backends' guards, the methods here are associated functions, so
+/// they don't shadow methods of the locked value.
+#[cfg(feature = "debug_checks")]
+#[must_use = "the lock is released when the guard is dropped"]
+pub struct HeldGuard<G> {
+    guard: G,
+    _held: HeldLevel,
+}
+
+#[cfg(feature = "debug_checks")]
+impl<G> HeldGuard<G> {
+    pub(crate) fn new(guard: G, name: &'static str) -> Self {
+        Self {
+            guard,
+            _held: HeldLevel::new(name),
+        }
+    }
+
+    /// Returns the backend's guard.
+    ///
+    /// The lock stays held until the returned guard is dropped, but its level
+    /// is no longer reported as held.
+    pub fn into_inner(this: Self) -> G {
+        this.guard
+    }
+
+    /// Returns the backend's guard by reference.
+    ///
+    /// This is for APIs that take the backend's guard itself, like
+    /// [`MutexLockData::data`](crate::lock::MutexLockData::data).
+    pub fn inner_mut(this: &mut Self) -> &mut G {
+        &mut this.guard
+    }
+
+    /// Replaces the backend's guard with the result of `f`, which must be a
+    /// guard for the same lock. The level stays held throughout.
+    pub(crate) fn map<U>(this: Self, f: impl FnOnce(G) -> U) -> HeldGuard<U> {
+        HeldGuard {
+            guard: f(this.guard),
+            _held: this._held,
+        }
+    }
+
+    /// Like [`HeldGuard::map`], for fallible operations. If `f` fails, the
+    /// level is no longer reported as held.
+    pub(crate) fn try_map<U, E>(
+        this: Self,
+        f: impl FnOnce(G) -> Result<U, E>,
+    ) -> Result<HeldGuard<U>, E> {
+        Ok(HeldGuard {
+            guard: f(this.guard)?,
+            _held: this._held,
+        })
+    }
+}
+
+#[cfg(feature = "debug_checks")]
+impl<G: Deref> Deref for HeldGuard<G> {
+    type Target = G::Target;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+#[cfg(feature = "debug_checks")]
+impl<G: DerefMut> DerefMut for HeldGuard<G> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+#[cfg(feature = "debug_checks")]
+impl<G: core::fmt::Debug> core::fmt::Debug for HeldGuard<G> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.guard.fmt(f)
+    }
+}
+
+/// Records that a guard for level `name` is live.
+///
+/// The level is pushed onto the acquiring thread's held levels. When this is
+/// dropped on that thread, the entry is removed right away; when it is
+/// dropped on another thread, it is only marked as released, and the
+/// acquiring thread removes it the next time it looks at its held levels.
+#[cfg(feature = "debug_checks")]
+struct HeldLevel {
+    name: &'static str,
+    released: Arc<AtomicBool>,
+}
+
+#[cfg(feature = "debug_checks")]
+impl HeldLevel {
+    fn new(name: &'static str) -> Self {
+        let released = Arc::new(AtomicBool::new(false));
+        HELD.with_borrow_mut(|held| {
+            held.push(HeldEntry {
+                name,
+                released: Arc::clone(&released),
+            })
+        });
+        Self { name, released }
+    }
+}
+
+#[cfg(feature = "debug_checks")]
+impl Drop for HeldLevel {
+    fn drop(&mut self) {
+        self.released.store(true, Ordering::Release);
+        // The thread-locals are gone if this runs during thread exit.
+        let _ = HELD.try_with(|held| remove_released(&mut held.borrow_mut()));
+        let _ = TEARDOWNS.try_with(|scopes| {
+            for released in scopes.borrow_mut().iter_mut() {
+                released.push(self.name);
+            }
+        });
+    }
+}
+
+/// Records the levels released while it is live.
+///
+/// Created by [`LockedAt::teardown_scope`](crate::LockedAt::teardown_scope).
+/// When dropped, including during unwinding, the recorded release order and
+/// any levels that are still held are printed to stderr.
+#[cfg(feature = "debug_checks")]
+pub(crate) struct TeardownScope(());
+
+#[cfg(feature = "debug_checks")]
+impl TeardownScope {
+    pub(crate) fn enter() -> Self {
+        TEARDOWNS.with_borrow_mut(|scopes| scopes.push(Vec::new()));
+        Self(())
+    }
+}
+
+#[cfg(feature = "debug_checks")]
+impl Drop for TeardownScope {
+    fn drop(&mut self) {
+        let released = TEARDOWNS
+            .with_borrow_mut(Vec::pop)
+            .expect("teardown scopes exit in order");
+        std::eprintln!(
+            "lock levels released during teardown: {}",
+            released.join(" -> ")
+        );
+        let held = held_levels();
+        if !held.is_empty() {
+            std::eprintln!(
+                "lock levels still held after teardown: {}",
+                held.join(" -> ")
+            );
+        }
+        LAST_TEARDOWN.with_borrow_mut(|last| *last = released);
+    }
+}
+
+/// Returns the names of the levels currently held by this thread.
+///
+/// Levels are listed outermost first. A level is considered held while the
+/// guard for a lock acquired at it by this thread is live. Acquisitions of async locks aren't tracked, since their
+/// guards aren't tied to a thread.
+///
+/// Always empty without the `debug_checks` feature.
+pub fn held_levels() -> Vec<&'static str> {
+    #[cfg(feature = "debug_checks")]
+    return with_held(|held| held.iter().map(|entry| entry.name).collect());
+    #[cfg(not(feature = "debug_checks"))]
+    return Vec::new();
+}
+
+/// Returns the levels that were held the last time this thread panicked.
+///
+/// This is only recorded if the hook from [`install_panic_hook`] is
+/// installed. Always empty without the `debug_checks` feature.
+pub fn held_at_last_panic() -> Vec<&'static str> {
+    #[cfg(feature = "debug_checks")]
+    return HELD_AT_PANIC.with_borrow(Clone::clone);
+    #[cfg(not(feature = "debug_checks"))]
+    return Vec::new();
+}
+
+/// Returns the levels released during the last teardown scope on this thread.
+///
+/// Levels are listed in the order they were released. See
+/// [`LockedAt::teardown_scope`](crate::LockedAt::teardown_scope). Always
+/// empty without the `debug_checks` feature.
+pub fn released_in_last_teardown() -> Vec<&'static str> {
+    #[cfg(feature = "debug_checks")]
+    return LAST_TEARDOWN.with_borrow(Clone::clone);
+    #[cfg(not(feature = "debug_checks"))]
+    return Vec::new();
+}
+
+/// Installs a panic hook that reports the lock levels held while panicking.
+///
+/// When a thread panics while holding several `std` locks, each of them is
+/// poisoned independently. The installed hook prints the levels held by the
+/// panicking thread (see [`held_levels`]) to stderr and records them for
+/// [`held_at_last_panic`], then calls the previously installed hook.
+///
+/// The hook is only installed by the first call; later calls do nothing.
+/// Without the `debug_checks` feature, this does nothing.
+pub fn install_panic_hook() {
+    #[cfg(feature = "debug_checks")]
+    {
+        static INSTALL: std::sync::Once = std::sync::Once::new();
+        INSTALL.call_once(|| {
+            let previous = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                let held = held_levels();
+                if !held.is_empty() {
+                    std::eprintln!("lock levels held while panicking: {}", held.join(" -> "));
+                }
+                HELD_AT_PANIC.with_borrow_mut(|at_panic| *at_panic = held);
+                previous(info)
+            }));
+        });
+    }
+}
+
+/// A lock order to check acquisitions against at runtime.
+///
+/// The `LockAfter` impls are checked at compile time, but can't catch an impl
+/// that shouldn't have been written, or a second root created with
+/// [`LockedAt::new_unchecked`](crate::LockedAt::new_unchecked) while another
+/// root on the same thread holds locks. While the guard from
+/// [`enforce`](Self::enforce) is live, each level acquired synchronously on
+/// the current thread is checked against the orderings allowed here:
+/// acquiring `After` while the innermost held level is `Before` panics unless
+/// [`allow::<Before, After>`](Self::allow) was called. Acquisitions with no
+/// levels held are checked against [`Unlocked`](crate::Unlocked).
+///
+/// Levels are identified by [`type_name`](core::any::type_name), which is
+/// also how they are reported by [`held_levels`]. Without the `debug_checks`
+/// feature, nothing is checked.
+///
+/// ```
+/// use lock_ordering::{debug_checks::ExpectedLockOrder, relation::LockAfter, Unlocked};
+///
+/// enum Accounts {}
+/// enum Ledger {}
+///
+/// impl LockAfter<Unlocked> for Accounts {}
+/// impl LockAfter<Accounts> for Ledger {}
+///
+/// let _check = ExpectedLockOrder::new()
+///     .allow::<Unlocked, Accounts>()
+///     .allow::<Accounts, Ledger>()
+///     .enforce();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ExpectedLockOrder {
+    edges: Vec<(&'static str, &'static str)>,
+}
+
+impl ExpectedLockOrder {
+    /// Creates an order that doesn't allow acquiring any level.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows `After` to be acquired while `Before` is the innermost held
+    /// level.
+    pub fn allow<Before, After>(mut self) -> Self {
+        self.edges.push((
+            core::any::type_name::<Before>(),
+            core::any::type_name::<After>(),
+        ));
+        self
+    }
+
+    /// Checks acquisitions on the current thread against this order until
+    /// the returned guard is dropped.
+    ///
+    /// When the guard is dropped, the previously enforced order, if any, is
+    /// restored.
+    pub fn enforce(self) -> ExpectedLockOrderGuard {
+        #[cfg(feature = "debug_checks")]
+        return ExpectedLockOrderGuard {
+            previous: EXPECTED_ORDER.replace(Some(self.edges)),
+        };
+        #[cfg(not(feature = "debug_checks"))]
+        return ExpectedLockOrderGuard {};
+    }
+}
+
+/// Keeps an [`ExpectedLockOrder`] enforced on the current thread.
+///
+/// Returned by [`ExpectedLockOrder::enforce`].
+#[must_use = "the order is only enforced until the guard is dropped"]
+#[derive(Debug)]
+pub struct ExpectedLockOrderGuard {
+    #[cfg(feature = "debug_checks")]
+    previous: Option<Vec<(&'static str, &'static str)>>,
+}
+
+#[cfg(feature = "debug_checks")]
+impl Drop for ExpectedLockOrderGuard {
+    fn drop(&mut self) {
+        EXPECTED_ORDER.set(self.previous.take());
+    }
+}
+
+/// Wrapper for a read guard that is tracked by the recursive read check.
+///
+/// Dereferences to the same target as the wrapped guard. When dropped, the
+/// lock is removed from the set of locks held for reading by the current
+/// thread.
+#[cfg(feature = "debug_checks")]
+pub struct ReadGuard<G> {
+    guard: G,
+    addr: usize,
+}
+
+#[cfg(all(feature = "debug_checks", feature = "lock-api"))]
+impl<G> ReadGuard<G> {
+    /// Read-locks `lock` with `acquire` and records it as held.
+    ///
+    /// Panics if the current thread already holds a read lock on the same
+    /// object. The check runs *before* blocking on the acquisition, otherwise
+    /// the thread could deadlock before it has a chance to run, but the lock
+    /// is only recorded once `acquire` returns.
+    pub(crate) fn acquire<'a, L: ?Sized>(lock: &'a L, acquire: impl FnOnce(&'a L) -> G) -> Self {
+        let addr = (lock as *const L).cast::<()>() as usize;
+        if HELD_READS.with_borrow(|held| held.contains(&addr)) {
+            panic!(
+                "recursive read lock of {} at {addr:#x} on the same thread; \
+                this can deadlock if a writer is waiting",
+                core::any::type_name::<L>()
+            );
+        }
+        let guard = acquire(lock);
+        HELD_READS.with_borrow_mut(|held| held.push(addr));
+        Self { guard, addr }
+    }
+
+    /// Attempts to read-lock `lock` without blocking.
+    ///
+    /// A non-blocking acquisition can't deadlock, so recursion isn't checked,
+    /// but a successful acquisition is recorded like [`ReadGuard::acquire`]
+    /// does.
+    pub(crate) fn try_acquire<'a, L: ?Sized>(
+        lock: &'a L,
+        acquire: impl FnOnce(&'a L) -> Option<G>,
+    ) -> Option<Self> {
+        let guard = acquire(lock)?;
+        let addr = (lock as *const L).cast::<()>() as usize;
+        HELD_READS.with_borrow_mut(|held| held.push(addr));
+        Some(Self { guard, addr })
+    }
+
+    /// Read-locks `lock` with `acquire`, an acquisition that is allowed to
+    /// recurse.
+    ///
+    /// The lock is still recorded as held once acquired, so a later
+    /// non-recursive read of the same object will panic.
+    pub(crate) fn acquire_recursive<'a, L: ?Sized>(
+        lock: &'a L,
+        acquire: impl FnOnce(&'a L) -> G,
+    ) -> Self {
+        let guard = acquire(lock);
+        let addr = (lock as *const L).cast::<()>() as usize;
+        HELD_READS.with_borrow_mut(|held| held.push(addr));
+        Self { guard, addr }
+    }
+}
+
+#[cfg(feature = "debug_checks")]
+impl<G: Deref> Deref for ReadGuard<G> {
+    type Target = G::Target;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+#[cfg(feature = "debug_checks")]
+impl<G> Drop for ReadGuard<G> {
+    fn drop(&mut self) {
+        HELD_READS.with_borrow_mut(|held| {
+            if let Some(i) = held.iter().rposition(|a| *a == self.addr) {
+                let _ = held.swap_remove(i);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use crate::{
+        lock::MutexLockLevel, relation::LockAfter, LockLevel, LockedAt, MutualExclusion, Unlocked,
+    };
+
+    use super::*;
+
+    struct LockA;
+    struct LockB;
+
+    impl LockAfter<Unlocked> for LockA {}
+    impl LockAfter<Unlocked> for LockB {}
+    impl LockAfter<LockA> for LockB {}
+
+    impl LockLevel for LockA {
+        type Method = MutualExclusion;
+    }
+    impl MutexLockLevel for LockA {
+        type Mutex = Mutex<u8>;
+    }
+
+    impl LockLevel for LockB {
+        type Method = MutualExclusion;
+    }
+    impl MutexLockLevel for LockB {
+        type Mutex = Mutex<u16>;
+    }
+
+    /// Panics while holding `a` and then `b`.
+    fn panic_holding_both(a: &Mutex<u8>, b: &Mutex<u16>) {
+        install_panic_hook();
+
+        let result = std::panic::catch_unwind(|| {
+            let mut locked = LockedAt::new();
+            let (mut locked, _a) = locked.with_lock::<LockA>(a).unwrap();
+            let (_locked, _b) = locked.with_lock::<LockB>(b).unwrap();
+            panic!("panicking while holding LockA and LockB");
+        });
+
+        assert!(result.is_err());
+        assert!(a.is_poisoned());
+        assert!(b.is_poisoned());
+    }
+
+    #[test]
+    #[cfg(feature = "debug_checks")]
+    fn records_levels_held_at_panic() {
+        let (a, b) = (Mutex::new(0), Mutex::new(0));
+        panic_holding_both(&a, &b);
+
+        assert_eq!(
+            held_at_last_panic(),
+            [
+                core::any::type_name::<LockA>(),
+                core::any::type_name::<LockB>()
+            ]
+        );
+        // Unwinding released both levels.
+        assert_eq!(held_levels(), [] as [&str; 0]);
+    }
+
+    #[test]
+    #[cfg(feature = "debug_checks")]
+    fn teardown_releases_in_reverse_order() {
+        let (a, b) = (Mutex::new(0), Mutex::new(0));
+        let acquired = [
+            core::any::type_name::<LockA>(),
+            core::any::type_name::<LockB>(),
+        ];
+
+        let mut locked = LockedAt::new();
+        locked.teardown_scope(|locked| {
+            let (mut locked, _a) = locked.with_lock::<LockA>(&a).unwrap();
+            let (_locked, _b) = locked.with_lock::<LockB>(&b).unwrap();
+            assert_eq!(held_levels(), acquired);
+        });
+        let mut reversed = acquired;
+        reversed.reverse();
+        assert_eq!(released_in_last_teardown(), reversed);
+
+        // The diagnostic is also produced when the body panics.
+        let result = std::panic::catch_unwind(|| {
+            let mut locked = LockedAt::new();
+            locked.teardown_scope(|locked| {
+                let (mut locked, _a) = locked.with_lock::<LockA>(&a).unwrap();
+                let (_locked, _b) = locked.with_lock::<LockB>(&b).unwrap();
+                panic!("panicking during teardown");
+            })
+        });
+        assert!(result.is_err());
+        assert_eq!(released_in_last_teardown(), reversed);
+    }
+
+    #[test]
+    #[cfg(feature = "debug_checks")]
+    fn expected_order_allows_declared_edges() {
+        let (a, b) = (Mutex::new(0), Mutex::new(0));
+        let _check = ExpectedLockOrder::new()
+            .allow::<Unlocked, LockA>()
+            .allow::<LockA, LockB>()
+            .enforce();
+
+        let mut locked = LockedAt::new();
+        let (mut locked, _a) = locked.with_lock::<LockA>(&a).unwrap();
+        let _b = locked.lock::<LockB>(&b).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "debug_checks")]
+    #[should_panic(expected = "which the expected lock order doesn't allow")]
+    fn expected_order_rejects_undeclared_edge() {
+        let (a, b) = (Mutex::new(0), Mutex::new(0));
+        let _check = ExpectedLockOrder::new()
+            .allow::<Unlocked, LockA>()
+            .enforce();
+
+        let mut locked = LockedAt::new();
+        let (mut locked, _a) = locked.with_lock::<LockA>(&a).unwrap();
+        let _b = locked.lock::<LockB>(&b);
+    }
+
+    #[test]
+    #[cfg(feature = "debug_checks")]
+    #[should_panic(expected = "which the expected lock order doesn't allow")]
+    fn expected_order_catches_second_root() {
+        let (a, other_a) = (Mutex::new(0), Mutex::new(0));
+        let _check = ExpectedLockOrder::new()
+            .allow::<Unlocked, LockA>()
+            .allow::<LockA, LockB>()
+            .enforce();
+
+        let mut locked = LockedAt::new();
+        let (_locked, _a) = locked.with_lock::<LockA>(&a).unwrap();
+        // SAFETY: this deliberately breaks the single-root requirement.
+        let mut second_root = unsafe { LockedAt::new_unchecked() };
+        let _other_a = second_root.lock::<LockA>(&other_a);
+    }
+
+    #[test]
+    #[cfg(feature = "debug_checks")]
+    fn expected_order_follows_guard_from_lock() {
+        let (a, b) = (Mutex::new(0), Mutex::new(0));
+        let _check = ExpectedLockOrder::new()
+            .allow::<Unlocked, LockA>()
+            .allow::<Unlocked, LockB>()
+            .enforce();
+
+        let mut locked = LockedAt::new();
+        let a_guard = locked.lock::<LockA>(&a).unwrap();
+        // `lock` doesn't return a `LockedAt`, but `LockA` is held until the
+        // guard is dropped.
+        assert_eq!(held_levels(), [core::any::type_name::<LockA>()]);
+
+        let result = std::panic::catch_unwind(|| {
+            // SAFETY: this deliberately breaks the single-root requirement.
+            let mut second_root = unsafe { LockedAt::new_unchecked() };
+            let _b = second_root.lock::<LockB>(&b);
+        });
+        let message = result.unwrap_err();
+        let message = message.downcast_ref::<String>().unwrap();
+        assert!(
+            message.contains("acquired while holding"),
+            "unexpected panic: {message}"
+        );
+
+        drop(a_guard);
+        assert_eq!(held_levels(), [] as [&str; 0]);
+        let _b = locked.lock::<LockB>(&b).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "debug_checks")]
+    #[should_panic(expected = "is already held by this thread")]
+    fn same_level_twice_panics() {
+        let (a, other_a) = (Mutex::new(0), Mutex::new(0));
+
+        let mut locked = LockedAt::new();
+        let (_locked, _a) = locked.with_lock::<LockA>(&a).unwrap();
+        // SAFETY: this deliberately breaks the single-root requirement.
+        let mut second_root = unsafe { LockedAt::new_unchecked() };
+        let _other_a = second_root.lock::<LockA>(&other_a);
+    }
+
+    #[test]
+    #[cfg(feature = "debug_checks")]
+    fn held_levels_belong_to_the_acquiring_thread() {
+        let (a, b, other_a) = (Mutex::new(0), Mutex::new(0), Mutex::new(0));
+
+        let mut locked = LockedAt::new();
+        let (mut locked, _a) = locked.with_lock::<LockA>(&a).unwrap();
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                // `LockA` is held by the spawning thread, not this one.
+                assert_eq!(held_levels(), [] as [&str; 0]);
+                let _b = locked.lock::<LockB>(&b).unwrap();
+                assert_eq!(held_levels(), [core::any::type_name::<LockB>()]);
+            });
+            s.spawn(|| {
+                // Acquiring a level held by another thread isn't a
+                // re-acquisition.
+                let mut locked = LockedAt::new();
+                let _other_a = locked.lock::<LockA>(&other_a).unwrap();
+            });
+        });
+        assert_eq!(held_levels(), [core::any::type_name::<LockA>()]);
+    }
+
+    #[test]
+    #[cfg(all(feature = "debug_checks", feature = "spin"))]
+    fn guard_dropped_on_another_thread_is_released() {
+        struct Spin;
+        impl LockAfter<Unlocked> for Spin {}
+        impl LockLevel for Spin {
+            type Method = MutualExclusion;
+        }
+        impl MutexLockLevel for Spin {
+            type Mutex = spin::Mutex<u8>;
+        }
+
+        let lock = spin::Mutex::new(0);
+        let mut locked = LockedAt::new();
+        let guard = locked.lock::<Spin>(&lock).unwrap();
+        assert_eq!(held_levels(), [core::any::type_name::<Spin>()]);
+
+        std::thread::scope(|s| {
+            s.spawn(move || drop(guard));
+        });
+        assert_eq!(held_levels(), [] as [&str; 0]);
+    }
+
+    #[test]
+    #[cfg(all(feature = "debug_checks", feature = "lock-api"))]
+    fn failed_read_not_recorded() {
+        let lock = 0u8;
+        let failed = std::panic::catch_unwind(|| {
+            ReadGuard::<()>::acquire(&lock, |_| panic!("acquisition failed"))
+        });
+        assert!(failed.is_err());
+
+        let _guard = ReadGuard::acquire(&lock, |_| ());
+    }
+
+    #[test]
+    #[cfg(not(feature = "debug_checks"))]
+    fn panic_hook_is_noop() {
+        let (a, b) = (Mutex::new(0), Mutex::new(0));
+        panic_holding_both(&a, &b);
+
+        assert_eq!(held_at_last_panic(), [] as [&str; 0]);
+    }
+}
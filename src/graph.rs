@@ -0,0 +1,269 @@
+//! Exporting the declared lock ordering as a graph.
+//!
+//! `LockAfter` impls can't be enumerated at runtime, so the graph is built
+//! from edges recorded by [`lock_order_graph!`](crate::lock_order_graph),
+//! which also generates the impls. Graphs can be rendered in the
+//! [Graphviz] DOT language with [`LockOrderGraph::to_dot`].
+//!
+//! With the `inventory` feature, the macro also registers its edges so that
+//! [`LockOrderGraph::collect`] can gather every declared edge in the program.
+//! With the `serde` feature, graphs can be serialized, for example to keep a
+//! snapshot of the ordering that a test compares against.
+//!
+//! [Graphviz]: https://graphviz.org/
+
+use std::{borrow::Cow, fmt::Write as _};
+
+#[cfg(feature = "inventory")]
+#[doc(hidden)]
+pub use inventory;
+
+/// A `LockAfter` relationship between two lock levels.
+///
+/// Levels are named by the full path of their type, as produced by
+/// [`core::any::type_name`], so the same level has the same name however it
+/// was written where the edge was declared. This matches the names used by
+/// the other diagnostics in this crate.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Edge {
+    /// The level that is held.
+    pub before: Cow<'static, str>,
+    /// The level that can be acquired while `before` is held.
+    pub after: Cow<'static, str>,
+}
+
+impl Edge {
+    /// Creates an edge from the level named `before` to the one named
+    /// `after`.
+    pub const fn new(before: &'static str, after: &'static str) -> Self {
+        Self {
+            before: Cow::Borrowed(before),
+            after: Cow::Borrowed(after),
+        }
+    }
+
+    /// Creates an edge from level `Before` to level `After`.
+    pub fn between<Before: ?Sized, After: ?Sized>() -> Self {
+        Self::new(
+            core::any::type_name::<Before>(),
+            core::any::type_name::<After>(),
+        )
+    }
+}
+
+/// An edge registered by [`lock_order_graph!`](crate::lock_order_graph).
+///
+/// Type names aren't available in constants, so this holds a function that
+/// produces the edge.
+#[cfg(feature = "inventory")]
+#[doc(hidden)]
+pub struct Registered(pub fn() -> Edge);
+
+#[cfg(feature = "inventory")]
+inventory::collect!(Registered);
+
+/// A set of lock levels and the orderings declared between them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LockOrderGraph {
+    edges: Cow<'static, [Edge]>,
+}
+
+impl LockOrderGraph {
+    /// Creates a graph with the given edges.
+    pub const fn new(edges: &'static [Edge]) -> Self {
+        Self {
+            edges: Cow::Borrowed(edges),
+        }
+    }
+
+    /// Creates a graph that owns the given edges.
+    pub fn from_edges(edges: Vec<Edge>) -> Self {
+        Self {
+            edges: Cow::Owned(edges),
+        }
+    }
+
+    /// Creates a graph with every edge declared with
+    /// [`lock_order_graph!`](crate::lock_order_graph) in the program.
+    ///
+    /// Edges are sorted and deduplicated, since the order in which they are
+    /// registered isn't specified.
+    #[cfg(feature = "inventory")]
+    pub fn collect() -> Self {
+        let mut edges: Vec<Edge> = inventory::iter::<Registered>
+            .into_iter()
+            .map(|Registered(edge)| edge())
+            .collect();
+        edges.sort_unstable();
+        edges.dedup();
+        Self::from_edges(edges)
+    }
+
+    /// Returns the edges in the order they were declared.
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    /// Renders the graph as a Graphviz digraph.
+    ///
+    /// Edges are sorted and deduplicated, so the output only changes when
+    /// the set of declared edges does.
+    pub fn to_dot(&self) -> String {
+        let mut edges: Vec<&Edge> = self.edges.iter().collect();
+        edges.sort_unstable();
+        edges.dedup();
+
+        let mut dot = String::from("digraph lock_order {\n");
+        for Edge { before, after } in edges {
+            writeln!(dot, "    {} -> {};", quoted(before), quoted(after)).unwrap();
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Quotes `name` as a DOT identifier.
+fn quoted(name: &str) -> String {
+    let mut quoted = String::with_capacity(name.len() + 2);
+    quoted.push('"');
+    for c in name.chars() {
+        if matches!(c, '"' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Declares `LockAfter` impls and records them in a [`LockOrderGraph`].
+///
+/// Each `Before => After` entry expands to `impl LockAfter<Before> for After
+/// {}`, and a function with the given name and visibility returns the graph
+/// of those edges. Levels are named as described for [`Edge`]. With the
+/// `inventory` feature, the edges are also registered for
+/// [`LockOrderGraph::collect`].
+///
+/// ```
+/// use lock_ordering::{
+///     graph::{Edge, LockOrderGraph},
+///     lock_order_graph, Unlocked,
+/// };
+///
+/// enum Accounts {}
+/// enum Ledger {}
+/// enum Audit {}
+///
+/// lock_order_graph! {
+///     pub fn lock_order;
+///     Unlocked => Accounts,
+///     Accounts => Ledger,
+///     Accounts => Audit,
+/// }
+///
+/// let graph: LockOrderGraph = lock_order();
+/// assert_eq!(graph.edges()[0], Edge::between::<Unlocked, Accounts>());
+/// assert_eq!(graph.edges()[0].before, "lock_ordering::Unlocked");
+/// assert_eq!(graph.to_dot().lines().count(), 5);
+/// ```
+#[macro_export]
+macro_rules! lock_order_graph {
+    ($vis:vis fn $name:ident; $($before:ty => $after:ty),* $(,)?) => {
+        $(impl $crate::relation::LockAfter<$before> for $after {})*
+
+        $vis fn $name() -> $crate::graph::LockOrderGraph {
+            $crate::graph::LockOrderGraph::from_edges(::std::vec![
+                $($crate::graph::Edge::between::<$before, $after>()),*
+            ])
+        }
+
+        $crate::__register_edges!($($before => $after),*);
+    };
+}
+
+#[cfg(feature = "inventory")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __register_edges {
+    ($($before:ty => $after:ty),*) => {
+        $($crate::graph::inventory::submit! {
+            $crate::graph::Registered($crate::graph::Edge::between::<$before, $after>)
+        })*
+    };
+}
+
+#[cfg(not(feature = "inventory"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __register_edges {
+    ($($before:ty => $after:ty),*) => {};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Edge, LockOrderGraph};
+
+    #[test]
+    fn dot_output_is_sorted_and_escaped() {
+        const EDGES: &[Edge] = &[
+            Edge::new("b", "c"),
+            Edge::new("a", "b"),
+            Edge::new("b", "c"),
+            Edge::new("a", "Quoted<\"x\">"),
+        ];
+        const GRAPH: LockOrderGraph = LockOrderGraph::new(EDGES);
+
+        assert_eq!(
+            GRAPH.to_dot(),
+            "digraph lock_order {\n    \
+                \"a\" -> \"Quoted<\\\"x\\\">\";\n    \
+                \"a\" -> \"b\";\n    \
+                \"b\" -> \"c\";\n\
+            }\n"
+        );
+    }
+
+    #[cfg(feature = "inventory")]
+    #[test]
+    fn collect_finds_declared_edges() {
+        enum First {}
+        enum Second {}
+
+        crate::lock_order_graph! {
+            fn graph;
+            crate::Unlocked => First,
+            First => Second,
+        }
+
+        let collected = LockOrderGraph::collect();
+        for edge in graph().edges() {
+            assert!(collected.edges().contains(edge));
+        }
+        // Written as `crate::Unlocked` above, but named by its full path.
+        assert!(collected.edges().contains(&Edge::new(
+            "lock_ordering::Unlocked",
+            core::any::type_name::<First>()
+        )));
+        assert!(collected.edges().is_sorted());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_round_trip() {
+        const EDGES: &[Edge] = &[Edge::new("a", "b"), Edge::new("b", "c")];
+        const GRAPH: LockOrderGraph = LockOrderGraph::new(EDGES);
+
+        let json = serde_json::to_string(&GRAPH).unwrap();
+        assert_eq!(
+            json,
+            r#"{"edges":[{"before":"a","after":"b"},{"before":"b","after":"c"}]}"#
+        );
+
+        assert_eq!(
+            serde_json::from_str::<LockOrderGraph>(&json).unwrap(),
+            GRAPH
+        );
+    }
+}
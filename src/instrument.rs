@@ -0,0 +1,425 @@
+//! Observation of acquisitions made through [`LockedAt`](crate::LockedAt).
+//!
+//! The `counters`, `tracing`, and `observer` features hook into blocking
+//! acquisitions here. Detecting whether an acquisition blocked takes an extra
+//! attempt to acquire the lock without blocking, so with none of them
+//! enabled the lock is acquired directly. Other acquisitions, like
+//! non-blocking or read/write ones, go through `acquire` so that they are
+//! still counted.
+//!
+//! Only successful acquisitions are counted.
+//!
+//! With the `tracing` feature, each acquisition runs inside a `TRACE`-level
+//! span named `lock` (or `wait_lock` for async mutexes), with these fields:
+//!
+//! - `level`: the name of the level's type, from [`core::any::type_name`]
+//! - `blocked`: whether the lock was held elsewhere when requested
+//! - `wait_us`: how long the acquisition took, in microseconds
+
+#[cfg(any(feature = "tracing", feature = "observer"))]
+use std::time::Instant;
+
+#[cfg(feature = "async")]
+use crate::lock::AsyncMutexLock;
+use crate::lock::MutexLock;
+
+/// Records a successful acquisition for the level named `level` with the
+/// `counters` feature.
+fn count(level: &'static str, blocked: bool) {
+    #[cfg(feature = "counters")]
+    crate::counters::record(level, blocked);
+    #[cfg(not(feature = "counters"))]
+    let _ = (level, blocked);
+}
+
+/// Acquires `t` for the level named `level`.
+pub(crate) fn lock<'a, M: MutexLock + ?Sized>(
+    level: &'static str,
+    t: &'a M,
+) -> Result<M::Guard<'a>, M::Error<'a>> {
+    #[cfg(not(any(feature = "counters", feature = "tracing", feature = "observer")))]
+    {
+        let _ = level;
+        t.lock()
+    }
+
+    #[cfg(any(feature = "counters", feature = "tracing", feature = "observer"))]
+    {
+        #[cfg(any(feature = "tracing", feature = "observer"))]
+        let observed = Observed::begin(level, false);
+        #[cfg(feature = "tracing")]
+        let _entered = observed.span.enter();
+
+        let (result, blocked) = match t.try_lock() {
+            Ok(guard) => (Ok(guard), false),
+            Err(e) => {
+                // Only a lock held elsewhere counts as blocking; other
+                // failures (e.g. poisoning) are reported by `lock` below.
+                let blocked = M::try_error_would_block(&e);
+                // The error might hold a guard (e.g. for a poisoned lock), so
+                // it has to be dropped before blocking on the lock.
+                drop(e);
+                (t.lock(), blocked)
+            }
+        };
+
+        if result.is_ok() {
+            count(level, blocked);
+        }
+        #[cfg(any(feature = "tracing", feature = "observer"))]
+        observed.finish(blocked);
+        result
+    }
+}
+
+/// Acquires a lock for the level named `level` with `acquire`.
+///
+/// This is for acquisitions other than blocking on a [`MutexLock`], which
+/// aren't checked for contention.
+pub(crate) fn acquire<G, E>(
+    level: &'static str,
+    acquire: impl FnOnce() -> Result<G, E>,
+) -> Result<G, E> {
+    let result = acquire();
+    if result.is_ok() {
+        count(level, false);
+    }
+    result
+}
+
+/// Asynchronously acquires `t` for the level named `level`.
+#[cfg(feature = "async")]
+pub(crate) async fn wait_lock<'a, M: AsyncMutexLock>(
+    level: &'static str,
+    t: &'a M,
+) -> M::Guard<'a> {
+    wait(level, t.lock()).await
+}
+
+/// Awaits `acquire`, an acquisition for the level named `level` that can't
+/// fail.
+#[cfg(feature = "async")]
+pub(crate) async fn wait<F: core::future::Future>(level: &'static str, acquire: F) -> F::Output {
+    let (output, blocked) = observe_wait(level, acquire).await;
+    count(level, blocked);
+    output
+}
+
+/// Awaits `acquire`, a fallible acquisition for the level named `level`.
+#[cfg(feature = "async")]
+pub(crate) async fn try_wait<G, E>(
+    level: &'static str,
+    acquire: impl core::future::Future<Output = Result<G, E>>,
+) -> Result<G, E> {
+    let (output, blocked) = observe_wait(level, acquire).await;
+    if output.is_ok() {
+        count(level, blocked);
+    }
+    output
+}
+
+/// Awaits `acquire`, reporting it to `tracing` and the observer, and returns
+/// its output along with whether it blocked.
+#[cfg(feature = "async")]
+async fn observe_wait<F: core::future::Future>(
+    level: &'static str,
+    acquire: F,
+) -> (F::Output, bool) {
+    #[cfg(not(any(feature = "counters", feature = "tracing", feature = "observer")))]
+    {
+        let _ = level;
+        (acquire.await, false)
+    }
+
+    #[cfg(any(feature = "counters", feature = "tracing", feature = "observer"))]
+    {
+        #[cfg(any(feature = "tracing", feature = "observer"))]
+        let observed = Observed::begin(level, true);
+        #[cfg(not(any(feature = "tracing", feature = "observer")))]
+        let _ = level;
+        #[cfg(feature = "tracing")]
+        let acquire = tracing::Instrument::instrument(acquire, observed.span.clone());
+
+        // The acquisition blocked if the first poll didn't complete it.
+        let mut polls = 0;
+        let mut acquire = core::pin::pin!(acquire);
+        let output = core::future::poll_fn(|cx| {
+            polls += 1;
+            core::future::Future::poll(acquire.as_mut(), cx)
+        })
+        .await;
+
+        let blocked = polls > 1;
+        #[cfg(any(feature = "tracing", feature = "observer"))]
+        observed.finish(blocked);
+        (output, blocked)
+    }
+}
+
+/// An acquisition in progress, reported to `tracing` and the thread's
+/// [`LockObserver`](crate::observer::LockObserver).
+#[cfg(any(feature = "tracing", feature = "observer"))]
+struct Observed {
+    level: &'static str,
+    start: Instant,
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+#[cfg(any(feature = "tracing", feature = "observer"))]
+impl Observed {
+    fn begin(level: &'static str, is_async: bool) -> Self {
+        #[cfg(feature = "observer")]
+        crate::observer::acquire_start(level);
+        #[cfg(not(feature = "tracing"))]
+        let _ = is_async;
+
+        Self {
+            level,
+            start: Instant::now(),
+            #[cfg(feature = "tracing")]
+            span: if is_async {
+                tracing::trace_span!(
+                    "wait_lock",
+                    level,
+                    blocked = tracing::field::Empty,
+                    wait_us = tracing::field::Empty
+                )
+            } else {
+                tracing::trace_span!(
+                    "lock",
+                    level,
+                    blocked = tracing::field::Empty,
+                    wait_us = tracing::field::Empty
+                )
+            },
+        }
+    }
+
+    fn finish(&self, blocked: bool) {
+        let wait = self.start.elapsed();
+        #[cfg(feature = "tracing")]
+        {
+            let wait_us = u64::try_from(wait.as_micros()).unwrap_or(u64::MAX);
+            self.span.record("blocked", blocked);
+            self.span.record("wait_us", wait_us);
+        }
+        #[cfg(feature = "observer")]
+        crate::observer::acquire_end(self.level, blocked, wait);
+        #[cfg(not(feature = "observer"))]
+        let _ = (self.level, wait);
+    }
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::time::Duration;
+
+    use tracing::{
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+        Event, Metadata, Subscriber,
+    };
+
+    use crate::{
+        lock::MutexLockLevel, relation::LockAfter, LockLevel, LockedAt, MutualExclusion, Unlocked,
+    };
+
+    struct Traced;
+
+    impl LockAfter<Unlocked> for Traced {}
+
+    impl LockLevel for Traced {
+        type Method = MutualExclusion;
+    }
+    impl MutexLockLevel for Traced {
+        type Mutex = Mutex<u32>;
+    }
+
+    /// Names and recorded fields of each span, in order of creation.
+    #[derive(Clone, Default)]
+    struct Spans(Arc<Mutex<Vec<(&'static str, Fields)>>>);
+
+    #[derive(Default)]
+    struct Fields(Vec<(&'static str, String)>);
+
+    impl Fields {
+        fn get(&self, name: &str) -> &str {
+            &self.0.iter().find(|(n, _)| *n == name).unwrap().1
+        }
+    }
+
+    impl Visit for Fields {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.push((field.name(), value.to_owned()));
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn core::fmt::Debug) {
+            self.0.push((field.name(), format!("{value:?}")));
+        }
+    }
+
+    impl Subscriber for Spans {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut spans = self.0.lock().unwrap();
+            let mut fields = Fields::default();
+            span.record(&mut fields);
+            spans.push((span.metadata().name(), fields));
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, span: &Id, values: &Record<'_>) {
+            let mut spans = self.0.lock().unwrap();
+            values.record(&mut spans[span.into_u64() as usize - 1].1);
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, _: &Event<'_>) {}
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn span_records_blocking() {
+        let spans = Spans::default();
+        let traced = Mutex::new(0);
+
+        tracing::subscriber::with_default(spans.clone(), || {
+            *LockedAt::new().lock::<Traced>(&traced).unwrap() += 1;
+
+            let (started, wait_started) = mpsc::channel();
+            std::thread::scope(|s| {
+                let held = traced.lock().unwrap();
+                let traced = &traced;
+                let dispatch = tracing::dispatcher::get_default(Clone::clone);
+                s.spawn(move || {
+                    tracing::dispatcher::with_default(&dispatch, || {
+                        started.send(()).unwrap();
+                        *LockedAt::new().lock::<Traced>(traced).unwrap() += 1;
+                    })
+                });
+                wait_started.recv().unwrap();
+                std::thread::sleep(Duration::from_millis(20));
+                drop(held);
+            });
+        });
+
+        let spans = spans.0.lock().unwrap();
+        let [(first, uncontended), (second, contended)] = &spans[..] else {
+            panic!("expected two spans, got {}", spans.len());
+        };
+        assert_eq!((*first, *second), ("lock", "lock"));
+        assert_eq!(uncontended.get("level"), core::any::type_name::<Traced>());
+        assert_eq!(uncontended.get("blocked"), "false");
+        assert_eq!(contended.get("blocked"), "true");
+        assert!(contended.get("wait_us").parse::<u64>().unwrap() >= 10_000);
+    }
+
+    #[test]
+    fn poisoned_lock_not_blocked() {
+        let spans = Spans::default();
+        let traced = Mutex::new(0);
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let _guard = traced.lock().unwrap();
+                panic!("poison the lock");
+            })
+            .join()
+            .unwrap_err();
+        });
+
+        tracing::subscriber::with_default(spans.clone(), || {
+            assert!(LockedAt::new().lock::<Traced>(&traced).is_err());
+        });
+
+        let spans = spans.0.lock().unwrap();
+        let [(_, fields)] = &spans[..] else {
+            panic!("expected one span, got {}", spans.len());
+        };
+        assert_eq!(fields.get("blocked"), "false");
+    }
+
+    #[cfg(feature = "parking_lot")]
+    #[test]
+    fn reentrant_acquisition_traced() {
+        struct Reentrant;
+
+        impl LockAfter<Unlocked> for Reentrant {}
+
+        impl LockLevel for Reentrant {
+            type Method = MutualExclusion;
+        }
+        impl MutexLockLevel for Reentrant {
+            type Mutex = parking_lot::ReentrantMutex<u32>;
+        }
+
+        let spans = Spans::default();
+        let traced = parking_lot::ReentrantMutex::new(0);
+
+        tracing::subscriber::with_default(spans.clone(), || {
+            let mut locked = LockedAt::new();
+            let Ok((locked, outer)) = locked.with_lock::<Reentrant>(&traced);
+            let Ok(inner) = locked.lock_reentrant(&traced);
+            assert_eq!(*outer, *inner);
+        });
+
+        let spans = spans.0.lock().unwrap();
+        let blocked: Vec<_> = spans
+            .iter()
+            .map(|(name, fields)| {
+                assert_eq!(*name, "lock");
+                fields.get("blocked")
+            })
+            .collect();
+        assert_eq!(blocked, ["false", "false"]);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn async_span_records_blocking() {
+        struct AsyncTraced;
+
+        impl LockAfter<Unlocked> for AsyncTraced {}
+
+        impl LockLevel for AsyncTraced {
+            type Method = MutualExclusion;
+        }
+        impl crate::lock::AsyncMutexLockLevel for AsyncTraced {
+            type Mutex = tokio::sync::Mutex<u32>;
+        }
+
+        let spans = Spans::default();
+        let traced = tokio::sync::Mutex::new(0);
+
+        tracing::subscriber::with_default(spans.clone(), || {
+            futures::executor::block_on(async {
+                *LockedAt::new().wait_lock::<AsyncTraced>(&traced).await += 1;
+
+                let held = traced.lock().await;
+                let mut locked = LockedAt::new();
+                let waiting = locked.wait_lock::<AsyncTraced>(&traced);
+                let release = async {
+                    tokio::task::yield_now().await;
+                    drop(held);
+                };
+                let (mut guard, ()) = futures::join!(waiting, release);
+                *guard += 1;
+            });
+        });
+
+        let spans = spans.0.lock().unwrap();
+        let blocked: Vec<_> = spans
+            .iter()
+            .map(|(name, fields)| {
+                assert_eq!(*name, "wait_lock");
+                fields.get("blocked")
+            })
+            .collect();
+        assert_eq!(blocked, ["false", "true"]);
+    }
+}
@@ -1,5 +1,4 @@
 #![cfg_attr(not(feature = "std"), no_std)]
-#![cfg_attr(feature = "async", feature(async_fn_in_trait))]
 
 //! # Lock ordering enforcement at compile time
 //!
@@ -12,25 +11,89 @@
 //! logic lives in the [`LockedAt`] type; it uses trait bounds to ensure that
 //! any acquisition of locks respects these orderings.
 
+#[cfg(any(feature = "std", feature = "parking_lot"))]
+pub mod backend;
+mod cell;
+#[cfg(feature = "counters")]
+pub mod counters;
+#[cfg(feature = "std")]
+pub mod debug_checks;
+#[cfg(feature = "std")]
+pub mod graph;
+mod instrument;
 pub mod lock;
 mod lockedat;
+#[cfg(feature = "observer")]
+pub mod observer;
 pub mod relation;
+#[cfg(feature = "macros")]
+mod section;
+#[cfg(feature = "std")]
+pub mod spawn;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+#[cfg(feature = "std")]
+mod token;
 
-pub use lockedat::{LockedAt, MutualExclusion, ReadWrite};
+pub use cell::{CellGuard, CellLockedAt};
+pub use lockedat::{LevelGuard, LockedAt, MutualExclusion, ReadWrite};
+#[cfg(feature = "std")]
+pub use token::ThreadToken;
+#[cfg(feature = "macros")]
+pub use {
+    lock_ordering_macros::{lock_section, LockLevel},
+    section::LockSection,
+};
 
 /// The least-restrictive lock level, when no locks are held.
 pub struct Unlocked;
 
 /// Marker for a type that indicates a level in the locking hierarchy.
 pub trait LockLevel {
+    /// How the level's state is accessed, [`MutualExclusion`] or
+    /// [`ReadWrite`].
+    ///
+    /// The traits connecting a level to a lock implementation, like
+    /// [`MutexLockLevel`](lock::MutexLockLevel) and
+    /// [`RwLockLevel`](lock::RwLockLevel), require the matching method, so a
+    /// level can't be declared as one kind of lock and backed by another.
     type Method;
 }
 
+/// A lock level with a numeric rank.
+///
+/// Ranks are optional and aren't used to check acquisitions, which only rely
+/// on [`LockAfter`](relation::LockAfter) impls. They give levels an order
+/// that can be compared at runtime, e.g. to log the highest level held, or to
+/// sort levels in reports. [`assert_rank_order!`] checks at compile time that
+/// ranks agree with the `LockAfter` impls.
+///
+/// [`Unlocked`] has rank 0, so levels acquired from it should have higher
+/// ranks.
+pub trait RankedLockLevel {
+    /// The rank of this level; levels acquired later have higher ranks.
+    const RANK: u32;
+}
+
+impl RankedLockLevel for Unlocked {
+    const RANK: u32 = 0;
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
     fn compile_fail() {
-        let t = trybuild::TestCases::new();
-        t.compile_fail("tests/fail/*.rs");
+        {
+            let t = trybuild::TestCases::new();
+            t.compile_fail("tests/fail/*.rs");
+            // These errors name the backend's guard types, which are wrapped
+            // with `debug_checks`.
+            #[cfg(not(feature = "debug_checks"))]
+            t.compile_fail("tests/fail/backend-guard/*.rs");
+            #[cfg(not(feature = "test-util"))]
+            t.compile_fail("tests/lock-order/*.rs");
+        }
+        #[cfg(feature = "test-util")]
+        crate::test_util::assert_lock_order_violations("tests/lock-order");
     }
 }
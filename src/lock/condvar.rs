@@ -0,0 +1,82 @@
+//! Condition variables paired with a [`MutexLock`].
+
+use super::MutexLock;
+
+/// A condition variable that can be waited on while holding a guard for `M`.
+///
+/// See [`LockedAt::wait_cvar`](crate::LockedAt::wait_cvar).
+pub trait CondvarLock<M: MutexLock + ?Sized> {
+    /// Error produced when the mutex can't be reacquired after waiting.
+    type WaitError<'a>
+    where
+        M: 'a;
+
+    /// Blocks until notified, atomically releasing the mutex for `guard`
+    /// while waiting and reacquiring it before returning.
+    ///
+    /// Like most condition variables, this can wake up spuriously, so
+    /// callers should check their condition in a loop.
+    fn wait<'a>(&self, guard: M::Guard<'a>) -> Result<M::Guard<'a>, Self::WaitError<'a>>
+    where
+        M: 'a;
+
+    /// Wakes up one thread waiting on this condition variable.
+    fn notify_one(&self);
+
+    /// Wakes up all threads waiting on this condition variable.
+    fn notify_all(&self);
+}
+
+#[cfg(feature = "std")]
+mod std {
+    use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+
+    impl<T> super::CondvarLock<Mutex<T>> for Condvar {
+        type WaitError<'a> = PoisonError<MutexGuard<'a, T>> where T: 'a;
+
+        fn wait<'a>(&self, guard: MutexGuard<'a, T>) -> Result<MutexGuard<'a, T>, Self::WaitError<'a>>
+        where
+            T: 'a,
+        {
+            Condvar::wait(self, guard)
+        }
+
+        fn notify_one(&self) {
+            Condvar::notify_one(self)
+        }
+
+        fn notify_all(&self) {
+            Condvar::notify_all(self)
+        }
+    }
+}
+
+#[cfg(feature = "parking_lot")]
+mod parking_lot {
+    use core::convert::Infallible;
+
+    use parking_lot::{Condvar, Mutex, MutexGuard};
+
+    impl<T: ?Sized> super::CondvarLock<Mutex<T>> for Condvar {
+        type WaitError<'a> = Infallible where T: 'a;
+
+        fn wait<'a>(
+            &self,
+            mut guard: MutexGuard<'a, T>,
+        ) -> Result<MutexGuard<'a, T>, Self::WaitError<'a>>
+        where
+            T: 'a,
+        {
+            Condvar::wait(self, &mut guard);
+            Ok(guard)
+        }
+
+        fn notify_one(&self) {
+            let _: bool = Condvar::notify_one(self);
+        }
+
+        fn notify_all(&self) {
+            let _: usize = Condvar::notify_all(self);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! Reader/writer lock that counts write acquisitions.
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError,
+};
+
+/// A [`std::sync::RwLock`] that tracks how many times it was write-locked.
+///
+/// The generation counter is incremented each time write access is granted,
+/// while the write lock is held. Comparing generations observed under two
+/// different acquisitions reveals whether any writer got the lock in between,
+/// which is what [`LockedAt::write_then_read_consistent`] uses to emulate a
+/// write-to-read downgrade with `std` locks.
+///
+/// [`LockedAt::write_then_read_consistent`]: crate::LockedAt::write_then_read_consistent
+#[derive(Debug, Default)]
+pub struct GenerationRwLock<T: ?Sized> {
+    generation: AtomicU64,
+    lock: RwLock<T>,
+}
+
+impl<T> GenerationRwLock<T> {
+    /// Creates a new lock holding `value`, at generation 0.
+    pub const fn new(value: T) -> Self {
+        Self {
+            generation: AtomicU64::new(0),
+            lock: RwLock::new(value),
+        }
+    }
+
+    /// Consumes the lock, returning the protected value.
+    pub fn into_inner(self) -> Result<T, PoisonError<T>> {
+        self.lock.into_inner()
+    }
+}
+
+impl<T: ?Sized> GenerationRwLock<T> {
+    /// Returns the number of times write access has been granted.
+    ///
+    /// While any guard for the lock is held, the returned value can't change
+    /// except by the holder of a write guard.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+}
+
+impl<T: ?Sized> super::RwLock for GenerationRwLock<T> {
+    type ReadError<'a> = PoisonError<RwLockReadGuard<'a, T>> where Self: 'a;
+    type WriteError<'a> = PoisonError<RwLockWriteGuard<'a, T>> where Self: 'a;
+    type TryReadError<'a> = TryLockError<RwLockReadGuard<'a, T>> where Self: 'a;
+    type TryWriteError<'a> = TryLockError<RwLockWriteGuard<'a, T>> where Self: 'a;
+
+    type ReadGuard<'a> = RwLockReadGuard<'a, T> where Self: 'a;
+    type WriteGuard<'a> = RwLockWriteGuard<'a, T> where Self: 'a;
+
+    fn read(&self) -> Result<Self::ReadGuard<'_>, Self::ReadError<'_>> {
+        self.lock.read()
+    }
+
+    fn write(&self) -> Result<Self::WriteGuard<'_>, Self::WriteError<'_>> {
+        let guard = self.lock.write()?;
+        let _: u64 = self.generation.fetch_add(1, Ordering::Release);
+        Ok(guard)
+    }
+
+    fn try_read(&self) -> Result<Self::ReadGuard<'_>, Self::TryReadError<'_>> {
+        self.lock.try_read()
+    }
+
+    fn try_write(&self) -> Result<Self::WriteGuard<'_>, Self::TryWriteError<'_>> {
+        let guard = self.lock.try_write()?;
+        let _: u64 = self.generation.fetch_add(1, Ordering::Release);
+        Ok(guard)
+    }
+}
@@ -0,0 +1,135 @@
+//! Single-threaded async "lock" built on [`RefCell`].
+
+use core::cell::{RefCell, RefMut};
+
+/// A [`RefCell`]-backed mutex for single-threaded async executors.
+///
+/// On a single-threaded executor without preemption there is no real
+/// contention, so acquiring a `LocalMutex` never has to wait: the future
+/// returned by [`AsyncMutexLock::lock`](super::AsyncMutexLock::lock) resolves
+/// immediately with a mutable borrow of the contents. This lets code written
+/// for the async [`LockedAt`](crate::LockedAt) methods run in `no_std`
+/// environments without a synchronization primitive.
+///
+/// Since the guard is a plain [`RefMut`], holding it across an `.await` and
+/// then trying to lock again from another task is a logic error and will
+/// panic, just like overlapping [`RefCell::borrow_mut`] calls.
+#[derive(Debug, Default)]
+pub struct LocalMutex<T: ?Sized>(RefCell<T>);
+
+impl<T> LocalMutex<T> {
+    /// Creates a new `LocalMutex` holding `value`.
+    pub const fn new(value: T) -> Self {
+        Self(RefCell::new(value))
+    }
+
+    /// Consumes the `LocalMutex`, returning the wrapped value.
+    pub fn into_inner(self) -> T {
+        self.0.into_inner()
+    }
+}
+
+impl<T: ?Sized> LocalMutex<T> {
+    /// Returns a mutable reference to the wrapped value.
+    ///
+    /// Since this borrows `self` mutably, no locking is needed.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.0.get_mut()
+    }
+}
+
+impl<T: ?Sized> super::AsyncMutexLock for LocalMutex<T> {
+    type Guard<'a> = RefMut<'a, T> where Self: 'a;
+
+    async fn lock(&self) -> Self::Guard<'_> {
+        self.0
+            .try_borrow_mut()
+            .expect("LocalMutex locked while already held")
+    }
+}
+
+impl<T: ?Sized> super::NonBlockingAsyncMutexLock for LocalMutex<T> {
+    fn try_lock(&self) -> Option<Self::Guard<'_>> {
+        self.0.try_borrow_mut().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        future::Future,
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use crate::{
+        lock::AsyncMutexLockLevel, relation::LockAfter, LockLevel, LockedAt, MutualExclusion,
+        Unlocked,
+    };
+
+    use super::LocalMutex;
+
+    struct First;
+    struct Second;
+
+    impl LockAfter<Unlocked> for First {}
+    impl LockAfter<First> for Second {}
+
+    impl LockLevel for First {
+        type Method = MutualExclusion;
+    }
+    impl AsyncMutexLockLevel for First {
+        type Mutex = LocalMutex<u32>;
+    }
+
+    impl LockLevel for Second {
+        type Method = MutualExclusion;
+    }
+    impl AsyncMutexLockLevel for Second {
+        type Mutex = LocalMutex<char>;
+    }
+
+    /// Minimal single-threaded executor for futures that never wait.
+    fn block_on<F: Future>(f: F) -> F::Output {
+        let mut f = pin!(f);
+        let mut cx = Context::from_waker(Waker::noop());
+        match f.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("LocalMutex acquisition should never be pending"),
+        }
+    }
+
+    #[test]
+    // Acquiring a `LocalMutex` never actually yields, so holding the first
+    // guard while locking the second is fine.
+    #[allow(clippy::await_holding_refcell_ref)]
+    fn wait_lock() {
+        let first = LocalMutex::new(1);
+        let second = LocalMutex::new('a');
+
+        block_on(async {
+            let mut locked = LockedAt::new();
+            let (mut locked, mut first_guard) = locked.wait_for_lock::<First>(&first).await;
+            let mut second_guard = locked.wait_lock::<Second>(&second).await;
+
+            *first_guard += 1;
+            *second_guard = 'b';
+        });
+
+        assert_eq!(first.into_inner(), 2);
+        assert_eq!(second.into_inner(), 'b');
+    }
+
+    #[test]
+    #[should_panic(expected = "already held")]
+    #[allow(clippy::await_holding_refcell_ref)]
+    fn overlapping_lock_panics() {
+        let first = LocalMutex::new(1);
+
+        block_on(async {
+            let (mut this_task, mut other_task) = (LockedAt::new(), LockedAt::new());
+            let _held = this_task.wait_lock::<First>(&first).await;
+            let _again = other_task.wait_lock::<First>(&first).await;
+        });
+    }
+}
@@ -1,20 +1,358 @@
 //! Traits that describe how locked data is accessed.
 
-pub use mutex::MutexLock;
-pub use rwlock::RwLock;
+pub use condvar::CondvarLock;
+pub use mutex::{
+    GetMutLock, IntoInnerLock, MappableGuard, MutexLock, MutexLockData, MutexLockTimeout,
+    ReentrantMutexLock,
+};
+pub use rwlock::{
+    CowGuard, DowngradableRwLock, RecursiveReadRwLock, RwError, RwGuard, RwLock,
+    UpgradableReadGuard, UpgradableRwLock,
+};
+#[cfg(feature = "critical-section")]
+pub use mutex::CriticalSectionGuard;
+#[cfg(feature = "std")]
+pub use generation::GenerationRwLock;
+#[cfg(feature = "std")]
+pub use poison::IgnorePoison;
+#[cfg(all(feature = "refcell", feature = "async"))]
+pub use local::LocalMutex;
+#[cfg(all(feature = "async", feature = "std"))]
+pub use mutex::OwnedMutexLock;
 #[cfg(feature = "async")]
-pub use {mutex::AsyncMutexLock, rwlock::AsyncRwLock};
+pub use {
+    mutex::{AsyncMutexLock, NonBlockingAsyncMutexLock, TryAsyncMutexLock},
+    rwlock::{AsyncRwLock, NonBlockingAsyncRwLock},
+    semaphore::AsyncSemaphore,
+};
 
-use crate::{LockLevel, MutualExclusion, ReadWrite};
+use core::{
+    ops::{Deref, DerefMut},
+    panic::Location,
+};
 
+use crate::{LevelGuard, LockLevel, MutualExclusion, ReadWrite};
+
+mod condvar;
+#[cfg(feature = "std")]
+mod generation;
+#[cfg(all(feature = "refcell", feature = "async"))]
+mod local;
 mod mutex;
+#[cfg(feature = "std")]
+mod poison;
 mod rwlock;
+#[cfg(feature = "async")]
+mod semaphore;
+
+/// Error for a non-blocking acquisition that failed because the lock is held.
+///
+/// This is the error type for lock implementations where the only way a
+/// non-blocking acquisition can fail is that the lock is currently held.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WouldBlock;
+
+impl core::fmt::Display for WouldBlock {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("lock is held elsewhere")
+    }
+}
+
+/// Error for an acquisition that gave up because a timeout expired.
+///
+/// This is the error type for [`MutexLockTimeout`] implementations where the
+/// only way a timed acquisition can fail is that the timeout expired.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimedOut;
+
+impl core::fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("timed out waiting for lock")
+    }
+}
+
+/// An acquisition error along with where the acquisition was attempted.
+///
+/// Returned by [`LockedAt::lock_located`](crate::LockedAt::lock_located) and
+/// [`LockedAt::with_lock_located`](crate::LockedAt::with_lock_located). The
+/// call site is only recorded with the `debug-locations` feature enabled;
+/// without it, this is a plain wrapper around the error.
+#[derive(Debug)]
+pub struct Located<E> {
+    error: E,
+    #[cfg(feature = "debug-locations")]
+    location: &'static Location<'static>,
+}
+
+impl<E> Located<E> {
+    #[track_caller]
+    pub(crate) fn new(error: E) -> Self {
+        Self {
+            error,
+            #[cfg(feature = "debug-locations")]
+            location: Location::caller(),
+        }
+    }
+
+    /// Returns the wrapped acquisition error.
+    pub fn error(&self) -> &E {
+        &self.error
+    }
+
+    /// Unwraps the acquisition error.
+    pub fn into_error(self) -> E {
+        self.error
+    }
+
+    /// Returns the call site of the failed acquisition, if it was recorded.
+    pub fn location(&self) -> Option<&'static Location<'static>> {
+        #[cfg(feature = "debug-locations")]
+        return Some(self.location);
+        #[cfg(not(feature = "debug-locations"))]
+        return None;
+    }
+}
+
+impl<E: core::fmt::Display> core::fmt::Display for Located<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.location() {
+            Some(location) => write!(f, "{} (at {location})", self.error),
+            None => self.error.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: std::error::Error + 'static> std::error::Error for Located<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Error from acquiring a pair of locks at successive levels.
+///
+/// Produced by [`LockedAt::lock_pair`](crate::LockedAt::lock_pair).
+#[derive(Debug)]
+pub enum PairError<F, S> {
+    /// Acquiring the first lock failed.
+    First(F),
+    /// Acquiring the second lock failed; the first lock was released.
+    Second(S),
+}
+
+impl<F: core::fmt::Display, S: core::fmt::Display> core::fmt::Display for PairError<F, S> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            PairError::First(e) => write!(f, "acquiring first lock of pair: {e}"),
+            PairError::Second(e) => write!(f, "acquiring second lock of pair: {e}"),
+        }
+    }
+}
+
+/// One of two guards or errors, for acquiring one of two levels.
+///
+/// Produced by [`LockedAt::with_lock_either`](crate::LockedAt::with_lock_either),
+/// which also takes the lock to acquire as an `Either`. Dereferences to the
+/// locked data when both guard types do.
+#[derive(Debug)]
+pub enum Either<F, S> {
+    /// The first of the two.
+    First(F),
+    /// The second of the two.
+    Second(S),
+}
+
+impl<T: ?Sized, F: Deref<Target = T>, S: Deref<Target = T>> Deref for Either<F, S> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match self {
+            Either::First(f) => f,
+            Either::Second(s) => s,
+        }
+    }
+}
+
+impl<T: ?Sized, F: DerefMut<Target = T>, S: DerefMut<Target = T>> DerefMut for Either<F, S> {
+    fn deref_mut(&mut self) -> &mut T {
+        match self {
+            Either::First(f) => f,
+            Either::Second(s) => s,
+        }
+    }
+}
+
+/// Guard for a lock that is only held for ordering and exclusion.
+///
+/// Produced by [`LockedAt::acquire_order`](crate::LockedAt::acquire_order)
+/// and [`LockedAt::with_acquire_order`](crate::LockedAt::with_acquire_order),
+/// typically for a `Mutex<()>` that protects invariants rather than a value.
+/// The lock is held until this is dropped, but the protected value can't be
+/// accessed through it.
+#[must_use = "the lock is released when the guard is dropped"]
+pub struct OrderGuard<'a, M: MutexLock + ?Sized + 'a> {
+    _guard: LevelGuard<M::Guard<'a>>,
+}
+
+impl<'a, M: MutexLock + ?Sized> OrderGuard<'a, M> {
+    pub(crate) fn new(guard: LevelGuard<M::Guard<'a>>) -> Self {
+        Self { _guard: guard }
+    }
+}
+
+/// Guard for a lock at level `L` that keeps track of which lock it holds.
+///
+/// Produced by
+/// [`LockedAt::with_lock_suspendable`](crate::LockedAt::with_lock_suspendable).
+/// Since it knows its lock, it can be released and re-acquired with
+/// [`LockedAt::suspend_lock`](crate::LockedAt::suspend_lock). Dereferences to
+/// the locked data when the wrapped guard does.
+#[must_use = "the lock is released when the guard is dropped"]
+pub struct SuspendableGuard<'a, L: MutexLockLevel> {
+    lock: &'a L::Mutex,
+    guard: LevelGuard<<L::Mutex as MutexLock>::Guard<'a>>,
+}
+
+impl<'a, L: MutexLockLevel> SuspendableGuard<'a, L> {
+    pub(crate) fn new(
+        lock: &'a L::Mutex,
+        guard: LevelGuard<<L::Mutex as MutexLock>::Guard<'a>>,
+    ) -> Self {
+        Self { lock, guard }
+    }
+
+    /// Releases the lock, returning it so that it can be acquired again.
+    pub(crate) fn release(self) -> &'a L::Mutex {
+        let Self { lock, guard } = self;
+        drop(guard);
+        lock
+    }
+
+    /// Unwraps the guard, which can then no longer be suspended.
+    pub fn into_guard(self) -> LevelGuard<<L::Mutex as MutexLock>::Guard<'a>> {
+        self.guard
+    }
+}
+
+impl<'a, L: MutexLockLevel> Deref for SuspendableGuard<'a, L>
+where
+    <L::Mutex as MutexLock>::Guard<'a>: Deref,
+{
+    type Target = <<L::Mutex as MutexLock>::Guard<'a> as Deref>::Target;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<'a, L: MutexLockLevel> DerefMut for SuspendableGuard<'a, L>
+where
+    <L::Mutex as MutexLock>::Guard<'a>: DerefMut,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+/// Write guard for a lock at level `L` that keeps track of which lock it
+/// holds.
+///
+/// Produced by
+/// [`LockedAt::with_write_lock_relockable`](crate::LockedAt::with_write_lock_relockable).
+/// Since it knows its lock, it can be exchanged for a read guard on the same
+/// lock with [`LockedAt::relock_read`](crate::LockedAt::relock_read).
+/// Dereferences to the locked data when the wrapped guard does.
+#[must_use = "the lock is released when the guard is dropped"]
+pub struct RelockableWriteGuard<'a, L: RwLockLevel> {
+    lock: &'a L::RwLock,
+    guard: LevelGuard<<L::RwLock as RwLock>::WriteGuard<'a>>,
+}
+
+impl<'a, L: RwLockLevel> RelockableWriteGuard<'a, L> {
+    pub(crate) fn new(
+        lock: &'a L::RwLock,
+        guard: LevelGuard<<L::RwLock as RwLock>::WriteGuard<'a>>,
+    ) -> Self {
+        Self { lock, guard }
+    }
+
+    /// Releases the lock, returning it so that it can be acquired again.
+    pub(crate) fn release(self) -> &'a L::RwLock {
+        let Self { lock, guard } = self;
+        drop(guard);
+        lock
+    }
+
+    /// Unwraps the guard, which can then no longer be relocked.
+    pub fn into_guard(self) -> LevelGuard<<L::RwLock as RwLock>::WriteGuard<'a>> {
+        self.guard
+    }
+}
+
+impl<'a, L: RwLockLevel> Deref for RelockableWriteGuard<'a, L>
+where
+    <L::RwLock as RwLock>::WriteGuard<'a>: Deref,
+{
+    type Target = <<L::RwLock as RwLock>::WriteGuard<'a> as Deref>::Target;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<'a, L: RwLockLevel> DerefMut for RelockableWriteGuard<'a, L>
+where
+    <L::RwLock as RwLock>::WriteGuard<'a>: DerefMut,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
 
 /// Connects a [`LockLevel`] with a [`MutexLock`] implementation.
 pub trait MutexLockLevel: LockLevel<Method = MutualExclusion> {
     type Mutex: MutexLock;
 }
 
+/// A [`MutexLockLevel`] whose locks form a tree.
+///
+/// Each lock at the level protects a node, and [`TreeLockLevel::children`]
+/// returns the locks of the node's children from its protected value. Child
+/// nodes are always locked after their parent, so holding a node allows
+/// locking its children at the same level with
+/// [`LockedAt::with_lock_child`](crate::LockedAt::with_lock_child).
+///
+/// Implementations must only return locks of the node's children, never of
+/// its ancestors or of unrelated nodes, or locking a child can deadlock.
+pub trait TreeLockLevel: MutexLockLevel
+where
+    Self::Mutex: MutexLockData,
+{
+    /// Returns the locks of `node`'s children.
+    fn children<'n>(
+        node: &'n <Self::Mutex as MutexLockData>::Data,
+    ) -> impl Iterator<Item = &'n Self::Mutex>
+    where
+        Self::Mutex: 'n;
+}
+
+/// A handle through which the [`MutexLock`] for level `L` can be reached.
+///
+/// This is implemented for every type that dereferences to `L::Mutex`, which
+/// includes plain references, [`Arc`](std::sync::Arc)s, and custom smart
+/// pointers. It lets helpers be generic over how they are given a lock; see
+/// [`LockedAt::lock_ref`](crate::LockedAt::lock_ref).
+pub trait LockRef<L: MutexLockLevel> {
+    /// Returns the lock this handle refers to.
+    fn mutex(&self) -> &L::Mutex;
+}
+
+impl<L: MutexLockLevel, R: Deref<Target = L::Mutex> + ?Sized> LockRef<L> for R {
+    fn mutex(&self) -> &L::Mutex {
+        self
+    }
+}
+
 /// Connects a [`LockLevel`] with a [`RwLock`] implementation.
 pub trait RwLockLevel: LockLevel<Method = ReadWrite> {
     type RwLock: RwLock;
@@ -26,6 +364,21 @@ pub trait AsyncMutexLockLevel: LockLevel<Method = MutualExclusion> {
     type Mutex: AsyncMutexLock;
 }
 
+/// Connects a [`LockLevel`] with an [`AsyncSemaphore`] implementation.
+///
+/// Semaphores don't distinguish readers from writers, so the level's
+/// [`Method`](LockLevel::Method) isn't constrained.
+#[cfg(feature = "async")]
+pub trait SemaphoreLevel: LockLevel {
+    type Semaphore: AsyncSemaphore;
+}
+
+/// Connects a [`LockLevel`] with a [`TryAsyncMutexLock`] implementation.
+#[cfg(feature = "async")]
+pub trait TryAsyncMutexLockLevel: LockLevel<Method = MutualExclusion> {
+    type Mutex: TryAsyncMutexLock;
+}
+
 /// Connects a [`LockLevel`] with a [`RwLock`] implementation.
 #[cfg(feature = "async")]
 pub trait AsyncRwLockLevel: LockLevel<Method = ReadWrite> {
@@ -1,3 +1,5 @@
+use core::{ops::DerefMut, time::Duration};
+
 /// Locking implementation for [crate::MutualExclusion].
 ///
 /// Describes how to acquire access to the state for a [crate::LockLevel]
@@ -19,30 +21,948 @@ pub trait MutexLock {
     where
         Self: 'a;
 
+    /// Error that could be produced when trying to acquire the lock without
+    /// blocking.
+    ///
+    /// This should distinguish the lock being held elsewhere from the other
+    /// failures represented by [`MutexLock::Error`]. For implementations with
+    /// no other failure modes, [`WouldBlock`](super::WouldBlock) can be used.
+    type TryError<'a>
+    where
+        Self: 'a;
+
     /// Attempts to acquire exclusive access to data.
     ///
     /// Returns an RAII guard that provides access to the data, or an error on
     /// failure.
     fn lock(&self) -> Result<Self::Guard<'_>, Self::Error<'_>>;
+
+    /// Attempts to acquire exclusive access to data without blocking.
+    ///
+    /// Returns an RAII guard that provides access to the data, or an error if
+    /// the lock is currently held or acquisition otherwise failed.
+    fn try_lock(&self) -> Result<Self::Guard<'_>, Self::TryError<'_>>;
+
+    /// Returns whether `error` from [`MutexLock::try_lock`] means only that
+    /// the lock is held elsewhere.
+    ///
+    /// With the `counters` feature, this decides whether an acquisition is
+    /// counted as contended. The default treats every failure as the lock
+    /// being held; implementations whose [`MutexLock::TryError`] also
+    /// represents other failures, like poisoning, should override it.
+    fn try_error_would_block(error: &Self::TryError<'_>) -> bool {
+        let _ = error;
+        true
+    }
+}
+
+/// A [`MutexLock`] that can be consumed to produce the protected value.
+///
+/// Since this takes the lock by value, no other references to it can exist,
+/// and so no locking is necessary. Implementations for locks that can be
+/// poisoned should recover the value anyway.
+pub trait IntoInnerLock: MutexLock + Sized {
+    /// The protected value.
+    type Inner;
+
+    /// Consumes the lock, returning the protected value.
+    fn into_inner(self) -> Self::Inner;
+}
+
+/// A [`MutexLock`] that provides access to the protected value through a
+/// unique reference to the lock.
+///
+/// Like [`IntoInnerLock`], having `&mut self` means no other references to
+/// the lock exist, so no locking is necessary. Implementations for locks that
+/// can be poisoned should provide access anyway.
+pub trait GetMutLock: MutexLock {
+    /// The protected value.
+    type Inner: ?Sized;
+
+    /// Returns a mutable reference to the protected value.
+    fn get_mut(&mut self) -> &mut Self::Inner;
+}
+
+/// A [`MutexLock`] whose guards give mutable access to a known data type.
+///
+/// The guard types of [`MutexLock`] implementations are backend-specific,
+/// so generic code can't assume how to reach the protected value through
+/// them. This names the protected value's type and provides that access, so
+/// code can be written against `M: MutexLockData<Data = T>` without knowing
+/// the backend.
+pub trait MutexLockData: MutexLock {
+    /// The protected value.
+    type Data: ?Sized;
+
+    /// Returns the protected value from a guard for this lock.
+    fn data<'g>(guard: &'g mut Self::Guard<'_>) -> &'g mut Self::Data;
+}
+
+/// A [`MutexLock`] that can be acquired again by the thread that holds it.
+///
+/// Locking a reentrant mutex from the thread that already holds it succeeds
+/// immediately instead of deadlocking. Since other threads can still hold
+/// it, the guards usually only give shared access to the protected value.
+pub trait ReentrantMutexLock: MutexLock {
+    /// Returns whether the current thread holds the lock.
+    fn is_owned_by_current_thread(&self) -> bool;
+}
+
+/// A mutex guard that can be narrowed to a part of the protected value.
+///
+/// The mapped guard keeps the lock held, but only provides access to the
+/// value returned by the mapping function, typically a field. Mapping is
+/// guard-specific, so this is implemented for the guards of backends that
+/// support it. `std`'s guards aren't included since
+/// [`MutexGuard::map`](std::sync::MutexGuard) isn't stable yet.
+pub trait MappableGuard<'a>: DerefMut + Sized {
+    /// Guard for a part of the protected value.
+    type Mapped<U: ?Sized + 'a>: DerefMut<Target = U>;
+
+    /// Makes a guard for the part of the protected value returned by `f`.
+    fn map<U: ?Sized + 'a>(self, f: impl FnOnce(&mut Self::Target) -> &mut U) -> Self::Mapped<U>;
+}
+
+/// A [`MutexLock`] that supports giving up on acquisition after a timeout.
+pub trait MutexLockTimeout: MutexLock {
+    /// Error that could be produced when acquiring the lock with a timeout.
+    ///
+    /// This should distinguish the timeout expiring from other failures. For
+    /// implementations with no other failure modes,
+    /// [`TimedOut`](super::TimedOut) can be used.
+    type TimeoutError<'a>
+    where
+        Self: 'a;
+
+    /// Attempts to acquire exclusive access to data, waiting at most
+    /// `timeout` for the lock to become available.
+    fn lock_timeout(&self, timeout: Duration) -> Result<Self::Guard<'_>, Self::TimeoutError<'_>>;
 }
 
 #[cfg(feature = "std")]
 mod std {
     //! Implementation of [`MutexLock`] for [`std::sync::Mutex`].
 
-    use std::sync::{Mutex, MutexGuard, PoisonError};
+    use std::{
+        sync::{Mutex, MutexGuard, PoisonError, TryLockError},
+        time::{Duration, Instant},
+    };
+
+    impl<T> super::IntoInnerLock for Mutex<T> {
+        type Inner = T;
+
+        fn into_inner(self) -> T {
+            Mutex::into_inner(self).unwrap_or_else(PoisonError::into_inner)
+        }
+    }
+
+    impl<T: ?Sized> super::GetMutLock for Mutex<T> {
+        type Inner = T;
+
+        fn get_mut(&mut self) -> &mut T {
+            Mutex::get_mut(self).unwrap_or_else(PoisonError::into_inner)
+        }
+    }
+
+    impl<T: ?Sized> super::MutexLockData for Mutex<T> {
+        type Data = T;
+
+        fn data<'g>(guard: &'g mut Self::Guard<'_>) -> &'g mut T {
+            guard
+        }
+    }
 
     impl<T: ?Sized> super::MutexLock for Mutex<T> {
         type Guard<'a> = MutexGuard<'a, T> where Self: 'a;
         type Error<'a> = PoisonError<MutexGuard<'a, T>> where Self: 'a;
+        type TryError<'a> = TryLockError<MutexGuard<'a, T>> where Self: 'a;
 
         fn lock(&self) -> Result<Self::Guard<'_>, Self::Error<'_>> {
             Mutex::lock(self)
         }
+
+        fn try_lock(&self) -> Result<Self::Guard<'_>, Self::TryError<'_>> {
+            Mutex::try_lock(self)
+        }
+
+        fn try_error_would_block(error: &Self::TryError<'_>) -> bool {
+            matches!(error, TryLockError::WouldBlock)
+        }
+    }
+
+    /// `std` mutexes can't wait with a timeout, so this polls with
+    /// [`Mutex::try_lock`] until the deadline. A timeout is reported as
+    /// [`TryLockError::WouldBlock`].
+    impl<T: ?Sized> super::MutexLockTimeout for Mutex<T> {
+        type TimeoutError<'a> = TryLockError<MutexGuard<'a, T>> where Self: 'a;
+
+        fn lock_timeout(
+            &self,
+            timeout: Duration,
+        ) -> Result<Self::Guard<'_>, Self::TimeoutError<'_>> {
+            /// Longest time to sleep between attempts.
+            const MAX_BACKOFF: Duration = Duration::from_millis(1);
+
+            let deadline = Instant::now() + timeout;
+            let mut backoff = Duration::from_micros(1);
+            loop {
+                match Mutex::try_lock(self) {
+                    Err(TryLockError::WouldBlock) => {}
+                    result => return result,
+                }
+                let now = Instant::now();
+                if now >= deadline {
+                    return Err(TryLockError::WouldBlock);
+                }
+                std::thread::sleep(backoff.min(deadline - now));
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "lock-api")]
+mod lock_api {
+    //! Implementation of [`MutexLock`](super::MutexLock) for
+    //! [`lock_api::Mutex`], which covers `parking_lot::Mutex` and any other
+    //! mutex built on a [`RawMutex`].
+    //!
+    //! `lock_api` locks aren't poisoned, so acquiring one can't fail and
+    //! the error type is [`Infallible`]. Callers can destructure the result
+    //! with an irrefutable `let Ok(guard) = ...;` instead of unwrapping it.
+
+    use core::{convert::Infallible, time::Duration};
+
+    use lock_api::{
+        GetThreadId, MappedMutexGuard, Mutex, MutexGuard, RawMutex, RawMutexTimed, ReentrantMutex,
+        ReentrantMutexGuard,
+    };
+
+    use crate::lock::{ReentrantMutexLock, TimedOut, WouldBlock};
+
+    impl<R: RawMutex, T: ?Sized> super::MutexLock for Mutex<R, T> {
+        type Guard<'a> = MutexGuard<'a, R, T> where Self: 'a;
+        type Error<'a> = Infallible where Self: 'a;
+        type TryError<'a> = WouldBlock where Self: 'a;
+
+        fn lock(&self) -> Result<Self::Guard<'_>, Self::Error<'_>> {
+            Ok(Mutex::lock(self))
+        }
+
+        fn try_lock(&self) -> Result<Self::Guard<'_>, Self::TryError<'_>> {
+            Mutex::try_lock(self).ok_or(WouldBlock)
+        }
+    }
+
+    impl<R: RawMutexTimed<Duration = Duration>, T: ?Sized> super::MutexLockTimeout for Mutex<R, T> {
+        type TimeoutError<'a> = TimedOut where Self: 'a;
+
+        fn lock_timeout(
+            &self,
+            timeout: Duration,
+        ) -> Result<Self::Guard<'_>, Self::TimeoutError<'_>> {
+            Mutex::try_lock_for(self, timeout).ok_or(TimedOut)
+        }
+    }
+
+    impl<R: RawMutex, T> super::IntoInnerLock for Mutex<R, T> {
+        type Inner = T;
+
+        fn into_inner(self) -> T {
+            Mutex::into_inner(self)
+        }
+    }
+
+    impl<R: RawMutex, T: ?Sized> super::GetMutLock for Mutex<R, T> {
+        type Inner = T;
+
+        fn get_mut(&mut self) -> &mut T {
+            Mutex::get_mut(self)
+        }
+    }
+
+    impl<R: RawMutex, T: ?Sized> super::MutexLockData for Mutex<R, T> {
+        type Data = T;
+
+        fn data<'g>(guard: &'g mut Self::Guard<'_>) -> &'g mut T {
+            guard
+        }
+    }
+
+    impl<'a, R: RawMutex + 'a, T: ?Sized + 'a> super::MappableGuard<'a> for MutexGuard<'a, R, T> {
+        type Mapped<U: ?Sized + 'a> = MappedMutexGuard<'a, R, U>;
+
+        fn map<U: ?Sized + 'a>(self, f: impl FnOnce(&mut T) -> &mut U) -> Self::Mapped<U> {
+            MutexGuard::map(self, f)
+        }
+    }
+
+    impl<R: RawMutex, G: GetThreadId, T: ?Sized> super::MutexLock for ReentrantMutex<R, G, T> {
+        type Guard<'a> = ReentrantMutexGuard<'a, R, G, T> where Self: 'a;
+        type Error<'a> = Infallible where Self: 'a;
+        type TryError<'a> = WouldBlock where Self: 'a;
+
+        fn lock(&self) -> Result<Self::Guard<'_>, Self::Error<'_>> {
+            Ok(ReentrantMutex::lock(self))
+        }
+
+        fn try_lock(&self) -> Result<Self::Guard<'_>, Self::TryError<'_>> {
+            ReentrantMutex::try_lock(self).ok_or(WouldBlock)
+        }
+    }
+
+    impl<R: RawMutex, G: GetThreadId, T: ?Sized> ReentrantMutexLock for ReentrantMutex<R, G, T> {
+        fn is_owned_by_current_thread(&self) -> bool {
+            ReentrantMutex::is_owned_by_current_thread(self)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use core::sync::atomic::{AtomicBool, Ordering};
+
+        use lock_api::{GuardSend, Mutex, RawMutex};
+
+        use crate::{
+            lock::MutexLockLevel, relation::LockAfter, LockLevel, LockedAt, MutualExclusion,
+            Unlocked,
+        };
+
+        /// Minimal spinning [`RawMutex`] that isn't from any lock crate.
+        struct RawSpin(AtomicBool);
+
+        unsafe impl RawMutex for RawSpin {
+            #[allow(clippy::declare_interior_mutable_const)]
+            const INIT: Self = RawSpin(AtomicBool::new(false));
+            type GuardMarker = GuardSend;
+
+            fn lock(&self) {
+                while !self.try_lock() {
+                    core::hint::spin_loop();
+                }
+            }
+
+            fn try_lock(&self) -> bool {
+                self.0
+                    .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            }
+
+            unsafe fn unlock(&self) {
+                self.0.store(false, Ordering::Release);
+            }
+        }
+
+        struct Counter;
+
+        impl LockAfter<Unlocked> for Counter {}
+
+        impl LockLevel for Counter {
+            type Method = MutualExclusion;
+        }
+        impl MutexLockLevel for Counter {
+            type Mutex = Mutex<RawSpin, u32>;
+        }
+
+        struct Pair;
+
+        impl LockAfter<Unlocked> for Pair {}
+
+        impl LockLevel for Pair {
+            type Method = MutualExclusion;
+        }
+        impl MutexLockLevel for Pair {
+            type Mutex = Mutex<RawSpin, (u32, u32)>;
+        }
+
+        #[cfg(feature = "parking_lot")]
+        struct ParkingLotCounter;
+
+        #[cfg(feature = "parking_lot")]
+        impl LockAfter<Unlocked> for ParkingLotCounter {}
+
+        #[cfg(feature = "parking_lot")]
+        impl LockLevel for ParkingLotCounter {
+            type Method = MutualExclusion;
+        }
+        #[cfg(feature = "parking_lot")]
+        impl MutexLockLevel for ParkingLotCounter {
+            type Mutex = parking_lot::Mutex<u32>;
+        }
+
+        #[cfg(feature = "parking_lot")]
+        struct Reentrant;
+
+        #[cfg(feature = "parking_lot")]
+        impl LockAfter<Unlocked> for Reentrant {}
+
+        #[cfg(feature = "parking_lot")]
+        impl LockLevel for Reentrant {
+            type Method = MutualExclusion;
+        }
+        #[cfg(feature = "parking_lot")]
+        impl MutexLockLevel for Reentrant {
+            type Mutex = parking_lot::ReentrantMutex<core::cell::Cell<u32>>;
+        }
+
+        #[test]
+        fn custom_raw_mutex() {
+            let counter = Mutex::new(0);
+            let mut locked = LockedAt::new();
+
+            let Ok(mut guard) = locked.lock::<Counter>(&counter);
+            *guard += 1;
+            drop(guard);
+
+            assert_eq!(locked.into_value::<Counter>(counter), 1);
+        }
+
+        #[test]
+        #[cfg(feature = "parking_lot")]
+        fn lock_is_infallible() {
+            let counter = parking_lot::Mutex::new(0);
+            let mut locked = LockedAt::new();
+
+            let Ok(mut guard) = locked.lock::<ParkingLotCounter>(&counter);
+            *guard += 1;
+            drop(guard);
+
+            assert_eq!(locked.into_value::<ParkingLotCounter>(counter), 1);
+        }
+
+        #[test]
+        fn map_to_field() {
+            let pair = Mutex::<RawSpin, _>::new((1u32, 2u32));
+            let mut locked = LockedAt::new();
+
+            let Ok(mut second) = locked.lock_map::<Pair, _>(&pair, |(_, second)| second);
+            *second += 1;
+            drop(second);
+
+            assert_eq!(pair.into_inner(), (1, 3));
+        }
+
+        #[test]
+        #[cfg(feature = "parking_lot")]
+        fn reenter_held_level() {
+            let mutex = parking_lot::ReentrantMutex::new(core::cell::Cell::new(1));
+            {
+                let mut locked = LockedAt::new();
+                let (locked, outer) = locked.with_lock::<Reentrant>(&mutex).unwrap();
+                let Ok(inner) = locked.lock_reentrant(&mutex);
+                let Ok(innermost) = locked.lock_reentrant(&mutex);
+                inner.set(outer.get() + innermost.get());
+            }
+
+            assert_eq!(mutex.into_inner().get(), 2);
+        }
+
+        #[test]
+        #[cfg(feature = "parking_lot")]
+        #[should_panic(expected = "not held by the current thread")]
+        fn reenter_other_lock_panics() {
+            let held = parking_lot::ReentrantMutex::new(core::cell::Cell::new(1));
+            let other = parking_lot::ReentrantMutex::new(core::cell::Cell::new(2));
+            let mut locked = LockedAt::new();
+
+            let (locked, _guard) = locked.with_lock::<Reentrant>(&held).unwrap();
+            let _ = locked.lock_reentrant(&other);
+        }
+    }
+}
+
+#[cfg(feature = "loom")]
+mod loom {
+    //! Implementation of [`MutexLock`](super::MutexLock) for
+    //! [`loom::sync::Mutex`].
+    //!
+    //! This lets lock levels be backed by `loom`'s mutex so that code using
+    //! them can be model checked. The guard and error types mirror `std`'s.
+    //!
+    //! `loom` runs its simulated threads on a single OS thread, so the
+    //! per-thread bookkeeping of `debug_checks` can't tell them apart and
+    //! shouldn't be enabled in `loom` tests.
+
+    use std::sync::{PoisonError, TryLockError};
+
+    use loom::sync::{Mutex, MutexGuard};
+
+    impl<T> super::IntoInnerLock for Mutex<T> {
+        type Inner = T;
+
+        fn into_inner(self) -> T {
+            Mutex::into_inner(self).unwrap_or_else(PoisonError::into_inner)
+        }
+    }
+
+    impl<T: ?Sized> super::GetMutLock for Mutex<T> {
+        type Inner = T;
+
+        fn get_mut(&mut self) -> &mut T {
+            Mutex::get_mut(self).unwrap_or_else(PoisonError::into_inner)
+        }
+    }
+
+    impl<T: ?Sized> super::MutexLockData for Mutex<T> {
+        type Data = T;
+
+        fn data<'g>(guard: &'g mut Self::Guard<'_>) -> &'g mut T {
+            guard
+        }
+    }
+
+    impl<T: ?Sized> super::MutexLock for Mutex<T> {
+        type Guard<'a> = MutexGuard<'a, T> where Self: 'a;
+        type Error<'a> = PoisonError<MutexGuard<'a, T>> where Self: 'a;
+        type TryError<'a> = TryLockError<MutexGuard<'a, T>> where Self: 'a;
+
+        fn lock(&self) -> Result<Self::Guard<'_>, Self::Error<'_>> {
+            Mutex::lock(self)
+        }
+
+        fn try_lock(&self) -> Result<Self::Guard<'_>, Self::TryError<'_>> {
+            Mutex::try_lock(self)
+        }
+
+        fn try_error_would_block(error: &Self::TryError<'_>) -> bool {
+            matches!(error, TryLockError::WouldBlock)
+        }
+    }
+
+    #[cfg(all(test, not(feature = "debug_checks")))]
+    mod tests {
+        use loom::sync::{Arc, Mutex};
+
+        use crate::{
+            lock::MutexLockLevel, relation::LockAfter, LockLevel, LockedAt, MutualExclusion,
+            Unlocked,
+        };
+
+        struct First;
+        struct Second;
+
+        impl LockAfter<Unlocked> for First {}
+        impl LockAfter<First> for Second {}
+
+        impl LockLevel for First {
+            type Method = MutualExclusion;
+        }
+        impl MutexLockLevel for First {
+            type Mutex = Mutex<u32>;
+        }
+
+        impl LockLevel for Second {
+            type Method = MutualExclusion;
+        }
+        impl MutexLockLevel for Second {
+            type Mutex = Mutex<u32>;
+        }
+
+        #[test]
+        fn ordered_acquisitions_dont_deadlock() {
+            loom::model(|| {
+                let locks = Arc::new((Mutex::new(0), Mutex::new(0)));
+
+                let threads: Vec<_> = (0..2)
+                    .map(|_| {
+                        let locks = Arc::clone(&locks);
+                        loom::thread::spawn(move || {
+                            let (first, second) = &*locks;
+                            // SAFETY: each simulated thread has a single root.
+                            let mut locked = unsafe { LockedAt::new_unchecked() };
+                            let (mut locked, mut first) = locked.with_lock::<First>(first).unwrap();
+                            *first += 1;
+                            *locked.lock::<Second>(second).unwrap() += *first;
+                        })
+                    })
+                    .collect();
+                for thread in threads {
+                    thread.join().unwrap();
+                }
+
+                let (first, second) = &*locks;
+                assert_eq!(*first.lock().unwrap(), 2);
+                assert_eq!(*second.lock().unwrap(), 3);
+            });
+        }
+    }
+}
+
+#[cfg(feature = "spin")]
+mod spin {
+    //! Implementation of [`MutexLock`](super::MutexLock) for [`spin::Mutex`].
+    //!
+    //! Like `parking_lot` locks, spinlocks aren't poisoned, so acquiring one
+    //! can't fail. This doesn't depend on `std` and is available in `no_std`
+    //! builds.
+
+    use core::convert::Infallible;
+
+    use spin::{Mutex, MutexGuard};
+
+    use crate::lock::WouldBlock;
+
+    impl<T: ?Sized> super::MutexLock for Mutex<T> {
+        type Guard<'a> = MutexGuard<'a, T> where Self: 'a;
+        type Error<'a> = Infallible where Self: 'a;
+        type TryError<'a> = WouldBlock where Self: 'a;
+
+        fn lock(&self) -> Result<Self::Guard<'_>, Self::Error<'_>> {
+            Ok(Mutex::lock(self))
+        }
+
+        fn try_lock(&self) -> Result<Self::Guard<'_>, Self::TryError<'_>> {
+            Mutex::try_lock(self).ok_or(WouldBlock)
+        }
+    }
+
+    impl<T> super::IntoInnerLock for Mutex<T> {
+        type Inner = T;
+
+        fn into_inner(self) -> T {
+            Mutex::into_inner(self)
+        }
+    }
+
+    impl<T: ?Sized> super::GetMutLock for Mutex<T> {
+        type Inner = T;
+
+        fn get_mut(&mut self) -> &mut T {
+            Mutex::get_mut(self)
+        }
+    }
+
+    impl<T: ?Sized> super::MutexLockData for Mutex<T> {
+        type Data = T;
+
+        fn data<'g>(guard: &'g mut Self::Guard<'_>) -> &'g mut T {
+            guard
+        }
+    }
+}
+
+#[cfg(feature = "critical-section")]
+pub use self::critical_section::CriticalSectionGuard;
+
+#[cfg(feature = "critical-section")]
+mod critical_section {
+    //! Implementation of [`MutexLock`](super::MutexLock) for
+    //! [`critical_section::Mutex`] wrapping a [`RefCell`].
+    //!
+    //! Locking enters a critical section and mutably borrows the `RefCell`;
+    //! the critical section is left once every guard has been dropped. Guards
+    //! can be dropped in any order since the outermost critical section is
+    //! tracked across all of them. They must not outlive an enclosing
+    //! [`critical_section::with`] closure, though, since that would leave the
+    //! critical section while they still hold the borrow.
+
+    use core::{
+        cell::{BorrowMutError, Cell, RefCell, RefMut},
+        ops::{Deref, DerefMut},
+    };
+
+    use critical_section::{CriticalSection, Mutex, RestoreState};
+
+    /// Number of live guards and the state to restore when the last one is
+    /// dropped.
+    static NESTING: Mutex<Cell<(usize, RestoreState)>> =
+        Mutex::new(Cell::new((0, RestoreState::invalid())));
+
+    /// Keeps the current thread in a critical section while alive.
+    struct Section(());
+
+    impl Section {
+        fn enter() -> Self {
+            // SAFETY: the matching release happens either below, for a nested
+            // acquisition, or when the last `Section` is dropped on this
+            // thread. `Section` isn't `Send` since it's only held by
+            // `CriticalSectionGuard`, which contains a `RefMut`.
+            let restore = unsafe { critical_section::acquire() };
+            let nesting = NESTING.borrow(Self::token());
+            match nesting.get() {
+                (0, _) => nesting.set((1, restore)),
+                (depth, outer) => {
+                    nesting.set((depth + 1, outer));
+                    // SAFETY: `restore` came from the acquisition just above,
+                    // which is the most recent one.
+                    unsafe { critical_section::release(restore) };
+                }
+            }
+            Self(())
+        }
+
+        fn token<'cs>() -> CriticalSection<'cs> {
+            // SAFETY: only called while a `Section` is (being) entered.
+            unsafe { CriticalSection::new() }
+        }
+    }
+
+    impl Drop for Section {
+        fn drop(&mut self) {
+            let nesting = NESTING.borrow(Self::token());
+            let (depth, outer) = nesting.get();
+            nesting.set((depth - 1, outer));
+            if depth == 1 {
+                // SAFETY: this is the last live `Section`, and `outer` came
+                // from the acquisition made when the first one was entered.
+                unsafe { critical_section::release(outer) };
+            }
+        }
+    }
+
+    /// Guard for a [`critical_section::Mutex<RefCell<T>>`] locked through
+    /// [`MutexLock`](super::MutexLock).
+    ///
+    /// Holds the `RefCell` borrow and keeps the current thread in a critical
+    /// section until dropped.
+    pub struct CriticalSectionGuard<'a, T> {
+        // Declared first so the borrow is released before the section.
+        borrow: RefMut<'a, T>,
+        _section: Section,
+    }
+
+    impl<T> Deref for CriticalSectionGuard<'_, T> {
+        type Target = T;
+
+        fn deref(&self) -> &T {
+            &self.borrow
+        }
+    }
+
+    impl<T> DerefMut for CriticalSectionGuard<'_, T> {
+        fn deref_mut(&mut self) -> &mut T {
+            &mut self.borrow
+        }
+    }
+
+    impl<T> super::MutexLock for Mutex<RefCell<T>> {
+        type Guard<'a> = CriticalSectionGuard<'a, T> where Self: 'a;
+        type Error<'a> = BorrowMutError where Self: 'a;
+        type TryError<'a> = BorrowMutError where Self: 'a;
+
+        fn lock(&self) -> Result<Self::Guard<'_>, Self::Error<'_>> {
+            let section = Section::enter();
+            let borrow = self.borrow(Section::token()).try_borrow_mut()?;
+            Ok(CriticalSectionGuard {
+                borrow,
+                _section: section,
+            })
+        }
+
+        fn try_lock(&self) -> Result<Self::Guard<'_>, Self::TryError<'_>> {
+            self.lock()
+        }
+    }
+
+    impl<T> super::IntoInnerLock for Mutex<RefCell<T>> {
+        type Inner = T;
+
+        fn into_inner(self) -> T {
+            Mutex::into_inner(self).into_inner()
+        }
+    }
+
+    impl<T> super::GetMutLock for Mutex<RefCell<T>> {
+        type Inner = T;
+
+        fn get_mut(&mut self) -> &mut T {
+            Mutex::get_mut(self).get_mut()
+        }
+    }
+
+    impl<T> super::MutexLockData for Mutex<RefCell<T>> {
+        type Data = T;
+
+        fn data<'g>(guard: &'g mut Self::Guard<'_>) -> &'g mut T {
+            guard
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use core::cell::RefCell;
+
+        use crate::{
+            lock::{MutexLock, MutexLockLevel},
+            relation::LockAfter,
+            LockLevel, LockedAt, MutualExclusion, Unlocked,
+        };
+
+        use super::{Mutex, NESTING};
+
+        struct Peripheral;
+        struct Buffer;
+
+        impl LockAfter<Unlocked> for Peripheral {}
+        impl LockAfter<Peripheral> for Buffer {}
+
+        impl LockLevel for Peripheral {
+            type Method = MutualExclusion;
+        }
+        impl MutexLockLevel for Peripheral {
+            type Mutex = Mutex<RefCell<u8>>;
+        }
+
+        impl LockLevel for Buffer {
+            type Method = MutualExclusion;
+        }
+        impl MutexLockLevel for Buffer {
+            type Mutex = Mutex<RefCell<[u8; 4]>>;
+        }
+
+        fn depth() -> usize {
+            critical_section::with(|cs| NESTING.borrow(cs).get().0)
+        }
+
+        #[test]
+        fn section_spans_all_guards() {
+            let peripheral = Mutex::new(RefCell::new(7));
+            let buffer = Mutex::new(RefCell::new([0; 4]));
+            let mut locked = LockedAt::new();
+
+            let (mut locked, peripheral_guard) =
+                locked.with_lock::<Peripheral>(&peripheral).unwrap();
+            let mut buffer_guard = locked.lock::<Buffer>(&buffer).unwrap();
+            buffer_guard[0] = *peripheral_guard;
+            assert!(MutexLock::lock(&peripheral).is_err());
+
+            // Out-of-order release keeps the section until the last guard.
+            drop(peripheral_guard);
+            assert_eq!(depth(), 1);
+            drop(buffer_guard);
+            assert_eq!(depth(), 0);
+
+            assert_eq!(locked.into_value::<Buffer>(buffer), [7, 0, 0, 0]);
+        }
+    }
+}
+
+#[cfg(feature = "refcell")]
+mod refcell {
+    //! Implementation of [`MutexLock`](super::MutexLock) for
+    //! [`core::cell::RefCell`].
+    //!
+    //! This brings the ordering discipline to single-threaded code, where a
+    //! `RefCell` borrow plays the role of a lock. Acquisition fails with a
+    //! [`BorrowMutError`] instead of blocking if the cell is already borrowed.
+
+    use core::cell::{BorrowMutError, RefCell, RefMut};
+
+    impl<T: ?Sized> super::MutexLock for RefCell<T> {
+        type Guard<'a> = RefMut<'a, T> where Self: 'a;
+        type Error<'a> = BorrowMutError where Self: 'a;
+        type TryError<'a> = BorrowMutError where Self: 'a;
+
+        fn lock(&self) -> Result<Self::Guard<'_>, Self::Error<'_>> {
+            RefCell::try_borrow_mut(self)
+        }
+
+        fn try_lock(&self) -> Result<Self::Guard<'_>, Self::TryError<'_>> {
+            RefCell::try_borrow_mut(self)
+        }
+    }
+
+    impl<T> super::IntoInnerLock for RefCell<T> {
+        type Inner = T;
+
+        fn into_inner(self) -> T {
+            RefCell::into_inner(self)
+        }
+    }
+
+    impl<T: ?Sized> super::GetMutLock for RefCell<T> {
+        type Inner = T;
+
+        fn get_mut(&mut self) -> &mut T {
+            RefCell::get_mut(self)
+        }
+    }
+
+    impl<T: ?Sized> super::MutexLockData for RefCell<T> {
+        type Data = T;
+
+        fn data<'g>(guard: &'g mut Self::Guard<'_>) -> &'g mut T {
+            guard
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::{
+            lock::MutexLockLevel, relation::LockAfter, LockLevel, LockedAt, MutualExclusion,
+            Unlocked,
+        };
+
+        use super::RefCell;
+
+        struct Outer;
+        struct Inner;
+
+        impl LockAfter<Unlocked> for Outer {}
+        impl LockAfter<Outer> for Inner {}
+
+        impl LockLevel for Outer {
+            type Method = MutualExclusion;
+        }
+        impl MutexLockLevel for Outer {
+            type Mutex = RefCell<Vec<u8>>;
+        }
+
+        impl LockLevel for Inner {
+            type Method = MutualExclusion;
+        }
+        impl MutexLockLevel for Inner {
+            type Mutex = RefCell<Vec<u8>>;
+        }
+
+        #[test]
+        fn nested_borrows() {
+            let outer = RefCell::new(vec![1]);
+            let inner = RefCell::new(vec![2]);
+            let mut locked = LockedAt::new();
+
+            {
+                let (mut locked, mut outer_guard) = locked.with_lock::<Outer>(&outer).unwrap();
+                let mut inner_guard = locked.lock::<Inner>(&inner).unwrap();
+                inner_guard.append(&mut outer_guard);
+            }
+
+            assert!(outer.into_inner().is_empty());
+            assert_eq!(inner.into_inner(), [2, 1]);
+        }
+
+        #[test]
+        fn borrowed_cell_fails() {
+            // The same cell is reachable at two different levels.
+            let cell = RefCell::new(vec![]);
+            let mut locked = LockedAt::new();
+
+            let (mut locked, _outer_guard) = locked.with_lock::<Outer>(&cell).unwrap();
+            assert!(locked.lock::<Inner>(&cell).is_err());
+        }
+
+        #[test]
+        fn generic_data_access() {
+            fn push<M: crate::lock::MutexLockData<Data = Vec<u8>>>(
+                guard: &mut M::Guard<'_>,
+                value: u8,
+            ) {
+                M::data(guard).push(value);
+            }
+
+            let cell = RefCell::new(vec![]);
+            let mut locked = LockedAt::new();
+
+            let mut guard = locked.lock::<Outer>(&cell).unwrap();
+            push::<RefCell<Vec<u8>>>(crate::lockedat::backend_guard_mut(&mut guard), 3);
+            drop(guard);
+            assert_eq!(cell.into_inner(), [3]);
+        }
     }
 }
 
 #[cfg(feature = "async")]
+#[allow(async_fn_in_trait)]
 pub trait AsyncMutexLock {
     /// [RAII guard] for accessing data protected by the lock.
     ///
@@ -62,10 +982,75 @@ pub trait AsyncMutexLock {
     async fn lock(&self) -> Self::Guard<'_>;
 }
 
+/// An [`AsyncMutexLock`] that can also be acquired without waiting.
+///
+/// Not every asynchronous lock supports non-blocking attempts, so this is
+/// separate from [`AsyncMutexLock`]. See
+/// [`LockedAt::try_wait_for_lock`](crate::LockedAt::try_wait_for_lock).
+#[cfg(feature = "async")]
+pub trait NonBlockingAsyncMutexLock: AsyncMutexLock {
+    /// Attempts to acquire exclusive access to data without waiting.
+    ///
+    /// Returns `None` if the lock is currently held.
+    fn try_lock(&self) -> Option<Self::Guard<'_>>;
+}
+
+/// Asynchronous locking implementation that can fail.
+///
+/// Like [`AsyncMutexLock`], but for locks where acquisition can produce an
+/// error, like a lock backed by a remote service. Infallible locks should
+/// implement [`AsyncMutexLock`] instead.
+#[cfg(feature = "async")]
+#[allow(async_fn_in_trait)]
+pub trait TryAsyncMutexLock {
+    /// Error that could be produced when acquiring the lock.
+    type Error<'a>
+    where
+        Self: 'a;
+
+    /// [RAII guard] for accessing data protected by the lock.
+    ///
+    /// [RAII guard]: https://doc.rust-lang.org/rust-by-example/scope/raii.html
+    type Guard<'a>
+    where
+        Self: 'a;
+
+    /// Attempts to acquire exclusive access to data.
+    ///
+    /// Causes the current task to yield until the lock has been acquired or
+    /// acquisition has failed. Returns an RAII guard that allows access to
+    /// the locked state, or an error on failure.
+    async fn lock(&self) -> Result<Self::Guard<'_>, Self::Error<'_>>;
+}
+
+/// An [`AsyncMutexLock`] that can be locked through an [`Arc`], producing a
+/// guard that doesn't borrow the lock.
+///
+/// See [`LockedAt::wait_for_lock_owned`](crate::LockedAt::wait_for_lock_owned).
+///
+/// [`Arc`]: ::std::sync::Arc
+#[cfg(all(feature = "async", feature = "std"))]
+#[allow(async_fn_in_trait)]
+pub trait OwnedMutexLock: AsyncMutexLock {
+    /// [RAII guard] that keeps the lock alive and held.
+    ///
+    /// [RAII guard]: https://doc.rust-lang.org/rust-by-example/scope/raii.html
+    type OwnedGuard;
+
+    /// Acquires exclusive access to data, holding a reference to the lock in
+    /// the returned guard.
+    async fn lock_owned(self: ::std::sync::Arc<Self>) -> Self::OwnedGuard;
+}
+
 #[cfg(feature = "tokio")]
 mod tokio {
     //! Implementation of lock traits for [`tokio::sync::Mutex`].
 
+    #[cfg(feature = "std")]
+    use std::sync::Arc;
+
+    #[cfg(feature = "std")]
+    use tokio::sync::OwnedMutexGuard;
     use tokio::sync::{Mutex, MutexGuard};
 
     impl<T: ?Sized> super::AsyncMutexLock for Mutex<T> {
@@ -77,4 +1062,141 @@ mod tokio {
             Mutex::lock(self).await
         }
     }
+
+    impl<T: ?Sized> super::NonBlockingAsyncMutexLock for Mutex<T> {
+        fn try_lock(&self) -> Option<Self::Guard<'_>> {
+            Mutex::try_lock(self).ok()
+        }
+    }
+
+    #[cfg(feature = "std")]
+    impl<T: ?Sized> super::OwnedMutexLock for Mutex<T> {
+        type OwnedGuard = OwnedMutexGuard<T>;
+
+        async fn lock_owned(self: Arc<Self>) -> Self::OwnedGuard {
+            Mutex::lock_owned(self).await
+        }
+    }
+}
+
+#[cfg(feature = "async-lock")]
+mod async_lock {
+    //! Implementation of lock traits for [`async_lock::Mutex`].
+    //!
+    //! `async_std::sync::Mutex` is a re-export of the same type, so this
+    //! also covers `async-std`.
+
+    use async_lock::{Mutex, MutexGuard};
+
+    impl<T: ?Sized> super::AsyncMutexLock for Mutex<T> {
+        type Guard<'a> = MutexGuard<'a, T>
+        where
+            Self: 'a;
+
+        async fn lock(&self) -> Self::Guard<'_> {
+            Mutex::lock(self).await
+        }
+    }
+
+    impl<T: ?Sized> super::NonBlockingAsyncMutexLock for Mutex<T> {
+        fn try_lock(&self) -> Option<Self::Guard<'_>> {
+            Mutex::try_lock(self)
+        }
+    }
+}
+
+#[cfg(feature = "embassy-sync")]
+mod embassy_sync {
+    //! Implementation of lock traits for [`embassy_sync::mutex::Mutex`].
+    //!
+    //! This doesn't need `std`, so levels on embedded targets can use
+    //! [`AsyncMutexLockLevel`](crate::lock::AsyncMutexLockLevel).
+
+    use embassy_sync::{
+        blocking_mutex::raw::RawMutex,
+        mutex::{Mutex, MutexGuard},
+    };
+
+    impl<M: RawMutex, T: ?Sized> super::AsyncMutexLock for Mutex<M, T> {
+        type Guard<'a> = MutexGuard<'a, M, T>
+        where
+            Self: 'a;
+
+        async fn lock(&self) -> Self::Guard<'_> {
+            Mutex::lock(self).await
+        }
+    }
+
+    impl<M: RawMutex, T: ?Sized> super::NonBlockingAsyncMutexLock for Mutex<M, T> {
+        fn try_lock(&self) -> Option<Self::Guard<'_>> {
+            Mutex::try_lock(self).ok()
+        }
+    }
+}
+
+#[cfg(feature = "futures")]
+mod futures {
+    //! Implementation of lock traits for [`futures::lock::Mutex`].
+
+    use futures::lock::{Mutex, MutexGuard};
+
+    impl<T: ?Sized> super::AsyncMutexLock for Mutex<T> {
+        type Guard<'a> = MutexGuard<'a, T>
+        where
+            Self: 'a;
+
+        async fn lock(&self) -> Self::Guard<'_> {
+            Mutex::lock(self).await
+        }
+    }
+
+    impl<T: ?Sized> super::NonBlockingAsyncMutexLock for Mutex<T> {
+        fn try_lock(&self) -> Option<Self::Guard<'_>> {
+            Mutex::try_lock(self)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::{
+            lock::AsyncMutexLockLevel, relation::LockAfter, LockLevel, LockedAt, MutualExclusion,
+            Unlocked,
+        };
+
+        use super::Mutex;
+
+        struct Outer;
+        struct Inner;
+
+        impl LockAfter<Unlocked> for Outer {}
+        impl LockAfter<Outer> for Inner {}
+
+        impl LockLevel for Outer {
+            type Method = MutualExclusion;
+        }
+        impl AsyncMutexLockLevel for Outer {
+            type Mutex = Mutex<Vec<u8>>;
+        }
+
+        impl LockLevel for Inner {
+            type Method = MutualExclusion;
+        }
+        impl AsyncMutexLockLevel for Inner {
+            type Mutex = Mutex<u8>;
+        }
+
+        #[test]
+        fn wait_for_futures_mutex() {
+            let outer = Mutex::new(Vec::new());
+            let inner = Mutex::new(3);
+
+            futures::executor::block_on(async {
+                let mut locked = LockedAt::new();
+                let (mut locked, mut outer) = locked.wait_for_lock::<Outer>(&outer).await;
+                outer.push(*locked.wait_lock::<Inner>(&inner).await);
+            });
+
+            assert_eq!(*outer.try_lock().unwrap(), [3]);
+        }
+    }
 }
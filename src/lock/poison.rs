@@ -0,0 +1,176 @@
+//! Adapter that recovers from lock poisoning.
+
+use core::convert::Infallible;
+use std::sync::{
+    Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError,
+};
+
+use super::WouldBlock;
+
+/// A `std` lock whose acquisitions ignore poisoning.
+///
+/// `std` locks are poisoned when a thread panics while holding them, and
+/// acquiring a poisoned lock returns an error. `IgnorePoison` implements
+/// [`MutexLock`](super::MutexLock) and [`RwLock`](super::RwLock) by
+/// recovering the guard from the [`PoisonError`] instead, so acquisitions
+/// can't fail and the error type is [`Infallible`]. Using it as the lock type
+/// for a level lets callers write `let Ok(guard) = locked.lock::<Level>(..);`
+/// instead of unwrapping.
+///
+/// This deliberately discards the poison state: the guarded data is handed
+/// out even if a panicking thread left it half-updated. Only use it for data
+/// that stays valid across a panic, or where a panic elsewhere is treated as
+/// fatal anyway.
+#[derive(Debug, Default)]
+pub struct IgnorePoison<M: ?Sized>(M);
+
+impl<M> IgnorePoison<M> {
+    /// Wraps `lock`.
+    pub const fn new(lock: M) -> Self {
+        Self(lock)
+    }
+
+    /// Unwraps the inner lock.
+    pub fn into_inner(self) -> M {
+        self.0
+    }
+}
+
+impl<M: ?Sized> IgnorePoison<M> {
+    /// Returns the inner lock, which still reports poisoning.
+    pub fn get_ref(&self) -> &M {
+        &self.0
+    }
+}
+
+impl<M> From<M> for IgnorePoison<M> {
+    fn from(lock: M) -> Self {
+        Self::new(lock)
+    }
+}
+
+/// Recovers the guard from a failed non-blocking acquisition, if it failed
+/// only because of poisoning.
+fn recover<G>(e: TryLockError<G>) -> Result<G, WouldBlock> {
+    match e {
+        TryLockError::Poisoned(e) => Ok(e.into_inner()),
+        TryLockError::WouldBlock => Err(WouldBlock),
+    }
+}
+
+impl<T: ?Sized> super::MutexLock for IgnorePoison<Mutex<T>> {
+    type Guard<'a> = MutexGuard<'a, T> where Self: 'a;
+    type Error<'a> = Infallible where Self: 'a;
+    type TryError<'a> = WouldBlock where Self: 'a;
+
+    fn lock(&self) -> Result<Self::Guard<'_>, Self::Error<'_>> {
+        Ok(self.0.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    fn try_lock(&self) -> Result<Self::Guard<'_>, Self::TryError<'_>> {
+        self.0.try_lock().or_else(recover)
+    }
+}
+
+impl<T> super::IntoInnerLock for IgnorePoison<Mutex<T>> {
+    type Inner = T;
+
+    fn into_inner(self) -> T {
+        self.0.into_inner().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T: ?Sized> super::GetMutLock for IgnorePoison<Mutex<T>> {
+    type Inner = T;
+
+    fn get_mut(&mut self) -> &mut T {
+        self.0.get_mut().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T: ?Sized> super::MutexLockData for IgnorePoison<Mutex<T>> {
+    type Data = T;
+
+    fn data<'g>(guard: &'g mut Self::Guard<'_>) -> &'g mut T {
+        guard
+    }
+}
+
+impl<T: ?Sized> super::RwLock for IgnorePoison<RwLock<T>> {
+    type ReadError<'a> = Infallible where Self: 'a;
+    type WriteError<'a> = Infallible where Self: 'a;
+    type TryReadError<'a> = WouldBlock where Self: 'a;
+    type TryWriteError<'a> = WouldBlock where Self: 'a;
+
+    type ReadGuard<'a> = RwLockReadGuard<'a, T> where Self: 'a;
+    type WriteGuard<'a> = RwLockWriteGuard<'a, T> where Self: 'a;
+
+    fn read(&self) -> Result<Self::ReadGuard<'_>, Self::ReadError<'_>> {
+        Ok(self.0.read().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    fn write(&self) -> Result<Self::WriteGuard<'_>, Self::WriteError<'_>> {
+        Ok(self.0.write().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    fn try_read(&self) -> Result<Self::ReadGuard<'_>, Self::TryReadError<'_>> {
+        self.0.try_read().or_else(recover)
+    }
+
+    fn try_write(&self) -> Result<Self::WriteGuard<'_>, Self::TryWriteError<'_>> {
+        self.0.try_write().or_else(recover)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Mutex, RwLock};
+
+    use crate::{
+        lock::{MutexLockLevel, RwLockLevel},
+        relation::LockAfter,
+        LockLevel, LockedAt, MutualExclusion, ReadWrite, Unlocked,
+    };
+
+    use super::IgnorePoison;
+
+    struct Config;
+    struct Counter;
+
+    impl LockAfter<Unlocked> for Config {}
+    impl LockAfter<Config> for Counter {}
+
+    impl LockLevel for Config {
+        type Method = ReadWrite;
+    }
+    impl RwLockLevel for Config {
+        type RwLock = IgnorePoison<RwLock<u32>>;
+    }
+
+    impl LockLevel for Counter {
+        type Method = MutualExclusion;
+    }
+    impl MutexLockLevel for Counter {
+        type Mutex = IgnorePoison<Mutex<u32>>;
+    }
+
+    #[test]
+    fn poisoned_locks_are_recovered() {
+        let config = IgnorePoison::new(RwLock::new(2));
+        let counter = IgnorePoison::new(Mutex::new(0));
+        let _ = std::panic::catch_unwind(|| {
+            let _config = config.get_ref().write().unwrap();
+            let _counter = counter.get_ref().lock().unwrap();
+            panic!("poisoning the locks");
+        });
+        assert!(counter.get_ref().is_poisoned());
+
+        let mut locked = LockedAt::new();
+        let Ok((mut locked, config)) = locked.with_read_lock::<Config>(&config);
+        let Ok(mut counter_guard) = locked.lock::<Counter>(&counter);
+        *counter_guard += *config;
+        drop((counter_guard, config));
+
+        assert_eq!(locked.into_value::<Counter>(counter), 2);
+    }
+}
@@ -1,3 +1,7 @@
+use core::ops::Deref;
+
+use crate::LevelGuard;
+
 /// Locking implementation for [crate::ReadWrite].
 ///
 /// Describes how to acquire access to the state for a [crate::LockLevel]
@@ -34,6 +38,26 @@ pub trait RwLock {
     where
         Self: 'a;
 
+    /// Error that could be produced when trying to acquire read access
+    /// without blocking.
+    ///
+    /// This should distinguish the lock being held for writing from other
+    /// failures. For implementations with no other failure modes,
+    /// [`WouldBlock`](super::WouldBlock) can be used.
+    type TryReadError<'a>
+    where
+        Self: 'a;
+
+    /// Error that could be produced when trying to acquire write access
+    /// without blocking.
+    ///
+    /// This should distinguish the lock being held from other failures. For
+    /// implementations with no other failure modes,
+    /// [`WouldBlock`](super::WouldBlock) can be used.
+    type TryWriteError<'a>
+    where
+        Self: 'a;
+
     /// Attempts to acquire shared access to data.
     ///
     /// Returns an RAII guard that provides shared (read) access to the data, or
@@ -45,17 +69,174 @@ pub trait RwLock {
     /// Returns an RAII guard that provides exclusive (read/write) access to the
     /// data, or an error on failure.
     fn write(&self) -> Result<Self::WriteGuard<'_>, Self::WriteError<'_>>;
+
+    /// Attempts to acquire shared access to data without blocking.
+    ///
+    /// Returns an RAII guard that provides shared (read) access to the data,
+    /// or an error if the lock is currently held for writing or acquisition
+    /// otherwise failed.
+    fn try_read(&self) -> Result<Self::ReadGuard<'_>, Self::TryReadError<'_>>;
+
+    /// Attempts to acquire exclusive access to data without blocking.
+    ///
+    /// Returns an RAII guard that provides exclusive (read/write) access to
+    /// the data, or an error if the lock is currently held or acquisition
+    /// otherwise failed.
+    fn try_write(&self) -> Result<Self::WriteGuard<'_>, Self::TryWriteError<'_>>;
+}
+
+/// An [`RwLock`] that supports recursive read locking.
+///
+/// With most reader-writer locks, a thread that already holds a read lock can
+/// deadlock by requesting another one: if a writer is queued in between, the
+/// second read waits for the writer, which waits for the first read to be
+/// released. Implementations of this trait guarantee that
+/// [`read_recursive`](RecursiveReadRwLock::read_recursive) doesn't wait for
+/// queued writers, so it can't deadlock this way.
+///
+/// See [`LockedAt::read_recursive`](crate::LockedAt::read_recursive).
+pub trait RecursiveReadRwLock: RwLock {
+    /// Attempts to acquire shared access to data, even if the current thread
+    /// already holds shared access to this or another lock at the same level.
+    fn read_recursive(&self) -> Result<Self::ReadGuard<'_>, Self::ReadError<'_>>;
+}
+
+/// An [`RwLock`] that supports upgradable reads.
+///
+/// An upgradable read lock allows shared access alongside ordinary readers,
+/// but at most one upgradable reader can exist at a time. That makes it
+/// possible to upgrade it to exclusive access in place, without releasing the
+/// lock in between.
+///
+/// See [`LockedAt::upgradable_read_lock`](crate::LockedAt::upgradable_read_lock).
+pub trait UpgradableRwLock: RwLock {
+    /// [RAII guard] for upgradable shared access to data protected by the
+    /// lock.
+    ///
+    /// [RAII guard]: https://doc.rust-lang.org/rust-by-example/scope/raii.html
+    type UpgradableGuard<'a>
+    where
+        Self: 'a;
+
+    /// Attempts to acquire upgradable shared access to data.
+    fn upgradable_read(&self) -> Result<Self::UpgradableGuard<'_>, Self::ReadError<'_>>;
+
+    /// Upgrades an upgradable guard to exclusive access.
+    ///
+    /// Waits for the other readers to release the lock.
+    fn upgrade(guard: Self::UpgradableGuard<'_>) -> Self::WriteGuard<'_>;
+}
+
+/// Guard for upgradable shared access to an [`UpgradableRwLock`].
+///
+/// Produced by
+/// [`LockedAt::upgradable_read_lock`](crate::LockedAt::upgradable_read_lock).
+/// Dereferences to the locked data when the wrapped guard does.
+pub struct UpgradableReadGuard<'a, R: UpgradableRwLock + ?Sized + 'a>(
+    LevelGuard<R::UpgradableGuard<'a>>,
+);
+
+impl<'a, R: UpgradableRwLock + ?Sized> UpgradableReadGuard<'a, R> {
+    pub(crate) fn new(guard: LevelGuard<R::UpgradableGuard<'a>>) -> Self {
+        Self(guard)
+    }
+
+    /// Upgrades to exclusive access without releasing the lock.
+    ///
+    /// The lock stays at the same level, so the `LockedAt` produced alongside
+    /// this guard remains valid for the write guard too.
+    pub fn upgrade(self) -> LevelGuard<R::WriteGuard<'a>> {
+        crate::lockedat::map_level_guard(self.0, R::upgrade)
+    }
+}
+
+impl<'a, R: UpgradableRwLock + ?Sized> Deref for UpgradableReadGuard<'a, R>
+where
+    R::UpgradableGuard<'a>: Deref,
+{
+    type Target = <R::UpgradableGuard<'a> as Deref>::Target;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// An [`RwLock`] whose write guards can be downgraded to read guards.
+///
+/// Downgrading atomically turns exclusive access into shared access, so no
+/// writer can acquire the lock in between.
+///
+/// See [`LockedAt::downgrade`](crate::LockedAt::downgrade).
+pub trait DowngradableRwLock: RwLock {
+    /// Downgrades a write guard to a read guard without releasing the lock.
+    fn downgrade(guard: Self::WriteGuard<'_>) -> Self::ReadGuard<'_>;
+}
+
+/// Guard for either shared or exclusive access to an [`RwLock`].
+///
+/// Produced by [`LockedAt::rw_lock`](crate::LockedAt::rw_lock), where the
+/// variant is selected at compile time. Dereferences to the locked data when
+/// both guard types do.
+pub enum RwGuard<R, W> {
+    /// A guard for shared access.
+    Read(R),
+    /// A guard for exclusive access.
+    Write(W),
+}
+
+/// Either a held guard or an owned copy of the locked data.
+///
+/// Produced by [`LockedAt::read_cow`](crate::LockedAt::read_cow). Dereferences
+/// to the data in both cases; only the `Borrowed` variant keeps the lock held.
+pub enum CowGuard<G, T> {
+    /// The guard, which keeps the lock held.
+    Borrowed(G),
+    /// A copy of the data, made before the lock was released.
+    Owned(T),
+}
+
+impl<T, G: Deref<Target = T>> Deref for CowGuard<G, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match self {
+            CowGuard::Borrowed(g) => g,
+            CowGuard::Owned(t) => t,
+        }
+    }
+}
+
+/// Error from acquiring either shared or exclusive access to an [`RwLock`].
+#[derive(Debug)]
+pub enum RwError<R, W> {
+    /// Acquiring shared access failed.
+    Read(R),
+    /// Acquiring exclusive access failed.
+    Write(W),
+}
+
+impl<T: ?Sized, R: Deref<Target = T>, W: Deref<Target = T>> Deref for RwGuard<R, W> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match self {
+            RwGuard::Read(r) => r,
+            RwGuard::Write(w) => w,
+        }
+    }
 }
 
 #[cfg(feature = "std")]
 mod std {
     //! Implementation of [`RwLock`] for [`std::sync::RwLock`].
     //!
-    use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+    use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError};
 
     impl<T: ?Sized> super::RwLock for RwLock<T> {
         type ReadError<'a> = PoisonError<RwLockReadGuard<'a, T>> where Self: 'a ;
         type WriteError<'a> = PoisonError<RwLockWriteGuard<'a, T>> where Self: 'a;
+        type TryReadError<'a> = TryLockError<RwLockReadGuard<'a, T>> where Self: 'a;
+        type TryWriteError<'a> = TryLockError<RwLockWriteGuard<'a, T>> where Self: 'a;
 
         type ReadGuard<'a> = RwLockReadGuard<'a, T> where Self: 'a ;
         type WriteGuard<'a> = RwLockWriteGuard<'a, T> where Self: 'a;
@@ -67,6 +248,420 @@ mod std {
         fn write(&self) -> Result<Self::WriteGuard<'_>, Self::WriteError<'_>> {
             RwLock::write(self)
         }
+
+        fn try_read(&self) -> Result<Self::ReadGuard<'_>, Self::TryReadError<'_>> {
+            RwLock::try_read(self)
+        }
+
+        fn try_write(&self) -> Result<Self::WriteGuard<'_>, Self::TryWriteError<'_>> {
+            RwLock::try_write(self)
+        }
+    }
+}
+
+#[cfg(feature = "lock-api")]
+mod lock_api {
+    //! Implementation of [`RwLock`](super::RwLock) for [`lock_api::RwLock`],
+    //! which covers `parking_lot::RwLock` and any other reader-writer lock
+    //! built on a [`RawRwLock`].
+    //!
+    //! With the `debug_checks` feature enabled, read locks are checked for
+    //! same-thread recursion, which `lock_api` locks generally don't support
+    //! and which can deadlock if a writer is waiting.
+
+    use core::convert::Infallible;
+
+    use lock_api::{
+        RawRwLock, RawRwLockDowngrade, RawRwLockRecursive, RawRwLockUpgrade, RwLock,
+        RwLockReadGuard, RwLockUpgradableReadGuard, RwLockWriteGuard,
+    };
+
+    use super::{DowngradableRwLock, RecursiveReadRwLock, UpgradableRwLock};
+    use crate::lock::WouldBlock;
+
+    impl<R: RawRwLock, T: ?Sized> super::RwLock for RwLock<R, T> {
+        type ReadError<'a> = Infallible where Self: 'a;
+        type WriteError<'a> = Infallible where Self: 'a;
+        type TryReadError<'a> = WouldBlock where Self: 'a;
+        type TryWriteError<'a> = WouldBlock where Self: 'a;
+
+        #[cfg(not(feature = "debug_checks"))]
+        type ReadGuard<'a> = RwLockReadGuard<'a, R, T> where Self: 'a;
+        #[cfg(feature = "debug_checks")]
+        type ReadGuard<'a> = crate::debug_checks::ReadGuard<RwLockReadGuard<'a, R, T>> where Self: 'a;
+        type WriteGuard<'a> = RwLockWriteGuard<'a, R, T> where Self: 'a;
+
+        fn read(&self) -> Result<Self::ReadGuard<'_>, Self::ReadError<'_>> {
+            #[cfg(not(feature = "debug_checks"))]
+            return Ok(RwLock::read(self));
+            #[cfg(feature = "debug_checks")]
+            return Ok(crate::debug_checks::ReadGuard::acquire(self, RwLock::read));
+        }
+
+        fn write(&self) -> Result<Self::WriteGuard<'_>, Self::WriteError<'_>> {
+            Ok(RwLock::write(self))
+        }
+
+        fn try_read(&self) -> Result<Self::ReadGuard<'_>, Self::TryReadError<'_>> {
+            #[cfg(not(feature = "debug_checks"))]
+            return RwLock::try_read(self).ok_or(WouldBlock);
+            #[cfg(feature = "debug_checks")]
+            return crate::debug_checks::ReadGuard::try_acquire(self, RwLock::try_read)
+                .ok_or(WouldBlock);
+        }
+
+        fn try_write(&self) -> Result<Self::WriteGuard<'_>, Self::TryWriteError<'_>> {
+            RwLock::try_write(self).ok_or(WouldBlock)
+        }
+    }
+
+    impl<R: RawRwLockRecursive, T: ?Sized> RecursiveReadRwLock for RwLock<R, T> {
+        fn read_recursive(&self) -> Result<Self::ReadGuard<'_>, Self::ReadError<'_>> {
+            #[cfg(not(feature = "debug_checks"))]
+            return Ok(RwLock::read_recursive(self));
+            #[cfg(feature = "debug_checks")]
+            return Ok(crate::debug_checks::ReadGuard::acquire_recursive(
+                self,
+                RwLock::read_recursive,
+            ));
+        }
+    }
+
+    impl<R: RawRwLockDowngrade, T: ?Sized> DowngradableRwLock for RwLock<R, T> {
+        fn downgrade(guard: Self::WriteGuard<'_>) -> Self::ReadGuard<'_> {
+            #[cfg(not(feature = "debug_checks"))]
+            return RwLockWriteGuard::downgrade(guard);
+            #[cfg(feature = "debug_checks")]
+            return crate::debug_checks::ReadGuard::acquire_recursive(
+                RwLockWriteGuard::rwlock(&guard),
+                |_| RwLockWriteGuard::downgrade(guard),
+            );
+        }
+    }
+
+    impl<R: RawRwLockUpgrade, T: ?Sized> UpgradableRwLock for RwLock<R, T> {
+        type UpgradableGuard<'a> = RwLockUpgradableReadGuard<'a, R, T> where Self: 'a;
+
+        fn upgradable_read(&self) -> Result<Self::UpgradableGuard<'_>, Self::ReadError<'_>> {
+            Ok(RwLock::upgradable_read(self))
+        }
+
+        fn upgrade(guard: Self::UpgradableGuard<'_>) -> Self::WriteGuard<'_> {
+            RwLockUpgradableReadGuard::upgrade(guard)
+        }
+    }
+
+    #[cfg(all(test, feature = "parking_lot"))]
+    mod tests {
+        use crate::{
+            lock::RwLockLevel, relation::LockAfter, LockLevel, LockedAt, ReadWrite, Unlocked,
+        };
+
+        use parking_lot::RwLock;
+
+        struct Outer;
+        struct Inner;
+
+        impl LockAfter<Unlocked> for Outer {}
+        impl LockAfter<Outer> for Inner {}
+
+        impl LockLevel for Outer {
+            type Method = ReadWrite;
+        }
+        impl RwLockLevel for Outer {
+            type RwLock = RwLock<u32>;
+        }
+
+        impl LockLevel for Inner {
+            type Method = ReadWrite;
+        }
+        impl RwLockLevel for Inner {
+            type RwLock = RwLock<u32>;
+        }
+
+        #[test]
+        fn distinct_reads_allowed() {
+            let outer = RwLock::new(1);
+            let inner = RwLock::new(2);
+            let mut locked = LockedAt::new();
+
+            let (mut locked, outer_guard) = locked.with_read_lock::<Outer>(&outer).unwrap();
+            let inner_guard = locked.read_lock::<Inner>(&inner).unwrap();
+            assert_eq!(*outer_guard + *inner_guard, 3);
+        }
+
+        #[test]
+        fn sequential_reads_allowed() {
+            let lock = RwLock::new(1);
+            let mut locked = LockedAt::new();
+
+            drop(locked.read_lock::<Outer>(&lock).unwrap());
+            drop(locked.read_lock::<Outer>(&lock).unwrap());
+        }
+
+        #[test]
+        #[cfg(feature = "debug_checks")]
+        #[should_panic(expected = "recursive read lock")]
+        fn recursive_read_panics() {
+            // The same object is reachable at two different levels.
+            let lock = RwLock::new(1);
+            let mut locked = LockedAt::new();
+
+            let (mut locked, _outer_guard) = locked.with_read_lock::<Outer>(&lock).unwrap();
+            let _inner_guard = locked.read_lock::<Inner>(&lock).unwrap();
+        }
+
+        #[test]
+        fn read_recursive_with_writer_waiting() {
+            let lock = RwLock::new(1);
+            let mut locked = LockedAt::new();
+
+            let (mut locked, outer_guard) = locked.with_read_lock::<Outer>(&lock).unwrap();
+            std::thread::scope(|s| {
+                let _writer = s.spawn(|| *lock.write() += 1);
+                // Give the writer time to queue up behind `outer_guard`.
+                std::thread::sleep(std::time::Duration::from_millis(50));
+
+                let inner_guard = locked.read_recursive(&lock).unwrap();
+                assert_eq!(*outer_guard + *inner_guard, 2);
+                drop((inner_guard, outer_guard));
+            });
+            assert_eq!(*lock.read(), 2);
+        }
+
+        #[test]
+        fn upgrade_keeps_level() {
+            let outer = RwLock::new(1);
+            let inner = RwLock::new(2);
+            let mut locked = LockedAt::new();
+
+            let (mut locked, guard) = locked.upgradable_read_lock::<Outer>(&outer).unwrap();
+            // Ordinary readers can share the lock with the upgradable reader.
+            assert_eq!(*outer.try_read().unwrap(), *guard);
+
+            let mut guard = guard.upgrade();
+            assert!(outer.try_read().is_none());
+            *guard += *locked.read_lock::<Inner>(&inner).unwrap();
+            drop(guard);
+
+            assert_eq!(*outer.read(), 3);
+        }
+
+        #[test]
+        fn downgrade_keeps_level() {
+            let outer = RwLock::new(1);
+            let inner = RwLock::new(2);
+            let mut locked = LockedAt::new();
+
+            let (locked, mut guard) = locked.with_write_lock::<Outer>(&outer).unwrap();
+            *guard += 1;
+            let (mut locked, guard) = locked.downgrade(guard);
+            // Other readers can now share the lock, but writers can't get in.
+            assert_eq!(*outer.try_read().unwrap(), 2);
+            assert!(outer.try_write().is_none());
+
+            assert_eq!(*guard + *locked.read_lock::<Inner>(&inner).unwrap(), 4);
+        }
+    }
+}
+
+#[cfg(feature = "loom")]
+mod loom {
+    //! Implementation of [`RwLock`](super::RwLock) for
+    //! [`loom::sync::RwLock`].
+
+    use std::sync::{PoisonError, TryLockError};
+
+    use loom::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+    impl<T> super::RwLock for RwLock<T> {
+        type ReadError<'a> = PoisonError<RwLockReadGuard<'a, T>> where Self: 'a;
+        type WriteError<'a> = PoisonError<RwLockWriteGuard<'a, T>> where Self: 'a;
+        type TryReadError<'a> = TryLockError<RwLockReadGuard<'a, T>> where Self: 'a;
+        type TryWriteError<'a> = TryLockError<RwLockWriteGuard<'a, T>> where Self: 'a;
+
+        type ReadGuard<'a> = RwLockReadGuard<'a, T> where Self: 'a;
+        type WriteGuard<'a> = RwLockWriteGuard<'a, T> where Self: 'a;
+
+        fn read(&self) -> Result<Self::ReadGuard<'_>, Self::ReadError<'_>> {
+            RwLock::read(self)
+        }
+
+        fn write(&self) -> Result<Self::WriteGuard<'_>, Self::WriteError<'_>> {
+            RwLock::write(self)
+        }
+
+        fn try_read(&self) -> Result<Self::ReadGuard<'_>, Self::TryReadError<'_>> {
+            RwLock::try_read(self)
+        }
+
+        fn try_write(&self) -> Result<Self::WriteGuard<'_>, Self::TryWriteError<'_>> {
+            RwLock::try_write(self)
+        }
+    }
+}
+
+#[cfg(feature = "spin")]
+mod spin {
+    //! Implementation of [`RwLock`](super::RwLock) for [`spin::RwLock`].
+    //!
+    //! This doesn't depend on `std` and is available in `no_std` builds.
+
+    use core::convert::Infallible;
+
+    use spin::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+    use crate::lock::WouldBlock;
+
+    impl<T: ?Sized> super::RwLock for RwLock<T> {
+        type ReadError<'a> = Infallible where Self: 'a;
+        type WriteError<'a> = Infallible where Self: 'a;
+        type TryReadError<'a> = WouldBlock where Self: 'a;
+        type TryWriteError<'a> = WouldBlock where Self: 'a;
+        type ReadGuard<'a> = RwLockReadGuard<'a, T> where Self: 'a;
+        type WriteGuard<'a> = RwLockWriteGuard<'a, T> where Self: 'a;
+
+        fn read(&self) -> Result<Self::ReadGuard<'_>, Self::ReadError<'_>> {
+            Ok(RwLock::read(self))
+        }
+
+        fn write(&self) -> Result<Self::WriteGuard<'_>, Self::WriteError<'_>> {
+            Ok(RwLock::write(self))
+        }
+
+        fn try_read(&self) -> Result<Self::ReadGuard<'_>, Self::TryReadError<'_>> {
+            RwLock::try_read(self).ok_or(WouldBlock)
+        }
+
+        fn try_write(&self) -> Result<Self::WriteGuard<'_>, Self::TryWriteError<'_>> {
+            RwLock::try_write(self).ok_or(WouldBlock)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use spin::Mutex;
+
+        use crate::{
+            lock::{MutexLockLevel, RwLockLevel},
+            relation::LockAfter,
+            LockLevel, LockedAt, MutualExclusion, ReadWrite, Unlocked,
+        };
+
+        use super::RwLock;
+
+        struct Table;
+        struct Row;
+
+        impl LockAfter<Unlocked> for Table {}
+        impl LockAfter<Table> for Row {}
+
+        impl LockLevel for Table {
+            type Method = ReadWrite;
+        }
+        impl RwLockLevel for Table {
+            type RwLock = RwLock<[u8; 2]>;
+        }
+
+        impl LockLevel for Row {
+            type Method = MutualExclusion;
+        }
+        impl MutexLockLevel for Row {
+            type Mutex = Mutex<u8>;
+        }
+
+        #[test]
+        fn spin_locks_are_infallible() {
+            let table = RwLock::new([1, 2]);
+            let row = Mutex::new(0);
+            let mut locked = LockedAt::new();
+
+            let Ok((mut locked, table_guard)) = locked.with_read_lock::<Table>(&table);
+            let Ok(mut row_guard) = locked.lock::<Row>(&row);
+            *row_guard = table_guard.iter().sum();
+            assert!(table.try_write().is_none());
+            drop((row_guard, table_guard));
+
+            assert_eq!(row.into_inner(), 3);
+        }
+    }
+}
+
+#[cfg(feature = "refcell")]
+mod refcell {
+    //! Implementation of [`RwLock`](super::RwLock) for
+    //! [`core::cell::RefCell`].
+    //!
+    //! Reads map to shared borrows and writes to exclusive borrows. Since a
+    //! `RefCell` can't block, the blocking and non-blocking acquisitions are
+    //! the same, and both fail if the cell is already borrowed incompatibly.
+
+    use core::cell::{BorrowError, BorrowMutError, Ref, RefCell, RefMut};
+
+    impl<T: ?Sized> super::RwLock for RefCell<T> {
+        type ReadError<'a> = BorrowError where Self: 'a;
+        type WriteError<'a> = BorrowMutError where Self: 'a;
+        type TryReadError<'a> = BorrowError where Self: 'a;
+        type TryWriteError<'a> = BorrowMutError where Self: 'a;
+
+        type ReadGuard<'a> = Ref<'a, T> where Self: 'a;
+        type WriteGuard<'a> = RefMut<'a, T> where Self: 'a;
+
+        fn read(&self) -> Result<Self::ReadGuard<'_>, Self::ReadError<'_>> {
+            RefCell::try_borrow(self)
+        }
+
+        fn write(&self) -> Result<Self::WriteGuard<'_>, Self::WriteError<'_>> {
+            RefCell::try_borrow_mut(self)
+        }
+
+        fn try_read(&self) -> Result<Self::ReadGuard<'_>, Self::TryReadError<'_>> {
+            RefCell::try_borrow(self)
+        }
+
+        fn try_write(&self) -> Result<Self::WriteGuard<'_>, Self::TryWriteError<'_>> {
+            RefCell::try_borrow_mut(self)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::{
+            lock::RwLockLevel, relation::LockAfter, LockLevel, LockedAt, ReadWrite, Unlocked,
+        };
+
+        use super::RefCell;
+
+        struct Outer;
+        struct Inner;
+
+        impl LockAfter<Unlocked> for Outer {}
+        impl LockAfter<Outer> for Inner {}
+
+        impl LockLevel for Outer {
+            type Method = ReadWrite;
+        }
+        impl RwLockLevel for Outer {
+            type RwLock = RefCell<u32>;
+        }
+
+        impl LockLevel for Inner {
+            type Method = ReadWrite;
+        }
+        impl RwLockLevel for Inner {
+            type RwLock = RefCell<u32>;
+        }
+
+        #[test]
+        fn shared_and_exclusive_borrows() {
+            // The same cell is reachable at two different levels.
+            let cell = RefCell::new(1);
+            let mut locked = LockedAt::new();
+
+            let (mut locked, outer_guard) = locked.with_read_lock::<Outer>(&cell).unwrap();
+            assert_eq!(*locked.read_lock::<Inner>(&cell).unwrap(), *outer_guard);
+            assert!(locked.write_lock::<Inner>(&cell).is_err());
+        }
     }
 }
 
@@ -76,6 +671,7 @@ mod std {
 /// implementation with [Method](crate::LockLevel::Method) = `ReadWrite`.
 /// The error and RAII guard types are implementation-defined.
 #[cfg(feature = "async")]
+#[allow(async_fn_in_trait)]
 pub trait AsyncRwLock {
     /// [RAII guard] for shared access to data protected by the lock.
     ///
@@ -104,6 +700,25 @@ pub trait AsyncRwLock {
     async fn write(&self) -> Self::WriteGuard<'_>;
 }
 
+/// An [`AsyncRwLock`] that can also be acquired without waiting.
+///
+/// Not every asynchronous lock supports non-blocking attempts, so this is
+/// separate from [`AsyncRwLock`]. See
+/// [`LockedAt::try_wait_read`](crate::LockedAt::try_wait_read) and
+/// [`LockedAt::try_wait_write`](crate::LockedAt::try_wait_write).
+#[cfg(feature = "async")]
+pub trait NonBlockingAsyncRwLock: AsyncRwLock {
+    /// Attempts to acquire shared access to data without waiting.
+    ///
+    /// Returns `None` if the lock is currently held for writing.
+    fn try_read(&self) -> Option<Self::ReadGuard<'_>>;
+
+    /// Attempts to acquire exclusive access to data without waiting.
+    ///
+    /// Returns `None` if the lock is currently held.
+    fn try_write(&self) -> Option<Self::WriteGuard<'_>>;
+}
+
 #[cfg(feature = "tokio")]
 mod tokio {
     use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
@@ -121,4 +736,221 @@ mod tokio {
             RwLock::write(self).await
         }
     }
+
+    impl<T: ?Sized> super::NonBlockingAsyncRwLock for RwLock<T> {
+        fn try_read(&self) -> Option<Self::ReadGuard<'_>> {
+            RwLock::try_read(self).ok()
+        }
+
+        fn try_write(&self) -> Option<Self::WriteGuard<'_>> {
+            RwLock::try_write(self).ok()
+        }
+    }
+}
+
+#[cfg(feature = "async-lock")]
+mod async_lock {
+    use async_lock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+    impl<T: ?Sized> super::AsyncRwLock for RwLock<T> {
+        type ReadGuard<'a> = RwLockReadGuard<'a, T> where Self: 'a;
+
+        type WriteGuard<'a> = RwLockWriteGuard<'a, T> where Self: 'a;
+
+        async fn read(&self) -> Self::ReadGuard<'_> {
+            RwLock::read(self).await
+        }
+
+        async fn write(&self) -> Self::WriteGuard<'_> {
+            RwLock::write(self).await
+        }
+    }
+
+    impl<T: ?Sized> super::NonBlockingAsyncRwLock for RwLock<T> {
+        fn try_read(&self) -> Option<Self::ReadGuard<'_>> {
+            RwLock::try_read(self)
+        }
+
+        fn try_write(&self) -> Option<Self::WriteGuard<'_>> {
+            RwLock::try_write(self)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use async_lock::Mutex;
+
+        use crate::{
+            lock::{AsyncMutexLockLevel, AsyncRwLockLevel},
+            relation::LockAfter,
+            LockLevel, LockedAt, MutualExclusion, ReadWrite, Unlocked,
+        };
+
+        use super::RwLock;
+
+        struct Config;
+        struct Counter;
+
+        impl LockAfter<Unlocked> for Config {}
+        impl LockAfter<Config> for Counter {}
+
+        impl LockLevel for Config {
+            type Method = ReadWrite;
+        }
+        impl AsyncRwLockLevel for Config {
+            type RwLock = RwLock<&'static str>;
+        }
+
+        impl LockLevel for Counter {
+            type Method = MutualExclusion;
+        }
+        impl AsyncMutexLockLevel for Counter {
+            type Mutex = Mutex<usize>;
+        }
+
+        #[test]
+        fn wait_for_async_lock_locks() {
+            let config = RwLock::new("name");
+            let counter = Mutex::new(0);
+
+            futures::executor::block_on(async {
+                let mut locked = LockedAt::new();
+                let (mut locked, config) = locked.wait_for_read::<Config>(&config).await;
+                *locked.wait_lock::<Counter>(&counter).await += config.len();
+            });
+
+            assert_eq!(*counter.try_lock().unwrap(), 4);
+        }
+    }
+}
+
+#[cfg(feature = "embassy-sync")]
+mod embassy_sync {
+    use embassy_sync::{
+        blocking_mutex::raw::RawMutex,
+        rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+    };
+
+    impl<M: RawMutex, T: ?Sized> super::AsyncRwLock for RwLock<M, T> {
+        type ReadGuard<'a> = RwLockReadGuard<'a, M, T> where Self: 'a;
+
+        type WriteGuard<'a> = RwLockWriteGuard<'a, M, T> where Self: 'a;
+
+        async fn read(&self) -> Self::ReadGuard<'_> {
+            RwLock::read(self).await
+        }
+
+        async fn write(&self) -> Self::WriteGuard<'_> {
+            RwLock::write(self).await
+        }
+    }
+
+    impl<M: RawMutex, T: ?Sized> super::NonBlockingAsyncRwLock for RwLock<M, T> {
+        fn try_read(&self) -> Option<Self::ReadGuard<'_>> {
+            RwLock::try_read(self).ok()
+        }
+
+        fn try_write(&self) -> Option<Self::WriteGuard<'_>> {
+            RwLock::try_write(self).ok()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use embassy_sync::{blocking_mutex::raw::NoopRawMutex, mutex::Mutex};
+
+        use crate::{
+            lock::{AsyncMutexLockLevel, AsyncRwLockLevel},
+            relation::LockAfter,
+            LockLevel, LockedAt, MutualExclusion, ReadWrite, Unlocked,
+        };
+
+        use super::RwLock;
+
+        struct Config;
+        struct Counter;
+
+        impl LockAfter<Unlocked> for Config {}
+        impl LockAfter<Config> for Counter {}
+
+        impl LockLevel for Config {
+            type Method = ReadWrite;
+        }
+        impl AsyncRwLockLevel for Config {
+            type RwLock = RwLock<NoopRawMutex, &'static str>;
+        }
+
+        impl LockLevel for Counter {
+            type Method = MutualExclusion;
+        }
+        impl AsyncMutexLockLevel for Counter {
+            type Mutex = Mutex<NoopRawMutex, usize>;
+        }
+
+        #[test]
+        fn wait_for_embassy_locks() {
+            let config = RwLock::new("name");
+            let counter = Mutex::new(0);
+
+            futures::executor::block_on(async {
+                let mut locked = LockedAt::new();
+                let (mut locked, config) = locked.wait_for_read::<Config>(&config).await;
+                *locked.wait_lock::<Counter>(&counter).await += config.len();
+            });
+
+            assert_eq!(*counter.try_lock().unwrap(), 4);
+        }
+    }
+}
+
+#[cfg(feature = "async-std")]
+mod async_std {
+    //! `async_std::sync::RwLock` and `Mutex` are re-exports of the
+    //! `async_lock` types, which implement the lock traits in the
+    //! `async_lock` modules.
+
+    #[cfg(test)]
+    mod tests {
+        use async_std::sync::{Mutex, RwLock};
+
+        use crate::{
+            lock::{AsyncMutexLockLevel, AsyncRwLockLevel},
+            relation::LockAfter,
+            LockLevel, LockedAt, MutualExclusion, ReadWrite, Unlocked,
+        };
+
+        struct Config;
+        struct Counter;
+
+        impl LockAfter<Unlocked> for Config {}
+        impl LockAfter<Config> for Counter {}
+
+        impl LockLevel for Config {
+            type Method = ReadWrite;
+        }
+        impl AsyncRwLockLevel for Config {
+            type RwLock = RwLock<&'static str>;
+        }
+
+        impl LockLevel for Counter {
+            type Method = MutualExclusion;
+        }
+        impl AsyncMutexLockLevel for Counter {
+            type Mutex = Mutex<usize>;
+        }
+
+        #[test]
+        fn wait_for_async_std_locks() {
+            let config = RwLock::new("name");
+            let counter = Mutex::new(0);
+
+            futures::executor::block_on(async {
+                let mut locked = LockedAt::new();
+                let (mut locked, config) = locked.wait_for_read::<Config>(&config).await;
+                *locked.wait_lock::<Counter>(&counter).await += config.len();
+            });
+
+            assert_eq!(*counter.try_lock().unwrap(), 4);
+        }
+    }
 }
@@ -0,0 +1,96 @@
+//! Counting semaphores, acquired in order with locks.
+
+/// A semaphore whose permits are acquired asynchronously.
+///
+/// Waiting for a permit while holding a lock can deadlock just like waiting
+/// for another lock, so semaphores are given levels too; see
+/// [`LockedAt::acquire_permit`](crate::LockedAt::acquire_permit).
+#[allow(async_fn_in_trait)]
+pub trait AsyncSemaphore {
+    /// Error that could be produced when acquiring a permit, e.g. because
+    /// the semaphore was closed.
+    type Error<'a>
+    where
+        Self: 'a;
+
+    /// A held permit, which is returned to the semaphore when dropped.
+    type Permit<'a>
+    where
+        Self: 'a;
+
+    /// Acquires a permit, causing the current task to yield until one is
+    /// available.
+    async fn acquire(&self) -> Result<Self::Permit<'_>, Self::Error<'_>>;
+}
+
+#[cfg(feature = "tokio")]
+mod tokio {
+    //! Implementation of [`AsyncSemaphore`](super::AsyncSemaphore) for
+    //! [`tokio::sync::Semaphore`].
+
+    use tokio::sync::{AcquireError, Semaphore, SemaphorePermit};
+
+    impl super::AsyncSemaphore for Semaphore {
+        type Error<'a> = AcquireError;
+        type Permit<'a> = SemaphorePermit<'a>;
+
+        async fn acquire(&self) -> Result<Self::Permit<'_>, Self::Error<'_>> {
+            Semaphore::acquire(self).await
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use tokio::sync::{Mutex, Semaphore};
+
+        use crate::{
+            lock::{AsyncMutexLockLevel, SemaphoreLevel},
+            relation::LockAfter,
+            LockLevel, LockedAt, MutualExclusion, Unlocked,
+        };
+
+        struct Connections;
+        struct Stats;
+
+        impl LockAfter<Unlocked> for Connections {}
+        impl LockAfter<Connections> for Stats {}
+
+        impl LockLevel for Connections {
+            type Method = MutualExclusion;
+        }
+        impl SemaphoreLevel for Connections {
+            type Semaphore = Semaphore;
+        }
+
+        impl LockLevel for Stats {
+            type Method = MutualExclusion;
+        }
+        impl AsyncMutexLockLevel for Stats {
+            type Mutex = Mutex<usize>;
+        }
+
+        #[tokio::test]
+        async fn permit_is_ordered_before_lock() {
+            let connections = Semaphore::new(1);
+            let stats = Mutex::new(0);
+            let mut locked = LockedAt::new();
+
+            {
+                let (mut locked, _permit) = locked
+                    .acquire_permit::<Connections>(&connections)
+                    .await
+                    .unwrap();
+                assert_eq!(connections.available_permits(), 0);
+                *locked.wait_lock::<Stats>(&stats).await += 1;
+            }
+            assert_eq!(connections.available_permits(), 1);
+
+            connections.close();
+            assert!(locked
+                .acquire_permit::<Connections>(&connections)
+                .await
+                .is_err());
+            assert_eq!(*stats.lock().await, 1);
+        }
+    }
+}
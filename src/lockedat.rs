@@ -1,13 +1,32 @@
-use core::marker::PhantomData;
+use core::{
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    time::Duration,
+};
 
 use crate::{
-    lock::{MutexLock, MutexLockLevel, RwLock, RwLockLevel},
-    relation::LockAfter,
+    lock::{
+        CondvarLock, CowGuard, DowngradableRwLock, Either, GetMutLock, IntoInnerLock, Located,
+        LockRef, MappableGuard, MutexLock, MutexLockData, MutexLockLevel, MutexLockTimeout,
+        OrderGuard, PairError, RecursiveReadRwLock, ReentrantMutexLock, RelockableWriteGuard,
+        RwError, RwGuard, RwLock, RwLockLevel, SuspendableGuard, TreeLockLevel,
+        UpgradableReadGuard, UpgradableRwLock,
+    },
+    relation::{LockAfter, LockBefore, OneOf},
     Unlocked,
 };
 
+#[cfg(feature = "macros")]
+use crate::LockSection;
+
+#[cfg(all(feature = "async", feature = "std"))]
+use crate::lock::OwnedMutexLock;
 #[cfg(feature = "async")]
-use crate::lock::{AsyncMutexLock, AsyncMutexLockLevel, AsyncRwLock, AsyncRwLockLevel};
+use crate::lock::{
+    AsyncMutexLock, AsyncMutexLockLevel, AsyncRwLock, AsyncRwLockLevel, AsyncSemaphore,
+    NonBlockingAsyncMutexLock, NonBlockingAsyncRwLock, SemaphoreLevel, TryAsyncMutexLock,
+    TryAsyncMutexLockLevel,
+};
 
 /// Indicator type for a mutual exclusion lock.
 ///
@@ -32,19 +51,264 @@ pub struct ReadWrite;
 /// For a given `L`, the methods on `LockedAt<'_, L>` will allow accessing state
 /// for a lock level `M` if [`M: LockAfter<L>`](crate::relation::LockAfter).
 ///
+/// Only root instances at [`Unlocked`] can be created directly, with
+/// [`LockedAt::new`] and friends. Instances for any other level are only
+/// produced by the methods here, after the lock for that level is acquired
+/// or skipped in order, so there's no way to start acquiring locks from the
+/// middle of the hierarchy. That includes building one from its private
+/// field:
+///
+/// ```compile_fail
+/// # use core::marker::PhantomData;
+/// # use lock_ordering::{relation::LockAfter, LockedAt, Unlocked};
+/// struct Outer;
+/// impl LockAfter<Unlocked> for Outer {}
+///
+/// let _mid = LockedAt::<Outer>(PhantomData);
+/// ```
+///
 /// The `with_` methods on this type will (if they don't return an error),
 /// produce two values: a new `LockedAt` instance and an accessor for locked
 /// state.  Both values will exclusively borrow the original `LockedAt`
 /// instance, preventing its use, until the new values go out of scope.
+///
+/// `LockedAt` is zero-sized, so passing a `&mut LockedAt<'_, L>` to helper
+/// functions is free. This includes dynamically dispatched code: boxed
+/// futures or trait objects can take the `&mut LockedAt` as an argument, and
+/// the level `L` remains part of the signature so acquisitions inside them
+/// are still checked.
 pub struct LockedAt<'a, L>(PhantomData<&'a mut L>);
 
 impl LockedAt<'static, Unlocked> {
     /// Creates a new `LockedAt` without any locks held.
+    ///
+    /// # Panics
+    ///
+    /// With `std`, panics if the current thread's [`ThreadToken`] is checked
+    /// out, since its holder expects to be the only root on this thread.
+    ///
+    /// [`ThreadToken`]: crate::ThreadToken
     pub fn new() -> Self {
+        #[cfg(feature = "std")]
+        assert!(
+            !crate::ThreadToken::is_checked_out(),
+            "LockedAt::new called while this thread's ThreadToken is checked out"
+        );
+        // SAFETY: `new` has always been safe to call; the check above catches
+        // the conflicts that can be detected, with `with_thread_token` roots.
+        unsafe { Self::new_unchecked() }
+    }
+
+    /// Creates a new `LockedAt` without any locks held, without checks.
+    ///
+    /// # Safety
+    ///
+    /// The ordering guarantee only holds if there is at most one live root
+    /// `LockedAt` per thread: two roots on the same thread could each acquire
+    /// locks as if nothing else were held.
+    pub unsafe fn new_unchecked() -> Self {
+        Self(PhantomData)
+    }
+}
+
+#[cfg(feature = "test-util")]
+impl LockedAt<'static, Unlocked> {
+    /// Creates a `LockedAt` at level `L` without acquiring anything.
+    ///
+    /// This is meant for unit tests of functions that take a
+    /// `&mut LockedAt<'_, L>`, so that they can be called without first
+    /// acquiring the real locks for the levels before `L`. It is only
+    /// available with the `test-util` feature, which should only be enabled
+    /// for dev-dependencies.
+    ///
+    /// # Safety
+    ///
+    /// Nothing is known to be held, so the ordering guarantee doesn't hold
+    /// for locks acquired from the returned `LockedAt`: it can be used to
+    /// acquire locks in any order. Never call this outside of tests.
+    pub unsafe fn at_level<L>() -> LockedAt<'static, L> {
+        LockedAt(PhantomData)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a> LockedAt<'a, Unlocked> {
+    /// Creates a root `LockedAt` from the current thread's token.
+    ///
+    /// The returned `LockedAt` exclusively borrows `token`, so no other root
+    /// can be created from it while this one (or anything derived from it) is
+    /// alive.
+    pub fn with_thread_token(token: &'a mut crate::ThreadToken) -> Self {
+        let _ = token;
         Self(PhantomData)
     }
 }
 
+impl Default for LockedAt<'static, Unlocked> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LockedAt<'_, Unlocked> {
+    /// Produces a root for code that runs while nothing is held.
+    ///
+    /// Callbacks usually shouldn't create their own root with
+    /// [`LockedAt::new`], since they can be called while the caller holds
+    /// locks. If the caller holds nothing, it can instead pass the callback
+    /// a root derived from its own: the `LockedAt<Unlocked>` proves that no
+    /// locks are held, and it can't be used until the callback's root is
+    /// gone.
+    pub fn reenter_as_root(&mut self) -> LockedAt<'_, Unlocked> {
+        self.reborrow()
+    }
+}
+
+impl<L> core::fmt::Debug for LockedAt<'_, L> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("LockedAt")
+            .field(&format_args!("{}", core::any::type_name::<L>()))
+            .finish()
+    }
+}
+
+impl<L: crate::RankedLockLevel> LockedAt<'_, L> {
+    /// Returns the [rank](crate::RankedLockLevel) of the held level `L`.
+    pub const fn rank(&self) -> u32 {
+        L::RANK
+    }
+}
+
+/// Guard for a lock acquired synchronously through a [`LockedAt`].
+///
+/// Without the `debug_checks` feature, this is the backend's guard `G`
+/// itself. With it, this is `debug_checks::HeldGuard<G>`, which dereferences
+/// to the same target and also reports the lock's level as held.
+#[cfg(not(feature = "debug_checks"))]
+pub type LevelGuard<G> = G;
+
+/// Guard for a lock acquired synchronously through a [`LockedAt`].
+///
+/// With the `debug_checks` feature, this is a [`HeldGuard`] wrapping the
+/// backend's guard `G`, which dereferences to the same target and reports
+/// the lock's level as held until it is dropped. Without the feature, this
+/// is `G` itself.
+///
+/// [`HeldGuard`]: crate::debug_checks::HeldGuard
+#[cfg(feature = "debug_checks")]
+pub type LevelGuard<G> = crate::debug_checks::HeldGuard<G>;
+
+/// Wraps `guard`, for a lock that was just acquired at `Level`.
+// `Level` is only used to name the held level for debug checks.
+#[cfg_attr(
+    not(feature = "debug_checks"),
+    allow(clippy::extra_unused_type_parameters)
+)]
+fn level_guard<Level, G>(guard: G) -> LevelGuard<G> {
+    #[cfg(feature = "debug_checks")]
+    return crate::debug_checks::HeldGuard::new(guard, core::any::type_name::<Level>());
+    #[cfg(not(feature = "debug_checks"))]
+    return guard;
+}
+
+/// Replaces the backend's guard in `guard` with the result of `f`, which
+/// must be a guard for the same lock.
+pub(crate) fn map_level_guard<G, U>(guard: LevelGuard<G>, f: impl FnOnce(G) -> U) -> LevelGuard<U> {
+    #[cfg(feature = "debug_checks")]
+    return crate::debug_checks::HeldGuard::map(guard, f);
+    #[cfg(not(feature = "debug_checks"))]
+    return f(guard);
+}
+
+/// Like [`map_level_guard`], for fallible operations.
+fn try_map_level_guard<G, U, E>(
+    guard: LevelGuard<G>,
+    f: impl FnOnce(G) -> Result<U, E>,
+) -> Result<LevelGuard<U>, E> {
+    #[cfg(feature = "debug_checks")]
+    return crate::debug_checks::HeldGuard::try_map(guard, f);
+    #[cfg(not(feature = "debug_checks"))]
+    return f(guard);
+}
+
+/// Returns the backend's guard in `guard`.
+pub(crate) fn backend_guard_mut<G>(guard: &mut LevelGuard<G>) -> &mut G {
+    #[cfg(feature = "debug_checks")]
+    return crate::debug_checks::HeldGuard::inner_mut(guard);
+    #[cfg(not(feature = "debug_checks"))]
+    return guard;
+}
+
+/// Checks, with the `debug_checks` feature, that the current thread can
+/// acquire a lock at `Level`.
+fn check_acquire<Level>() {
+    #[cfg(feature = "debug_checks")]
+    crate::debug_checks::check_acquire(core::any::type_name::<Level>());
+}
+
+/// Acquires a lock at `Level` with `acquire`, wrapping the resulting guard.
+pub(crate) fn acquire_level<Level, G, E>(
+    acquire: impl FnOnce() -> Result<G, E>,
+) -> Result<LevelGuard<G>, E> {
+    check_acquire::<Level>();
+    crate::instrument::acquire(core::any::type_name::<Level>(), acquire)
+        .map(level_guard::<Level, _>)
+}
+
+/// Blocks on `t` at `Level`, wrapping the resulting guard.
+pub(crate) fn lock_level<Level, M: MutexLock + ?Sized>(
+    t: &M,
+) -> Result<LevelGuard<M::Guard<'_>>, M::Error<'_>> {
+    check_acquire::<Level>();
+    crate::instrument::lock(core::any::type_name::<Level>(), t).map(level_guard::<Level, _>)
+}
+
+impl<L> LockedAt<'_, L> {
+    /// Produces a shorter-lived `LockedAt` at the same level.
+    ///
+    /// This is the `LockedAt` equivalent of reborrowing a `&mut` reference:
+    /// the result can be passed by value to a function taking a
+    /// `LockedAt<'_, L>`, and `self` becomes usable again once it is gone.
+    /// No locks are acquired or released.
+    pub fn reborrow(&mut self) -> LockedAt<'_, L> {
+        LockedAt(PhantomData)
+    }
+
+    /// Moves to level `NewLock` without acquiring any lock.
+    ///
+    /// This is for code that knows, by contract, that it is running under
+    /// `NewLock` even though it holds no guard for it, and needs to call
+    /// helpers that expect a `LockedAt<'_, NewLock>`. It is a purely
+    /// type-level transition, and `NewLock` must still come after `L`.
+    ///
+    /// Since no guard is held, this works the same in async code: the
+    /// returned `LockedAt` can be held across `.await`s, and is [`Send`]
+    /// whenever `NewLock` is, so it doesn't stop the enclosing future from
+    /// being spawned on a multi-threaded executor.
+    pub fn skip_locking<NewLock>(&mut self) -> LockedAt<'_, NewLock>
+    where
+        L: LockBefore<NewLock>,
+    {
+        LockedAt(PhantomData)
+    }
+
+    /// Runs `f` as a teardown body for the levels it acquires.
+    ///
+    /// This is a plain call to `f` unless the `debug_checks` feature is
+    /// enabled. With it, the levels released while `f` runs are recorded, and
+    /// when the scope exits (including by panicking) the release order and any
+    /// levels still held are printed to stderr. The release order is also
+    /// available from
+    /// [`debug_checks::released_in_last_teardown`](crate::debug_checks::released_in_last_teardown).
+    /// This is useful for verifying that shutdown paths release locks in the
+    /// reverse of the order they were acquired.
+    pub fn teardown_scope<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        #[cfg(feature = "debug_checks")]
+        let _scope = crate::debug_checks::TeardownScope::enter();
+        f(self)
+    }
+}
+
 impl<L> LockedAt<'_, L> {
     /// Attempts to acquire a lock on `NewLock` state.
     ///
@@ -56,17 +320,86 @@ impl<L> LockedAt<'_, L> {
     ///
     /// If no further `LockedAt` calls need to be made after this one, consider
     /// using [`LockedAt::lock`] instead.
+    #[allow(clippy::type_complexity)]
     pub fn with_lock<'a, NewLock: LockAfter<L> + MutexLockLevel>(
         &'a mut self,
         t: &'a NewLock::Mutex,
     ) -> Result<
         (
             LockedAt<'a, NewLock>,
-            <NewLock::Mutex as MutexLock>::Guard<'a>,
+            LevelGuard<<NewLock::Mutex as MutexLock>::Guard<'a>>,
+        ),
+        <NewLock::Mutex as MutexLock>::Error<'a>,
+    > {
+        let guard = lock_level::<NewLock, _>(t)?;
+        Ok((LockedAt(PhantomData), guard))
+    }
+
+    /// Attempts to acquire a lock on `NewLock` state without blocking.
+    ///
+    /// Like [`LockedAt::with_lock`], but if the lock is currently held, this
+    /// returns an error immediately instead of waiting for it. The error type
+    /// distinguishes contention from other failures like poisoning.
+    ///
+    /// If no further `LockedAt` calls need to be made after this one, consider
+    /// using [`LockedAt::try_lock`] instead.
+    #[allow(clippy::type_complexity)]
+    pub fn with_try_lock<'a, NewLock: LockAfter<L> + MutexLockLevel>(
+        &'a mut self,
+        t: &'a NewLock::Mutex,
+    ) -> Result<
+        (
+            LockedAt<'a, NewLock>,
+            LevelGuard<<NewLock::Mutex as MutexLock>::Guard<'a>>,
+        ),
+        <NewLock::Mutex as MutexLock>::TryError<'a>,
+    > {
+        let guard = acquire_level::<NewLock, _, _>(|| t.try_lock())?;
+        Ok((LockedAt(PhantomData), guard))
+    }
+
+    /// Attempts to acquire a lock on `NewLock` state, giving up after
+    /// `timeout`.
+    ///
+    /// Like [`LockedAt::with_lock`], but if the lock doesn't become available
+    /// within `timeout`, this returns an error instead of waiting further.
+    ///
+    /// If no further `LockedAt` calls need to be made after this one, consider
+    /// using [`LockedAt::lock_timeout`] instead.
+    #[allow(clippy::type_complexity)]
+    pub fn with_lock_timeout<'a, NewLock: LockAfter<L> + MutexLockLevel>(
+        &'a mut self,
+        t: &'a NewLock::Mutex,
+        timeout: Duration,
+    ) -> Result<
+        (
+            LockedAt<'a, NewLock>,
+            LevelGuard<<NewLock::Mutex as MutexLock>::Guard<'a>>,
         ),
+        <NewLock::Mutex as MutexLockTimeout>::TimeoutError<'a>,
+    >
+    where
+        NewLock::Mutex: MutexLockTimeout,
+    {
+        let guard = acquire_level::<NewLock, _, _>(|| t.lock_timeout(timeout))?;
+        Ok((LockedAt(PhantomData), guard))
+    }
+
+    /// Acquires a lock on `NewLock` state with a guard that can be suspended.
+    ///
+    /// Like [`LockedAt::with_lock`], but the returned guard remembers `t`, so
+    /// the lock can later be released and re-acquired with
+    /// [`LockedAt::suspend_lock`].
+    #[allow(clippy::type_complexity)]
+    pub fn with_lock_suspendable<'a, NewLock: LockAfter<L> + MutexLockLevel>(
+        &'a mut self,
+        t: &'a NewLock::Mutex,
+    ) -> Result<
+        (LockedAt<'a, NewLock>, SuspendableGuard<'a, NewLock>),
         <NewLock::Mutex as MutexLock>::Error<'a>,
     > {
-        t.lock().map(|guard| (LockedAt(PhantomData), guard))
+        let guard = lock_level::<NewLock, _>(t)?;
+        Ok((LockedAt(PhantomData), SuspendableGuard::new(t, guard)))
     }
 
     /// Attempts to acquire a shared lock on `NewLock` state.
@@ -79,17 +412,19 @@ impl<L> LockedAt<'_, L> {
     ///
     /// If no further `LockedAt` calls need to be made after this one, consider
     /// using [`LockedAt::read_lock`] instead.
+    #[allow(clippy::type_complexity)]
     pub fn with_read_lock<'a, NewLock: LockAfter<L> + RwLockLevel>(
         &'a mut self,
         t: &'a NewLock::RwLock,
     ) -> Result<
         (
             LockedAt<'a, NewLock>,
-            <NewLock::RwLock as RwLock>::ReadGuard<'a>,
+            LevelGuard<<NewLock::RwLock as RwLock>::ReadGuard<'a>>,
         ),
         <NewLock::RwLock as RwLock>::ReadError<'a>,
     > {
-        t.read().map(|guard| (LockedAt(PhantomData), guard))
+        let guard = acquire_level::<NewLock, _, _>(|| t.read())?;
+        Ok((LockedAt(PhantomData), guard))
     }
 
     /// Attempts to acquire an exclusive lock on `NewLock` state.
@@ -102,151 +437,3038 @@ impl<L> LockedAt<'_, L> {
     ///
     /// If no further `LockedAt` calls need to be made after this one, consider
     /// using [`LockedAt::write_lock`] instead.
+    #[allow(clippy::type_complexity)]
     pub fn with_write_lock<'a, NewLock: LockAfter<L> + RwLockLevel>(
         &'a mut self,
         t: &'a NewLock::RwLock,
     ) -> Result<
         (
             LockedAt<'a, NewLock>,
-            <NewLock::RwLock as RwLock>::WriteGuard<'a>,
+            LevelGuard<<NewLock::RwLock as RwLock>::WriteGuard<'a>>,
         ),
         <NewLock::RwLock as RwLock>::WriteError<'a>,
     > {
-        t.write().map(|guard| (LockedAt(PhantomData), guard))
+        let guard = acquire_level::<NewLock, _, _>(|| t.write())?;
+        Ok((LockedAt(PhantomData), guard))
     }
-}
 
-// Convenience methods for accessing leaf locks in the ordering tree.
-impl<L> LockedAt<'_, L> {
-    /// Provides access to a [MutexLock]'s state.
-    pub fn lock<'a, NewLock: LockAfter<L> + 'a + MutexLockLevel>(
+    /// Acquires an exclusive lock on `NewLock` state with a guard that can be
+    /// relocked for reading.
+    ///
+    /// Like [`LockedAt::with_write_lock`], but the returned guard remembers
+    /// `t`, so it can later be exchanged for a read guard with
+    /// [`LockedAt::relock_read`].
+    #[allow(clippy::type_complexity)]
+    pub fn with_write_lock_relockable<'a, NewLock: LockAfter<L> + RwLockLevel>(
         &'a mut self,
-        t: &'a NewLock::Mutex,
-    ) -> Result<<NewLock::Mutex as MutexLock>::Guard<'a>, <NewLock::Mutex as MutexLock>::Error<'a>>
-    {
-        self.with_lock::<NewLock>(t).map(|(_locked, guard)| guard)
+        t: &'a NewLock::RwLock,
+    ) -> Result<
+        (LockedAt<'a, NewLock>, RelockableWriteGuard<'a, NewLock>),
+        <NewLock::RwLock as RwLock>::WriteError<'a>,
+    > {
+        let guard = acquire_level::<NewLock, _, _>(|| t.write())?;
+        Ok((LockedAt(PhantomData), RelockableWriteGuard::new(t, guard)))
     }
 
-    /// Provides read access to a [RwLock]'s state.
-    pub fn read_lock<'a, NewLock: LockAfter<L> + RwLockLevel + 'a>(
+    /// Attempts to acquire a shared lock on `NewLock` state without blocking.
+    ///
+    /// Like [`LockedAt::with_read_lock`], but if the lock is currently held
+    /// for writing, this returns an error immediately instead of waiting for
+    /// it.
+    ///
+    /// If no further `LockedAt` calls need to be made after this one, consider
+    /// using [`LockedAt::try_read_lock`] instead.
+    #[allow(clippy::type_complexity)]
+    pub fn with_try_read_lock<'a, NewLock: LockAfter<L> + RwLockLevel>(
         &'a mut self,
         t: &'a NewLock::RwLock,
     ) -> Result<
-        <NewLock::RwLock as RwLock>::ReadGuard<'a>,
-        <NewLock::RwLock as RwLock>::ReadError<'a>,
+        (
+            LockedAt<'a, NewLock>,
+            LevelGuard<<NewLock::RwLock as RwLock>::ReadGuard<'a>>,
+        ),
+        <NewLock::RwLock as RwLock>::TryReadError<'a>,
     > {
-        self.with_read_lock::<NewLock>(t)
-            .map(|(_locked, guard)| guard)
+        let guard = acquire_level::<NewLock, _, _>(|| t.try_read())?;
+        Ok((LockedAt(PhantomData), guard))
     }
 
-    /// Provides read/write access to a [RwLock]'s state.
-    pub fn write_lock<'a, NewLock: LockAfter<L> + RwLockLevel + 'a>(
+    /// Attempts to acquire an exclusive lock on `NewLock` state without
+    /// blocking.
+    ///
+    /// Like [`LockedAt::with_write_lock`], but if the lock is currently held,
+    /// this returns an error immediately instead of waiting for it.
+    ///
+    /// If no further `LockedAt` calls need to be made after this one, consider
+    /// using [`LockedAt::try_write_lock`] instead.
+    #[allow(clippy::type_complexity)]
+    pub fn with_try_write_lock<'a, NewLock: LockAfter<L> + RwLockLevel>(
         &'a mut self,
         t: &'a NewLock::RwLock,
     ) -> Result<
-        <NewLock::RwLock as RwLock>::WriteGuard<'a>,
-        <NewLock::RwLock as RwLock>::WriteError<'a>,
+        (
+            LockedAt<'a, NewLock>,
+            LevelGuard<<NewLock::RwLock as RwLock>::WriteGuard<'a>>,
+        ),
+        <NewLock::RwLock as RwLock>::TryWriteError<'a>,
     > {
-        self.with_write_lock::<NewLock>(t)
-            .map(|(_locked, guard)| guard)
+        let guard = acquire_level::<NewLock, _, _>(|| t.try_write())?;
+        Ok((LockedAt(PhantomData), guard))
     }
-}
 
-#[cfg(feature = "async")]
-impl<L> LockedAt<'_, L> {
-    /// Asynchronously acquires a lock on `NewLock` state.
+    /// Attempts to acquire a lock on `NewLock` state and narrows the guard.
     ///
-    /// Assuming `NewLock` is a lock level that can be acquired after `L`, this
-    /// method provides access to state held in the [`AsyncMutexLock`] type
-    /// `NewLock::Mutex`, yielding the current task until the lock can be
-    /// acquired. Once the state is locked, returns a guard for accessing it
-    /// and a new `LockedAt` instance that can be used to acquire additional
-    /// locks.
+    /// Like [`LockedAt::with_lock`], but the guard is mapped with `f` so that
+    /// it only provides access to part of the locked state, usually a field.
+    /// The lock stays held until the mapped guard is dropped. This requires
+    /// the backend's guard to implement [`MappableGuard`].
     ///
     /// If no further `LockedAt` calls need to be made after this one, consider
-    /// using [`LockedAt::wait_lock`] instead.
-    pub async fn wait_for_lock<'a, NewLock: LockAfter<L> + AsyncMutexLockLevel>(
+    /// using [`LockedAt::lock_map`] instead.
+    #[allow(clippy::type_complexity)]
+    pub fn with_lock_map<'a, NewLock: LockAfter<L> + MutexLockLevel, U: ?Sized + 'a>(
         &'a mut self,
         t: &'a NewLock::Mutex,
-    ) -> (
-        LockedAt<'a, NewLock>,
-        <NewLock::Mutex as AsyncMutexLock>::Guard<'a>,
-    ) {
-        let guard = t.lock().await;
-        (LockedAt(PhantomData), guard)
+        f: impl for<'g> FnOnce(
+            &'g mut <<NewLock::Mutex as MutexLock>::Guard<'a> as Deref>::Target,
+        ) -> &'g mut U,
+    ) -> Result<
+        (
+            LockedAt<'a, NewLock>,
+            LevelGuard<<<NewLock::Mutex as MutexLock>::Guard<'a> as MappableGuard<'a>>::Mapped<U>>,
+        ),
+        <NewLock::Mutex as MutexLock>::Error<'a>,
+    >
+    where
+        <NewLock::Mutex as MutexLock>::Guard<'a>: MappableGuard<'a>,
+    {
+        self.with_lock::<NewLock>(t)
+            .map(|(locked, guard)| (locked, map_level_guard(guard, |guard| guard.map(f))))
     }
 
-    /// Asynchronously acquires a shared lock on `NewLock` state.
+    /// Attempts to acquire an upgradable shared lock on `NewLock` state.
     ///
     /// Assuming `NewLock` is a lock level that can be acquired after `L`, this
-    /// method provides access to state held in the [`ReadWrite`] type T.
-    /// This method will yield the current task until the lock can be acquired.
-    /// Once the state is locked, this method returns a guard for accessing it
-    /// and a new `LockedAt` instance that can be used to acquire additional
-    /// locks.
-    ///
-    /// If no further `LockedAt` calls need to be made after this one, consider
-    /// using [`LockedAt::wait_read`] instead.
-    pub async fn wait_for_read<'a, NewLock: LockAfter<L> + AsyncRwLockLevel>(
+    /// provides read access to state held in an [`UpgradableRwLock`]. The
+    /// returned guard can later be upgraded to exclusive access with
+    /// [`UpgradableReadGuard::upgrade`]; since upgrading doesn't acquire
+    /// another lock, the returned `LockedAt` stays valid afterwards.
+    #[allow(clippy::type_complexity)]
+    pub fn upgradable_read_lock<'a, NewLock: LockAfter<L> + RwLockLevel>(
         &'a mut self,
         t: &'a NewLock::RwLock,
-    ) -> (
-        LockedAt<'a, NewLock>,
-        <NewLock::RwLock as AsyncRwLock>::ReadGuard<'a>,
-    ) {
-        let guard = t.read().await;
-        (LockedAt(PhantomData), guard)
+    ) -> Result<
+        (
+            LockedAt<'a, NewLock>,
+            UpgradableReadGuard<'a, NewLock::RwLock>,
+        ),
+        <NewLock::RwLock as RwLock>::ReadError<'a>,
+    >
+    where
+        NewLock::RwLock: UpgradableRwLock,
+    {
+        let guard = acquire_level::<NewLock, _, _>(|| t.upgradable_read())?;
+        Ok((LockedAt(PhantomData), UpgradableReadGuard::new(guard)))
     }
 
-    /// Attempts to acquire an exclusive lock on `NewLock` state.
+    /// Acquires a lock on `NewLock` state and runs `f` with it held.
     ///
-    /// Assuming `NewLock` is a lock level that can be acquired after `L`, this
-    /// method provides access to state held in the [`ReadWrite`] type T. If the
-    /// lock acquisition fails, an error will be returned. Otherwise, this
-    /// method returns a new `LockedAt` along with a read/write accessor for the
-    /// held state.
+    /// This is [`LockedAt::with_lock`] with the resulting `LockedAt` and guard
+    /// passed to `f` instead of returned, so nested critical sections read
+    /// top-to-bottom without rebinding `locked` at each level. Both are
+    /// released when `f` returns, unless it moves the guard into its result.
+    ///
+    /// This is also how to use a blocking lock from async code. Guards for
+    /// locks like [`std::sync::Mutex`] are usually `!Send`, and holding one
+    /// across an `.await` makes the enclosing future `!Send` too, which
+    /// multi-threaded executors reject at the spawn site. Since `f` is a
+    /// synchronous closure, the guard is released before the caller can reach
+    /// the next yield point.
+    pub fn lock_scoped<'a, NewLock: LockAfter<L> + 'a + MutexLockLevel, R>(
+        &'a mut self,
+        t: &'a NewLock::Mutex,
+        f: impl FnOnce(
+            &mut LockedAt<'a, NewLock>,
+            LevelGuard<<NewLock::Mutex as MutexLock>::Guard<'a>>,
+        ) -> R,
+    ) -> Result<R, <NewLock::Mutex as MutexLock>::Error<'a>> {
+        let (mut locked, guard) = self.with_lock::<NewLock>(t)?;
+        Ok(f(&mut locked, guard))
+    }
+
+    /// Attempts to acquire a lock on `NewLock` state that might not exist.
+    ///
+    /// Like [`LockedAt::with_lock`] if `t` is `Some`. If it is `None`, nothing
+    /// is acquired and `Ok(None)` is returned, so there's no transition to
+    /// `NewLock` either.
+    #[allow(clippy::type_complexity)]
+    pub fn with_lock_opt<'a, NewLock: LockAfter<L> + MutexLockLevel>(
+        &'a mut self,
+        t: Option<&'a NewLock::Mutex>,
+    ) -> Result<
+        Option<(
+            LockedAt<'a, NewLock>,
+            LevelGuard<<NewLock::Mutex as MutexLock>::Guard<'a>>,
+        )>,
+        <NewLock::Mutex as MutexLock>::Error<'a>,
+    > {
+        t.map(|t| self.with_lock::<NewLock>(t)).transpose()
+    }
+
+    /// Acquires a lock on `NewLock` only for its ordering and exclusion.
+    ///
+    /// Like [`LockedAt::with_lock`], but the returned [`OrderGuard`] doesn't
+    /// give access to the protected value. This is for locks like
+    /// `Mutex<()>` that guard invariants spanning other state rather than a
+    /// value of their own.
     ///
     /// If no further `LockedAt` calls need to be made after this one, consider
-    /// using [`LockedAt::write_lock`] instead.
-    pub async fn wait_for_write<'a, NewLock: LockAfter<L> + AsyncRwLockLevel>(
+    /// using [`LockedAt::acquire_order`] instead.
+    #[allow(clippy::type_complexity)]
+    pub fn with_acquire_order<'a, NewLock: LockAfter<L> + MutexLockLevel>(
         &'a mut self,
-        t: &'a NewLock::RwLock,
-    ) -> (
-        LockedAt<'a, NewLock>,
-        <NewLock::RwLock as AsyncRwLock>::WriteGuard<'a>,
-    ) {
-        let guard = t.write().await;
-        (LockedAt(PhantomData), guard)
+        t: &'a NewLock::Mutex,
+    ) -> Result<
+        (LockedAt<'a, NewLock>, OrderGuard<'a, NewLock::Mutex>),
+        <NewLock::Mutex as MutexLock>::Error<'a>,
+    > {
+        let (locked, guard) = self.with_lock::<NewLock>(t)?;
+        Ok((locked, OrderGuard::new(guard)))
+    }
+
+    /// Attempts to acquire a lock on `NewLock` state from a lock section.
+    ///
+    /// Behaves like [`LockedAt::with_lock`], but requires a [`LockSection`]
+    /// token. Since those are only available inside functions annotated with
+    /// [`#[lock_section]`](macro@crate::lock_section), calling this elsewhere
+    /// is a compile error. The other acquisition methods don't take the token,
+    /// so this only restricts code that acquires locks through this method.
+    #[cfg(feature = "macros")]
+    #[allow(clippy::type_complexity)]
+    pub fn with_lock_in_section<'a, NewLock: LockAfter<L> + MutexLockLevel>(
+        &'a mut self,
+        _section: &LockSection<'_>,
+        t: &'a NewLock::Mutex,
+    ) -> Result<
+        (
+            LockedAt<'a, NewLock>,
+            LevelGuard<<NewLock::Mutex as MutexLock>::Guard<'a>>,
+        ),
+        <NewLock::Mutex as MutexLock>::Error<'a>,
+    > {
+        self.with_lock::<NewLock>(t)
     }
 }
 
+/// Acquires a chain of locks, each after the previous one.
+///
+/// `lock_path!(let pattern = locked; (A, &a), (B, &b), ...);` calls
+/// [`LockedAt::with_lock`] for each level in turn, starting from `locked`,
+/// and binds `pattern` to the innermost `LockedAt` and a tuple of the guards
+/// in acquisition order. Each step has the same [`LockAfter`] bound as the
+/// call it replaces, so a path that couldn't be written out by hand doesn't
+/// compile. A step written as `(C, read &c)` or `(C, write &c)` acquires an
+/// [`RwLock`] level with [`LockedAt::with_read_lock`] or
+/// [`LockedAt::with_write_lock`] instead.
+///
+/// This expands to `let` statements rather than an expression, because the
+/// intermediate `LockedAt`s have to stay alive in the enclosing block for as
+/// long as the innermost one is used.
+///
+/// A step followed by `?`, like `(B, &b)?`, propagates its acquisition error
+/// from the enclosing function with `?`. Since errors can borrow the lock
+/// (`std`'s `PoisonError` holds the guard), this
+/// only works for error types that can leave the function, like
+/// [`Infallible`](core::convert::Infallible) or
+/// [`BorrowMutError`](core::cell::BorrowMutError); otherwise, chain the calls
+/// by hand.
+///
+/// # Panics
+///
+/// Panics with the error if an acquisition for a step without `?` fails.
+///
+/// ```
+/// use std::sync::{Mutex, RwLock};
+///
+/// use lock_ordering::{
+///     lock::{MutexLockLevel, RwLockLevel},
+///     lock_path,
+///     relation::LockAfter,
+///     LockLevel, LockedAt, MutualExclusion, ReadWrite, Unlocked,
+/// };
+///
+/// struct Accounts;
+/// struct Ledger;
+/// struct Audit;
+///
+/// impl LockAfter<Unlocked> for Accounts {}
+/// impl LockAfter<Accounts> for Ledger {}
+/// impl LockAfter<Ledger> for Audit {}
+///
+/// impl LockLevel for Accounts {
+///     type Method = MutualExclusion;
+/// }
+/// impl MutexLockLevel for Accounts {
+///     type Mutex = Mutex<u32>;
+/// }
+/// impl LockLevel for Ledger {
+///     type Method = ReadWrite;
+/// }
+/// impl RwLockLevel for Ledger {
+///     type RwLock = RwLock<Vec<u32>>;
+/// }
+/// impl LockLevel for Audit {
+///     type Method = MutualExclusion;
+/// }
+/// impl MutexLockLevel for Audit {
+///     type Mutex = Mutex<usize>;
+/// }
+///
+/// let (accounts, ledger, audit) = (Mutex::new(1), RwLock::new(vec![2]), Mutex::new(0));
+/// let mut locked = LockedAt::new();
+///
+/// lock_path!(
+///     let (mut locked, (accounts, ledger)) = locked;
+///     (Accounts, &accounts),
+///     (Ledger, read &ledger),
+/// );
+/// *locked.lock::<Audit>(&audit).unwrap() = *accounts as usize + ledger.len();
+/// ```
+///
+/// Skipping from `Unlocked` straight to `Ledger` is rejected, as it would be
+/// with `with_lock`:
+///
+/// ```compile_fail
+/// # use std::sync::{Mutex, RwLock};
+/// # use lock_ordering::{
+/// #     lock::{MutexLockLevel, RwLockLevel},
+/// #     lock_path,
+/// #     relation::LockAfter,
+/// #     LockLevel, LockedAt, MutualExclusion, ReadWrite, Unlocked,
+/// # };
+/// # struct Accounts;
+/// # struct Ledger;
+/// # impl LockAfter<Unlocked> for Accounts {}
+/// # impl LockAfter<Accounts> for Ledger {}
+/// # impl LockLevel for Accounts {
+/// #     type Method = MutualExclusion;
+/// # }
+/// # impl MutexLockLevel for Accounts {
+/// #     type Mutex = Mutex<u32>;
+/// # }
+/// # impl LockLevel for Ledger {
+/// #     type Method = ReadWrite;
+/// # }
+/// # impl RwLockLevel for Ledger {
+/// #     type RwLock = RwLock<Vec<u32>>;
+/// # }
+/// let ledger = RwLock::new(vec![2]);
+/// let mut locked = LockedAt::new();
+///
+/// lock_path!(let (_locked, (_ledger,)) = locked; (Ledger, read &ledger));
+/// ```
+///
+/// [`LockAfter`]: crate::relation::LockAfter
+/// [`RwLock`]: crate::lock::RwLock
+#[macro_export]
+macro_rules! lock_path {
+    (@step $locked:ident [$($guard:ident)*] [$($pat:tt)*]) => {
+        let $($pat)* = ($locked, ($($guard,)*));
+    };
+    (@step $locked:ident [$($guard:ident)*] [$($pat:tt)*] ($level:ty, read $t:expr)? $(, $($rest:tt)*)?) => {
+        $crate::lock_path!(@acquire $locked [$($guard)*] [$($pat)*] [?] with_read_lock $level, $t; $($($rest)*)?);
+    };
+    (@step $locked:ident [$($guard:ident)*] [$($pat:tt)*] ($level:ty, read $t:expr) $(, $($rest:tt)*)?) => {
+        $crate::lock_path!(@acquire $locked [$($guard)*] [$($pat)*] [] with_read_lock $level, $t; $($($rest)*)?);
+    };
+    (@step $locked:ident [$($guard:ident)*] [$($pat:tt)*] ($level:ty, write $t:expr)? $(, $($rest:tt)*)?) => {
+        $crate::lock_path!(@acquire $locked [$($guard)*] [$($pat)*] [?] with_write_lock $level, $t; $($($rest)*)?);
+    };
+    (@step $locked:ident [$($guard:ident)*] [$($pat:tt)*] ($level:ty, write $t:expr) $(, $($rest:tt)*)?) => {
+        $crate::lock_path!(@acquire $locked [$($guard)*] [$($pat)*] [] with_write_lock $level, $t; $($($rest)*)?);
+    };
+    (@step $locked:ident [$($guard:ident)*] [$($pat:tt)*] ($level:ty, $t:expr)? $(, $($rest:tt)*)?) => {
+        $crate::lock_path!(@acquire $locked [$($guard)*] [$($pat)*] [?] with_lock $level, $t; $($($rest)*)?);
+    };
+    (@step $locked:ident [$($guard:ident)*] [$($pat:tt)*] ($level:ty, $t:expr) $(, $($rest:tt)*)?) => {
+        $crate::lock_path!(@acquire $locked [$($guard)*] [$($pat)*] [] with_lock $level, $t; $($($rest)*)?);
+    };
+    (@acquire $locked:ident [$($guard:ident)*] [$($pat:tt)*] [$($try:tt)?] $method:ident $level:ty, $t:expr; $($rest:tt)*) => {
+        // `locked` and `guard` are distinct from the ones bound by other
+        // steps, since each comes from a separate expansion.
+        #[allow(unused_mut)]
+        let (mut locked, guard) =
+            $crate::lock_path!(@result [$($try)?] $level, $locked.$method::<$level>($t));
+        $crate::lock_path!(@step locked [$($guard)* guard] [$($pat)*] $($rest)*);
+    };
+    (@result [?] $level:ty, $result:expr) => {
+        $result?
+    };
+    (@result [] $level:ty, $result:expr) => {
+        match $result {
+            ::core::result::Result::Ok(acquired) => acquired,
+            ::core::result::Result::Err(e) => ::core::panic!(
+                "failed to acquire {}: {:?}",
+                ::core::any::type_name::<$level>(),
+                e
+            ),
+        }
+    };
+    (let $pat:pat = $locked:expr; $($steps:tt)+) => {
+        let root = &mut $locked;
+        $crate::lock_path!(@step root [] [$pat] $($steps)+);
+    };
+}
+
 // Convenience methods for accessing leaf locks in the ordering tree.
-#[cfg(feature = "async")]
 impl<L> LockedAt<'_, L> {
-    /// Asynchronously provides access to an [AsyncMutexLock]'s state.
-    pub async fn wait_lock<'a, NewLock: LockAfter<L> + 'a + AsyncMutexLockLevel>(
+    /// Provides access to a [MutexLock]'s state.
+    pub fn lock<'a, NewLock: LockAfter<L> + 'a + MutexLockLevel>(
         &'a mut self,
         t: &'a NewLock::Mutex,
-    ) -> <NewLock::Mutex as AsyncMutexLock>::Guard<'a> {
-        let (_locked, guard) = self.wait_for_lock::<NewLock>(t).await;
-        guard
+    ) -> Result<
+        LevelGuard<<NewLock::Mutex as MutexLock>::Guard<'a>>,
+        <NewLock::Mutex as MutexLock>::Error<'a>,
+    > {
+        self.with_lock::<NewLock>(t).map(|(_locked, guard)| guard)
     }
 
-    /// Asynchronously provides read access to an [AsyncRwLock]'s state.
-    pub async fn wait_read<'a, NewLock: LockAfter<L> + AsyncRwLockLevel + 'a>(
+    /// Provides access to a [MutexLock]'s state if it isn't currently held.
+    pub fn try_lock<'a, NewLock: LockAfter<L> + 'a + MutexLockLevel>(
         &'a mut self,
-        t: &'a NewLock::RwLock,
-    ) -> <NewLock::RwLock as AsyncRwLock>::ReadGuard<'a> {
-        let (_locked, guard) = self.wait_for_read::<NewLock>(t).await;
-        guard
+        t: &'a NewLock::Mutex,
+    ) -> Result<
+        LevelGuard<<NewLock::Mutex as MutexLock>::Guard<'a>>,
+        <NewLock::Mutex as MutexLock>::TryError<'a>,
+    > {
+        self.with_try_lock::<NewLock>(t)
+            .map(|(_locked, guard)| guard)
     }
 
-    /// Asynchronously provides read/write access to an [AsyncRwLock]'s state.
-    pub async fn wait_write<'a, NewLock: LockAfter<L> + AsyncRwLockLevel + 'a>(
+    /// Provides access to a [MutexLock]'s state if it becomes available within
+    /// `timeout`.
+    pub fn lock_timeout<'a, NewLock: LockAfter<L> + 'a + MutexLockLevel>(
+        &'a mut self,
+        t: &'a NewLock::Mutex,
+        timeout: Duration,
+    ) -> Result<
+        LevelGuard<<NewLock::Mutex as MutexLock>::Guard<'a>>,
+        <NewLock::Mutex as MutexLockTimeout>::TimeoutError<'a>,
+    >
+    where
+        NewLock::Mutex: MutexLockTimeout,
+    {
+        self.with_lock_timeout::<NewLock>(t, timeout)
+            .map(|(_locked, guard)| guard)
+    }
+
+    /// Provides read access to a [RwLock]'s state if it isn't currently held
+    /// for writing.
+    pub fn try_read_lock<'a, NewLock: LockAfter<L> + RwLockLevel + 'a>(
         &'a mut self,
         t: &'a NewLock::RwLock,
-    ) -> <NewLock::RwLock as AsyncRwLock>::WriteGuard<'a> {
-        let (_locked, guard) = self.wait_for_write::<NewLock>(t).await;
-        guard
+    ) -> Result<
+        LevelGuard<<NewLock::RwLock as RwLock>::ReadGuard<'a>>,
+        <NewLock::RwLock as RwLock>::TryReadError<'a>,
+    > {
+        self.with_try_read_lock::<NewLock>(t)
+            .map(|(_locked, guard)| guard)
+    }
+
+    /// Provides read/write access to a [RwLock]'s state if it isn't currently
+    /// held.
+    pub fn try_write_lock<'a, NewLock: LockAfter<L> + RwLockLevel + 'a>(
+        &'a mut self,
+        t: &'a NewLock::RwLock,
+    ) -> Result<
+        LevelGuard<<NewLock::RwLock as RwLock>::WriteGuard<'a>>,
+        <NewLock::RwLock as RwLock>::TryWriteError<'a>,
+    > {
+        self.with_try_write_lock::<NewLock>(t)
+            .map(|(_locked, guard)| guard)
+    }
+
+    /// Provides access to the state of a [MutexLock] reached through a
+    /// [`LockRef`].
+    ///
+    /// This is like [`LockedAt::lock`], but accepts anything that dereferences
+    /// to the lock, like an `Arc` or a custom smart pointer.
+    pub fn lock_ref<
+        'a,
+        NewLock: LockAfter<L> + 'a + MutexLockLevel,
+        R: LockRef<NewLock> + ?Sized,
+    >(
+        &'a mut self,
+        r: &'a R,
+    ) -> Result<
+        LevelGuard<<NewLock::Mutex as MutexLock>::Guard<'a>>,
+        <NewLock::Mutex as MutexLock>::Error<'a>,
+    > {
+        self.lock::<NewLock>(r.mutex())
+    }
+
+    /// Provides access to a [MutexLock]'s state along with the level's name.
+    ///
+    /// Like [`LockedAt::lock`], but also returns the name of `NewLock` as
+    /// produced by [`core::any::type_name`]. This is convenient for attaching
+    /// the level to log messages at the call site.
+    #[allow(clippy::type_complexity)]
+    pub fn lock_named<'a, NewLock: LockAfter<L> + 'a + MutexLockLevel>(
+        &'a mut self,
+        t: &'a NewLock::Mutex,
+    ) -> Result<
+        (
+            LevelGuard<<NewLock::Mutex as MutexLock>::Guard<'a>>,
+            &'static str,
+        ),
+        <NewLock::Mutex as MutexLock>::Error<'a>,
+    > {
+        self.lock::<NewLock>(t)
+            .map(|guard| (guard, core::any::type_name::<NewLock>()))
+    }
+
+    /// Provides access to part of a [MutexLock]'s state.
+    ///
+    /// See [`LockedAt::with_lock_map`].
+    #[allow(clippy::type_complexity)]
+    pub fn lock_map<'a, NewLock: LockAfter<L> + 'a + MutexLockLevel, U: ?Sized + 'a>(
+        &'a mut self,
+        t: &'a NewLock::Mutex,
+        f: impl for<'g> FnOnce(
+            &'g mut <<NewLock::Mutex as MutexLock>::Guard<'a> as Deref>::Target,
+        ) -> &'g mut U,
+    ) -> Result<
+        LevelGuard<<<NewLock::Mutex as MutexLock>::Guard<'a> as MappableGuard<'a>>::Mapped<U>>,
+        <NewLock::Mutex as MutexLock>::Error<'a>,
+    >
+    where
+        <NewLock::Mutex as MutexLock>::Guard<'a>: MappableGuard<'a>,
+    {
+        self.with_lock_map::<NewLock, U>(t, f)
+            .map(|(_locked, guard)| guard)
+    }
+
+    /// Provides access to one of two [MutexLock]s' state, chosen at runtime.
+    ///
+    /// See [`LockedAt::with_lock_either`].
+    #[allow(clippy::type_complexity)]
+    pub fn lock_either<
+        'a,
+        B: LockAfter<L> + 'a + MutexLockLevel,
+        C: LockAfter<L> + 'a + MutexLockLevel,
+    >(
+        &'a mut self,
+        lock: Either<&'a B::Mutex, &'a C::Mutex>,
+    ) -> Result<
+        Either<
+            LevelGuard<<B::Mutex as MutexLock>::Guard<'a>>,
+            LevelGuard<<C::Mutex as MutexLock>::Guard<'a>>,
+        >,
+        Either<<B::Mutex as MutexLock>::Error<'a>, <C::Mutex as MutexLock>::Error<'a>>,
+    > {
+        self.with_lock_either::<B, C>(lock)
+            .map(|(_locked, guard)| guard)
+    }
+
+    /// Provides read access to a [RwLock]'s state.
+    pub fn read_lock<'a, NewLock: LockAfter<L> + RwLockLevel + 'a>(
+        &'a mut self,
+        t: &'a NewLock::RwLock,
+    ) -> Result<
+        LevelGuard<<NewLock::RwLock as RwLock>::ReadGuard<'a>>,
+        <NewLock::RwLock as RwLock>::ReadError<'a>,
+    > {
+        self.with_read_lock::<NewLock>(t)
+            .map(|(_locked, guard)| guard)
+    }
+
+    /// Provides read/write access to a [RwLock]'s state.
+    pub fn write_lock<'a, NewLock: LockAfter<L> + RwLockLevel + 'a>(
+        &'a mut self,
+        t: &'a NewLock::RwLock,
+    ) -> Result<
+        LevelGuard<<NewLock::RwLock as RwLock>::WriteGuard<'a>>,
+        <NewLock::RwLock as RwLock>::WriteError<'a>,
+    > {
+        self.with_write_lock::<NewLock>(t)
+            .map(|(_locked, guard)| guard)
+    }
+
+    /// Acquires a [MutexLock] only for its ordering and exclusion.
+    pub fn acquire_order<'a, NewLock: LockAfter<L> + 'a + MutexLockLevel>(
+        &'a mut self,
+        t: &'a NewLock::Mutex,
+    ) -> Result<OrderGuard<'a, NewLock::Mutex>, <NewLock::Mutex as MutexLock>::Error<'a>> {
+        self.with_acquire_order::<NewLock>(t)
+            .map(|(_locked, guard)| guard)
+    }
+
+    /// Consumes a [MutexLock] and returns the value it protects.
+    ///
+    /// This is intended for teardown, when the state is being dismantled and
+    /// no other threads can access it. Since the lock is taken by value, no
+    /// ordering applies past this point, so `self` is consumed too. Locks that
+    /// were poisoned have their values recovered anyway.
+    pub fn into_value<NewLock: LockAfter<L> + MutexLockLevel>(
+        self,
+        t: NewLock::Mutex,
+    ) -> <NewLock::Mutex as IntoInnerLock>::Inner
+    where
+        NewLock::Mutex: IntoInnerLock,
+    {
+        t.into_inner()
+    }
+
+    /// Provides access to a [MutexLock]'s state without locking it.
+    ///
+    /// This is for setup code that owns the state exclusively, for example
+    /// while initializing it before sharing it with other threads. Taking
+    /// `t` by unique reference guarantees that nothing else can be holding
+    /// the lock, so the value is accessed directly. Locks that were poisoned
+    /// have their values returned anyway.
+    pub fn lock_mut<'a, NewLock: LockAfter<L> + 'a + MutexLockLevel>(
+        &'a mut self,
+        t: &'a mut NewLock::Mutex,
+    ) -> &'a mut <NewLock::Mutex as GetMutLock>::Inner
+    where
+        NewLock::Mutex: GetMutLock,
+    {
+        t.get_mut()
+    }
+
+    /// Provides read access to a [RwLock]'s state, or a copy of it.
+    ///
+    /// Acquires a read lock and calls `clone_if` with the locked value. If it
+    /// returns `true`, the value is cloned and the lock released before
+    /// returning the copy; otherwise the guard is returned and the lock stays
+    /// held. This lets callers clone small values to keep lock hold times
+    /// short while borrowing large ones.
+    #[allow(clippy::type_complexity)]
+    pub fn read_cow<'a, NewLock: LockAfter<L> + RwLockLevel + 'a, T: Clone>(
+        &'a mut self,
+        t: &'a NewLock::RwLock,
+        clone_if: impl FnOnce(&T) -> bool,
+    ) -> Result<
+        CowGuard<LevelGuard<<NewLock::RwLock as RwLock>::ReadGuard<'a>>, T>,
+        <NewLock::RwLock as RwLock>::ReadError<'a>,
+    >
+    where
+        <NewLock::RwLock as RwLock>::ReadGuard<'a>: Deref<Target = T>,
+    {
+        let guard = self.read_lock::<NewLock>(t)?;
+        Ok(if clone_if(&guard) {
+            CowGuard::Owned(T::clone(&guard))
+        } else {
+            CowGuard::Borrowed(guard)
+        })
+    }
+
+    /// Provides read or read/write access to a [RwLock]'s state.
+    ///
+    /// Acquires a write lock if `WRITE` is `true` and a read lock otherwise,
+    /// wrapping the guard in the corresponding [`RwGuard`] variant. This lets
+    /// generic code choose the access mode with a const parameter instead of
+    /// duplicating call sites.
+    #[allow(clippy::type_complexity)]
+    pub fn rw_lock<'a, NewLock: LockAfter<L> + RwLockLevel + 'a, const WRITE: bool>(
+        &'a mut self,
+        t: &'a NewLock::RwLock,
+    ) -> Result<
+        RwGuard<
+            LevelGuard<<NewLock::RwLock as RwLock>::ReadGuard<'a>>,
+            LevelGuard<<NewLock::RwLock as RwLock>::WriteGuard<'a>>,
+        >,
+        RwError<
+            <NewLock::RwLock as RwLock>::ReadError<'a>,
+            <NewLock::RwLock as RwLock>::WriteError<'a>,
+        >,
+    > {
+        if WRITE {
+            self.write_lock::<NewLock>(t)
+                .map(RwGuard::Write)
+                .map_err(RwError::Write)
+        } else {
+            self.read_lock::<NewLock>(t)
+                .map(RwGuard::Read)
+                .map_err(RwError::Read)
+        }
+    }
+
+    /// Acquires a [MutexLock] and immediately releases it.
+    ///
+    /// This waits until the lock is available without keeping it, e.g. to
+    /// make sure that a holder has finished its critical section. Since the
+    /// lock isn't held afterwards, the returned `LockedAt` is at the same
+    /// level `L` as `self` rather than at `NewLock`.
+    pub fn touch<'a, NewLock: LockAfter<L> + 'a + MutexLockLevel>(
+        &'a mut self,
+        t: &'a NewLock::Mutex,
+    ) -> Result<LockedAt<'a, L>, <NewLock::Mutex as MutexLock>::Error<'a>> {
+        let guard = self.lock::<NewLock>(t)?;
+        drop(guard);
+        Ok(LockedAt(PhantomData))
+    }
+
+    /// Locks each of several [MutexLock]s at the same level, one at a time.
+    ///
+    /// For each lock in `children`, acquires it, calls `f` with the guard, and
+    /// releases it before moving on to the next one. The levels held by
+    /// `self` stay held throughout, so this visits the children of a locked
+    /// node without re-acquiring the node for each one. Stops at and returns
+    /// the first acquisition error.
+    pub fn for_each_child<'a, ChildLevel, C>(
+        &'a mut self,
+        children: C,
+        mut f: impl FnMut(&mut <ChildLevel::Mutex as MutexLock>::Guard<'a>),
+    ) -> Result<(), <ChildLevel::Mutex as MutexLock>::Error<'a>>
+    where
+        ChildLevel: LockAfter<L> + MutexLockLevel + 'a,
+        C: IntoIterator<Item = &'a ChildLevel::Mutex>,
+    {
+        for child in children {
+            let mut guard = lock_level::<ChildLevel, _>(child)?;
+            f(backend_guard_mut(&mut guard));
+        }
+        Ok(())
+    }
+}
+
+// Methods for holding multiple locks at the same level.
+//
+// Holding two locks at the same level is only safe if every thread acquires
+// them in the same order. These methods use the locks' addresses as that
+// canonical order.
+impl<L> LockedAt<'_, L> {
+    /// Locks two [MutexLock]s at the same level and provides access to both.
+    ///
+    /// The locks are acquired in order of their addresses, so concurrent
+    /// callers passing the same pair in either order can't deadlock with each
+    /// other. `f` receives the locked values in argument order, `a` then `b`,
+    /// and both locks are released when it returns.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `a` and `b` are the same lock.
+    pub fn with_pair_ordered<'a, NewLock: LockAfter<L> + MutexLockLevel, R>(
+        &'a mut self,
+        a: &'a NewLock::Mutex,
+        b: &'a NewLock::Mutex,
+        f: impl FnOnce(
+            &mut <<NewLock::Mutex as MutexLock>::Guard<'a> as Deref>::Target,
+            &mut <<NewLock::Mutex as MutexLock>::Guard<'a> as Deref>::Target,
+        ) -> R,
+    ) -> Result<R, <NewLock::Mutex as MutexLock>::Error<'a>>
+    where
+        <NewLock::Mutex as MutexLock>::Guard<'a>: DerefMut,
+    {
+        assert!(
+            !core::ptr::eq(a, b),
+            "with_pair_ordered called with the same lock twice"
+        );
+
+        let a_first = address(a) < address(b);
+        let (first, second) = if a_first { (a, b) } else { (b, a) };
+        // Both locks are at `NewLock`, so only the first acquisition is
+        // checked.
+        let mut first = lock_level::<NewLock, _>(first)?;
+        let mut second = crate::instrument::lock(core::any::type_name::<NewLock>(), second)
+            .map(level_guard::<NewLock, _>)?;
+
+        let (a, b) = if a_first {
+            (&mut *first, &mut *second)
+        } else {
+            (&mut *second, &mut *first)
+        };
+        Ok(f(a, b))
+    }
+
+    /// Locks two [MutexLock]s at successive levels and holds both.
+    ///
+    /// `First` is acquired after `L`, and `Second` after `First`, in that
+    /// order. This is the same as nesting [`with_lock`](LockedAt::with_lock)
+    /// calls, but returns a single `LockedAt` at `Second` along with both
+    /// guards, so the pair can be passed around together. Since `Second`
+    /// comes after `First`, any level acquired from the returned `LockedAt`
+    /// is also ordered after both.
+    ///
+    /// If acquiring `second` fails, `first` is released before the error is
+    /// returned.
+    #[allow(clippy::type_complexity)]
+    pub fn lock_pair<
+        'a,
+        First: LockAfter<L> + MutexLockLevel,
+        Second: LockAfter<First> + MutexLockLevel,
+    >(
+        &'a mut self,
+        first: &'a First::Mutex,
+        second: &'a Second::Mutex,
+    ) -> Result<
+        (
+            LockedAt<'a, Second>,
+            (
+                LevelGuard<<First::Mutex as MutexLock>::Guard<'a>>,
+                LevelGuard<<Second::Mutex as MutexLock>::Guard<'a>>,
+            ),
+        ),
+        PairError<<First::Mutex as MutexLock>::Error<'a>, <Second::Mutex as MutexLock>::Error<'a>>,
+    > {
+        let first = lock_level::<First, _>(first).map_err(PairError::First)?;
+        let second = lock_level::<Second, _>(second).map_err(PairError::Second)?;
+        Ok((LockedAt(PhantomData), (first, second)))
+    }
+
+    /// Locks one of two [MutexLock]s at sibling levels, chosen at runtime.
+    ///
+    /// `lock` selects which lock to acquire: the one for `B` or the one for
+    /// `C`, both of which come after `L`. The returned guard is the
+    /// corresponding variant of [`Either`]. The returned `LockedAt` is at
+    /// [`OneOf<B, C>`], so code after this point doesn't depend on which
+    /// level was chosen, and can only acquire levels declared with
+    /// [`impl_lock_after_one_of!`](crate::impl_lock_after_one_of).
+    #[allow(clippy::type_complexity)]
+    pub fn with_lock_either<
+        'a,
+        B: LockAfter<L> + MutexLockLevel,
+        C: LockAfter<L> + MutexLockLevel,
+    >(
+        &'a mut self,
+        lock: Either<&'a B::Mutex, &'a C::Mutex>,
+    ) -> Result<
+        (
+            LockedAt<'a, OneOf<B, C>>,
+            Either<
+                LevelGuard<<B::Mutex as MutexLock>::Guard<'a>>,
+                LevelGuard<<C::Mutex as MutexLock>::Guard<'a>>,
+            >,
+        ),
+        Either<<B::Mutex as MutexLock>::Error<'a>, <C::Mutex as MutexLock>::Error<'a>>,
+    > {
+        let guard = match lock {
+            Either::First(b) => lock_level::<B, _>(b)
+                .map(Either::First)
+                .map_err(Either::First)?,
+            Either::Second(c) => lock_level::<C, _>(c)
+                .map(Either::Second)
+                .map_err(Either::Second)?,
+        };
+        Ok((LockedAt(PhantomData), guard))
+    }
+
+    /// Locks every [MutexLock] in a slice, all at the same level.
+    ///
+    /// The elements of a slice are laid out in order of increasing address,
+    /// so locking them by index is the same canonical address order that the
+    /// other methods here use. Concurrent callers locking overlapping slices
+    /// of the same storage therefore can't deadlock with each other. The
+    /// guards are returned in slice order. If acquiring any of the locks
+    /// fails, the ones that were already acquired are released and the error
+    /// is returned.
+    ///
+    /// The returned `LockedAt` is at `NewLock`, so no further locks at that
+    /// level can be acquired while the guards are held.
+    #[cfg(feature = "std")]
+    #[allow(clippy::type_complexity)]
+    pub fn lock_all<'a, NewLock: LockAfter<L> + MutexLockLevel>(
+        &'a mut self,
+        locks: &'a [NewLock::Mutex],
+    ) -> Result<
+        (
+            LockedAt<'a, NewLock>,
+            std::vec::Vec<LevelGuard<<NewLock::Mutex as MutexLock>::Guard<'a>>>,
+        ),
+        <NewLock::Mutex as MutexLock>::Error<'a>,
+    >
+    where
+        NewLock::Mutex: Sized,
+    {
+        check_acquire::<NewLock>();
+        let level = core::any::type_name::<NewLock>();
+        let guards = locks
+            .iter()
+            .map(|t| crate::instrument::lock(level, t).map(level_guard::<NewLock, _>))
+            .collect::<Result<_, _>>()?;
+        Ok((LockedAt(PhantomData), guards))
+    }
+
+    /// Acquires exclusive locks on several [RwLock]s at the same level.
+    ///
+    /// The locks are acquired in order of their addresses, so concurrent
+    /// callers writing overlapping sets of locks can't deadlock with each
+    /// other regardless of the order they list them in. The returned guards
+    /// are in the same order as `locks`. If acquiring any of the locks fails,
+    /// the ones that were already acquired are released and the error is
+    /// returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the same lock appears more than once in `locks`.
+    #[cfg(feature = "std")]
+    #[allow(clippy::type_complexity)]
+    pub fn write_all_sorted<'a, NewLock: LockAfter<L> + RwLockLevel>(
+        &'a mut self,
+        locks: &[&'a NewLock::RwLock],
+    ) -> Result<
+        (
+            LockedAt<'a, NewLock>,
+            std::vec::Vec<LevelGuard<<NewLock::RwLock as RwLock>::WriteGuard<'a>>>,
+        ),
+        <NewLock::RwLock as RwLock>::WriteError<'a>,
+    > {
+        let mut order: std::vec::Vec<usize> = (0..locks.len()).collect();
+        order.sort_unstable_by_key(|&i| address(locks[i]));
+        assert!(
+            order
+                .windows(2)
+                .all(|w| address(locks[w[0]]) != address(locks[w[1]])),
+            "write_all_sorted called with the same lock twice"
+        );
+
+        check_acquire::<NewLock>();
+        let level = core::any::type_name::<NewLock>();
+        let mut guards: std::vec::Vec<_> = locks.iter().map(|_| None).collect();
+        for i in order {
+            let guard = crate::instrument::acquire(level, || locks[i].write())?;
+            guards[i] = Some(level_guard::<NewLock, _>(guard));
+        }
+        Ok((
+            LockedAt(PhantomData),
+            guards.into_iter().map(Option::unwrap).collect(),
+        ))
+    }
+}
+
+impl<L: RwLockLevel> LockedAt<'_, L>
+where
+    L::RwLock: RecursiveReadRwLock,
+{
+    /// Provides read access to a lock at the currently held level `L`.
+    ///
+    /// The other methods on `LockedAt` only acquire levels that come after
+    /// `L`, so a lock can never be acquired at a level that is already held.
+    /// This relaxes that for reads of a [`RecursiveReadRwLock`]: while
+    /// holding level `L` for reading, more locks at `L` (including the same
+    /// one) can be read-locked, since those reads don't wait for queued
+    /// writers.
+    ///
+    /// `LockedAt` doesn't track whether `L` was acquired for reading or
+    /// writing. Calling this while holding `L` for writing can deadlock, so
+    /// only use it below a [`with_read_lock`](LockedAt::with_read_lock).
+    pub fn read_recursive<'a>(
+        &'a mut self,
+        t: &'a L::RwLock,
+    ) -> Result<
+        LevelGuard<<L::RwLock as RwLock>::ReadGuard<'a>>,
+        <L::RwLock as RwLock>::ReadError<'a>,
+    > {
+        // `L` is already held, so this isn't checked like a new acquisition.
+        crate::instrument::acquire(core::any::type_name::<L>(), || t.read_recursive())
+            .map(level_guard::<L, _>)
+    }
+}
+
+impl<L: MutexLockLevel> LockedAt<'_, L>
+where
+    L::Mutex: ReentrantMutexLock,
+{
+    /// Re-acquires the [`ReentrantMutexLock`] that is held at level `L`.
+    ///
+    /// The other methods on `LockedAt` only acquire levels that come after
+    /// `L`, and take `&mut self` so that `L` can't be acquired again while
+    /// it's held. Reentrant mutexes can be locked again by the thread that
+    /// holds them without blocking, so this takes `&self` instead: the
+    /// existing `LockedAt` for `L` can be shared by any number of reentrant
+    /// acquisitions, and levels after `L` can still be acquired once those
+    /// guards are dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `t` is not held by the current thread. Locking a different
+    /// mutex at level `L` could block, which would break the lock ordering.
+    pub fn lock_reentrant<'a>(
+        &'a self,
+        t: &'a L::Mutex,
+    ) -> Result<LevelGuard<<L::Mutex as MutexLock>::Guard<'a>>, <L::Mutex as MutexLock>::Error<'a>>
+    {
+        assert!(
+            t.is_owned_by_current_thread(),
+            "reentrant lock for {} is not held by the current thread",
+            core::any::type_name::<L>()
+        );
+        // `L` is already held, so this isn't checked like a new acquisition.
+        crate::instrument::lock(core::any::type_name::<L>(), t).map(level_guard::<L, _>)
+    }
+}
+
+impl<L: MutexLockLevel> LockedAt<'_, L> {
+    /// Releases the lock held at level `L`, runs `f`, and then re-acquires it.
+    ///
+    /// `guard` comes from [`with_lock_suspendable`](LockedAt::with_lock_suspendable),
+    /// so it is the only guard held at `L` and knows which lock to acquire
+    /// again. Its lock is released before `f` runs so that other waiters can
+    /// take it, and locked again once `f` returns. The `LockedAt` stays at
+    /// `L` throughout. While suspended, `f` can still acquire levels after
+    /// `L`: those are also after every level held before `L`, so acquiring
+    /// them without holding `L` keeps the ordering. Levels before `L` can't be
+    /// acquired, since it isn't known which of them are still held.
+    ///
+    /// Returns `f`'s result along with the new guard. State protected by the
+    /// lock may have been changed by other threads in the meantime.
+    pub fn suspend_lock<'g, R>(
+        &mut self,
+        guard: SuspendableGuard<'g, L>,
+        f: impl FnOnce(&mut LockedAt<'_, L>) -> R,
+    ) -> Result<(R, SuspendableGuard<'g, L>), <L::Mutex as MutexLock>::Error<'g>> {
+        let t = guard.release();
+        let result = f(&mut self.reborrow());
+        let guard = lock_level::<L, _>(t)?;
+        Ok((result, SuspendableGuard::new(t, guard)))
+    }
+}
+
+impl<L: TreeLockLevel> LockedAt<'_, L>
+where
+    L::Mutex: MutexLockData,
+{
+    /// Acquires the lock of a child node at the [`TreeLockLevel`] `L`.
+    ///
+    /// `parent` must be the guard for a node at `L`, and `index` picks one of
+    /// the children returned by [`TreeLockLevel::children`] for its value.
+    /// Since the child is only reachable through the held parent, it is
+    /// always locked after it, which makes re-acquiring `L` safe. Returns a
+    /// new `LockedAt` at `L` along with the child's guard, so the traversal
+    /// can continue from the child.
+    ///
+    /// The child's lock is borrowed from `parent`, so the whole path from the
+    /// first node stays locked until the traversal unwinds.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node has no child at `index`.
+    #[allow(clippy::type_complexity)]
+    pub fn with_lock_child<'a, 'p: 'a>(
+        &'a mut self,
+        parent: &'a mut LevelGuard<<L::Mutex as MutexLock>::Guard<'p>>,
+        index: usize,
+    ) -> Result<
+        (
+            LockedAt<'a, L>,
+            LevelGuard<<L::Mutex as MutexLock>::Guard<'a>>,
+        ),
+        <L::Mutex as MutexLock>::Error<'a>,
+    > {
+        let node: &'a _ = L::Mutex::data(backend_guard_mut(parent));
+        let child = L::children(node).nth(index).unwrap_or_else(|| {
+            panic!(
+                "{} has no child at index {index}",
+                core::any::type_name::<L>()
+            )
+        });
+        // Children are always locked after their parent, so re-acquiring `L`
+        // isn't checked like a new acquisition.
+        crate::instrument::lock(core::any::type_name::<L>(), child)
+            .map(|guard| (LockedAt(PhantomData), level_guard::<L, _>(guard)))
+    }
+}
+
+impl<'a, L: RwLockLevel> LockedAt<'a, L>
+where
+    L::RwLock: DowngradableRwLock,
+{
+    /// Downgrades a write lock at level `L` to a read lock.
+    ///
+    /// Consumes the `LockedAt` produced alongside `guard` by
+    /// [`with_write_lock`](LockedAt::with_write_lock) and returns it with the
+    /// downgraded guard. The level is unchanged since no lock is acquired;
+    /// the returned `LockedAt` can be used to acquire levels after `L` while
+    /// other threads read the same lock.
+    pub fn downgrade(
+        self,
+        guard: LevelGuard<<L::RwLock as RwLock>::WriteGuard<'a>>,
+    ) -> (Self, LevelGuard<<L::RwLock as RwLock>::ReadGuard<'a>>) {
+        (self, map_level_guard(guard, L::RwLock::downgrade))
+    }
+}
+
+impl<L: RwLockLevel> LockedAt<'_, L> {
+    /// Replaces a write lock at level `L` with a read lock on the same lock.
+    ///
+    /// `guard` comes from
+    /// [`with_write_lock_relockable`](LockedAt::with_write_lock_relockable),
+    /// so it is the only guard held at `L` and knows which lock to acquire
+    /// again. It is dropped and its lock is then locked for reading, without
+    /// changing the `LockedAt`, so levels after `L` can still be acquired from
+    /// it afterwards. Acquiring the lock again is in order since nothing after
+    /// `L` can be held while `self` is borrowed.
+    ///
+    /// Unlike [`downgrade`](LockedAt::downgrade), this isn't atomic: another
+    /// writer can take `t` between the two steps, so the state seen through
+    /// the read guard may differ from what was written. This is the only
+    /// option for [`std::sync::RwLock`]; backends that implement
+    /// [`DowngradableRwLock`], like `parking_lot`'s, should use `downgrade`.
+    pub fn relock_read<'g>(
+        &mut self,
+        guard: RelockableWriteGuard<'g, L>,
+    ) -> Result<
+        LevelGuard<<L::RwLock as RwLock>::ReadGuard<'g>>,
+        <L::RwLock as RwLock>::ReadError<'g>,
+    > {
+        let t = guard.release();
+        acquire_level::<L, _, _>(|| t.read())
+    }
+}
+
+/// Returns the address of `t`, for use as a canonical lock order.
+fn address<T: ?Sized>(t: &T) -> usize {
+    (t as *const T).cast::<()>() as usize
+}
+
+#[cfg(feature = "async")]
+impl<L> LockedAt<'_, L> {
+    /// Asynchronously acquires a lock on `NewLock` state.
+    ///
+    /// Assuming `NewLock` is a lock level that can be acquired after `L`, this
+    /// method provides access to state held in the [`AsyncMutexLock`] type
+    /// `NewLock::Mutex`, yielding the current task until the lock can be
+    /// acquired. Once the state is locked, returns a guard for accessing it
+    /// and a new `LockedAt` instance that can be used to acquire additional
+    /// locks.
+    ///
+    /// If no further `LockedAt` calls need to be made after this one, consider
+    /// using [`LockedAt::wait_lock`] instead.
+    pub async fn wait_for_lock<'a, NewLock: LockAfter<L> + AsyncMutexLockLevel + 'a>(
+        &'a mut self,
+        t: &'a NewLock::Mutex,
+    ) -> (
+        LockedAt<'a, NewLock>,
+        <NewLock::Mutex as AsyncMutexLock>::Guard<'a>,
+    ) {
+        let guard = crate::instrument::wait_lock(core::any::type_name::<NewLock>(), t).await;
+        (LockedAt(PhantomData), guard)
+    }
+
+    /// Attempts to acquire a lock on `NewLock` state without waiting.
+    ///
+    /// Like [`LockedAt::wait_for_lock`], but returns `None` instead of
+    /// yielding if the lock is currently held. Only locks that implement
+    /// [`NonBlockingAsyncMutexLock`] are supported. Since this never waits,
+    /// it can be called from
+    /// synchronous code that occasionally needs an [`AsyncMutexLock`], while
+    /// keeping the returned `LockedAt` at `NewLock` for further acquisitions.
+    ///
+    /// If no further `LockedAt` calls need to be made after this one, consider
+    /// using [`LockedAt::try_wait_lock`] instead.
+    pub fn try_wait_for_lock<'a, NewLock: LockAfter<L> + AsyncMutexLockLevel + 'a>(
+        &'a mut self,
+        t: &'a NewLock::Mutex,
+    ) -> Option<(
+        LockedAt<'a, NewLock>,
+        <NewLock::Mutex as AsyncMutexLock>::Guard<'a>,
+    )>
+    where
+        NewLock::Mutex: NonBlockingAsyncMutexLock,
+    {
+        let level = core::any::type_name::<NewLock>();
+        let guard = crate::instrument::acquire(level, || t.try_lock().ok_or(())).ok()?;
+        Some((LockedAt(PhantomData), guard))
+    }
+
+    /// Asynchronously acquires a permit from the semaphore for `NewLock`.
+    ///
+    /// Assuming `NewLock` is a lock level that can be acquired after `L`,
+    /// this waits for a permit from the [`AsyncSemaphore`]
+    /// `NewLock::Semaphore`. On success, returns a new `LockedAt` along with the permit, so that
+    /// locks acquired while holding the permit are ordered after it.
+    pub async fn acquire_permit<'a, NewLock: LockAfter<L> + SemaphoreLevel + 'a>(
+        &'a mut self,
+        t: &'a NewLock::Semaphore,
+    ) -> Result<
+        (
+            LockedAt<'a, NewLock>,
+            <NewLock::Semaphore as AsyncSemaphore>::Permit<'a>,
+        ),
+        <NewLock::Semaphore as AsyncSemaphore>::Error<'a>,
+    > {
+        let permit =
+            crate::instrument::try_wait(core::any::type_name::<NewLock>(), t.acquire()).await?;
+        Ok((LockedAt(PhantomData), permit))
+    }
+
+    /// Attempts to asynchronously acquire a fallible lock on `NewLock` state.
+    ///
+    /// Like [`LockedAt::wait_for_lock`], but for a [`TryAsyncMutexLock`]. If
+    /// acquisition fails, the error is returned and no `LockedAt` for
+    /// `NewLock` is produced.
+    ///
+    /// If no further `LockedAt` calls need to be made after this one, consider
+    /// using [`LockedAt::wait_lock_fallible`] instead.
+    pub async fn wait_for_lock_fallible<'a, NewLock: LockAfter<L> + TryAsyncMutexLockLevel + 'a>(
+        &'a mut self,
+        t: &'a NewLock::Mutex,
+    ) -> Result<
+        (
+            LockedAt<'a, NewLock>,
+            <NewLock::Mutex as TryAsyncMutexLock>::Guard<'a>,
+        ),
+        <NewLock::Mutex as TryAsyncMutexLock>::Error<'a>,
+    > {
+        let guard = crate::instrument::try_wait(
+            core::any::type_name::<NewLock>(),
+            TryAsyncMutexLock::lock(t),
+        )
+        .await?;
+        Ok((LockedAt(PhantomData), guard))
+    }
+
+    /// Asynchronously acquires a shared lock on `NewLock` state.
+    ///
+    /// Assuming `NewLock` is a lock level that can be acquired after `L`, this
+    /// method provides access to state held in the [`ReadWrite`] type T.
+    /// This method will yield the current task until the lock can be acquired.
+    /// Once the state is locked, this method returns a guard for accessing it
+    /// and a new `LockedAt` instance that can be used to acquire additional
+    /// locks.
+    ///
+    /// If no further `LockedAt` calls need to be made after this one, consider
+    /// using [`LockedAt::wait_read`] instead.
+    pub async fn wait_for_read<'a, NewLock: LockAfter<L> + AsyncRwLockLevel + 'a>(
+        &'a mut self,
+        t: &'a NewLock::RwLock,
+    ) -> (
+        LockedAt<'a, NewLock>,
+        <NewLock::RwLock as AsyncRwLock>::ReadGuard<'a>,
+    ) {
+        let guard = crate::instrument::wait(core::any::type_name::<NewLock>(), t.read()).await;
+        (LockedAt(PhantomData), guard)
+    }
+
+    /// Attempts to acquire an exclusive lock on `NewLock` state.
+    ///
+    /// Assuming `NewLock` is a lock level that can be acquired after `L`, this
+    /// method provides access to state held in the [`ReadWrite`] type T. If the
+    /// lock acquisition fails, an error will be returned. Otherwise, this
+    /// method returns a new `LockedAt` along with a read/write accessor for the
+    /// held state.
+    ///
+    /// If no further `LockedAt` calls need to be made after this one, consider
+    /// using [`LockedAt::write_lock`] instead.
+    pub async fn wait_for_write<'a, NewLock: LockAfter<L> + AsyncRwLockLevel + 'a>(
+        &'a mut self,
+        t: &'a NewLock::RwLock,
+    ) -> (
+        LockedAt<'a, NewLock>,
+        <NewLock::RwLock as AsyncRwLock>::WriteGuard<'a>,
+    ) {
+        let guard = crate::instrument::wait(core::any::type_name::<NewLock>(), t.write()).await;
+        (LockedAt(PhantomData), guard)
+    }
+
+    /// Asynchronously acquires several [AsyncMutexLock]s at the same level.
+    ///
+    /// The locks are awaited in order of their addresses, so concurrent tasks
+    /// acquiring overlapping sets of locks can't deadlock with each other
+    /// regardless of the order they list them in. Each lock is still granted
+    /// in its own waiters' order. The returned guards are in the same order as
+    /// `locks`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the same lock appears more than once in `locks`.
+    #[cfg(feature = "std")]
+    pub async fn wait_lock_all_sorted<'a, NewLock: LockAfter<L> + AsyncMutexLockLevel + 'a>(
+        &'a mut self,
+        locks: &[&'a NewLock::Mutex],
+    ) -> (
+        LockedAt<'a, NewLock>,
+        std::vec::Vec<<NewLock::Mutex as AsyncMutexLock>::Guard<'a>>,
+    ) {
+        let mut order: std::vec::Vec<usize> = (0..locks.len()).collect();
+        order.sort_unstable_by_key(|&i| address(locks[i]));
+        assert!(
+            order
+                .windows(2)
+                .all(|w| address(locks[w[0]]) != address(locks[w[1]])),
+            "wait_lock_all_sorted called with the same lock twice"
+        );
+
+        let level = core::any::type_name::<NewLock>();
+        let mut guards: std::vec::Vec<_> = locks.iter().map(|_| None).collect();
+        for i in order {
+            guards[i] = Some(crate::instrument::wait_lock(level, locks[i]).await);
+        }
+        (
+            LockedAt(PhantomData),
+            guards.into_iter().map(Option::unwrap).collect(),
+        )
+    }
+}
+
+#[cfg(all(feature = "async", feature = "std"))]
+impl<L> LockedAt<'static, L> {
+    /// Asynchronously acquires a lock on `NewLock` state through an [`Arc`],
+    /// producing a guard that owns a reference to the lock.
+    ///
+    /// Like [`LockedAt::wait_for_lock`], this requires that `NewLock` can be
+    /// acquired after `L`. Unlike it, the guard doesn't borrow anything, so it
+    /// can be held across task boundaries or stored in `'static` state. To
+    /// keep the guard from outliving the `LockedAt` it was acquired from, this
+    /// consumes `self`, which must not borrow from another `LockedAt`, and
+    /// returns a `LockedAt<'static, NewLock>` that can be moved along with the
+    /// guard.
+    ///
+    /// [`Arc`]: std::sync::Arc
+    pub async fn wait_for_lock_owned<NewLock: LockAfter<L> + AsyncMutexLockLevel + 'static>(
+        self,
+        t: std::sync::Arc<NewLock::Mutex>,
+    ) -> (
+        LockedAt<'static, NewLock>,
+        <NewLock::Mutex as OwnedMutexLock>::OwnedGuard,
+    )
+    where
+        NewLock::Mutex: OwnedMutexLock,
+    {
+        let guard =
+            crate::instrument::wait(core::any::type_name::<NewLock>(), t.lock_owned()).await;
+        (LockedAt(PhantomData), guard)
+    }
+}
+
+// Convenience methods for accessing leaf locks in the ordering tree.
+#[cfg(feature = "async")]
+impl<L> LockedAt<'_, L> {
+    /// Asynchronously provides access to an [AsyncMutexLock]'s state.
+    pub async fn wait_lock<'a, NewLock: LockAfter<L> + 'a + AsyncMutexLockLevel>(
+        &'a mut self,
+        t: &'a NewLock::Mutex,
+    ) -> <NewLock::Mutex as AsyncMutexLock>::Guard<'a> {
+        let (_locked, guard) = self.wait_for_lock::<NewLock>(t).await;
+        guard
+    }
+
+    /// Asynchronously provides access to a [TryAsyncMutexLock]'s state.
+    pub async fn wait_lock_fallible<'a, NewLock: LockAfter<L> + 'a + TryAsyncMutexLockLevel>(
+        &'a mut self,
+        t: &'a NewLock::Mutex,
+    ) -> Result<
+        <NewLock::Mutex as TryAsyncMutexLock>::Guard<'a>,
+        <NewLock::Mutex as TryAsyncMutexLock>::Error<'a>,
+    > {
+        let (_locked, guard) = self.wait_for_lock_fallible::<NewLock>(t).await?;
+        Ok(guard)
+    }
+
+    /// Asynchronously provides read access to an [AsyncRwLock]'s state.
+    pub async fn wait_read<'a, NewLock: LockAfter<L> + AsyncRwLockLevel + 'a>(
+        &'a mut self,
+        t: &'a NewLock::RwLock,
+    ) -> <NewLock::RwLock as AsyncRwLock>::ReadGuard<'a> {
+        let (_locked, guard) = self.wait_for_read::<NewLock>(t).await;
+        guard
+    }
+
+    /// Asynchronously provides read/write access to an [AsyncRwLock]'s state.
+    pub async fn wait_write<'a, NewLock: LockAfter<L> + AsyncRwLockLevel + 'a>(
+        &'a mut self,
+        t: &'a NewLock::RwLock,
+    ) -> <NewLock::RwLock as AsyncRwLock>::WriteGuard<'a> {
+        let (_locked, guard) = self.wait_for_write::<NewLock>(t).await;
+        guard
+    }
+
+    /// Attempts to provide read access to an [AsyncRwLock]'s state without
+    /// waiting.
+    ///
+    /// Returns `None` if the lock is currently held for writing. Only locks
+    /// that implement [`NonBlockingAsyncRwLock`] are supported. Since this
+    /// never waits, it can be called from synchronous code.
+    pub fn try_wait_read<'a, NewLock: LockAfter<L> + AsyncRwLockLevel + 'a>(
+        &'a mut self,
+        t: &'a NewLock::RwLock,
+    ) -> Option<<NewLock::RwLock as AsyncRwLock>::ReadGuard<'a>>
+    where
+        NewLock::RwLock: NonBlockingAsyncRwLock,
+    {
+        let level = core::any::type_name::<NewLock>();
+        crate::instrument::acquire(level, || t.try_read().ok_or(())).ok()
+    }
+
+    /// Attempts to provide read/write access to an [AsyncRwLock]'s state
+    /// without waiting.
+    ///
+    /// Returns `None` if the lock is currently held. Only locks that
+    /// implement [`NonBlockingAsyncRwLock`] are supported. Since this never
+    /// waits, it can be called from synchronous code.
+    pub fn try_wait_write<'a, NewLock: LockAfter<L> + AsyncRwLockLevel + 'a>(
+        &'a mut self,
+        t: &'a NewLock::RwLock,
+    ) -> Option<<NewLock::RwLock as AsyncRwLock>::WriteGuard<'a>>
+    where
+        NewLock::RwLock: NonBlockingAsyncRwLock,
+    {
+        let level = core::any::type_name::<NewLock>();
+        crate::instrument::acquire(level, || t.try_write().ok_or(())).ok()
+    }
+
+    /// Attempts to provide access to an [AsyncMutexLock]'s state without
+    /// waiting.
+    ///
+    /// Returns `None` if the lock is currently held. Only locks that
+    /// implement [`NonBlockingAsyncMutexLock`] are supported. Since this never
+    /// waits, it can be called from synchronous code.
+    pub fn try_wait_lock<'a, NewLock: LockAfter<L> + AsyncMutexLockLevel + 'a>(
+        &'a mut self,
+        t: &'a NewLock::Mutex,
+    ) -> Option<<NewLock::Mutex as AsyncMutexLock>::Guard<'a>>
+    where
+        NewLock::Mutex: NonBlockingAsyncMutexLock,
+    {
+        let (_locked, guard) = self.try_wait_for_lock::<NewLock>(t)?;
+        Some(guard)
+    }
+
+    /// Asynchronously provides access to the state of one of several
+    /// [AsyncMutexLock]s at the same level.
+    ///
+    /// This is for two-level structures like an outer lock guarding a
+    /// `Vec` of inner locks: after acquiring the outer level, the inner level
+    /// `NewLock` is shared by every element, and `i` picks which one to lock.
+    /// Since the returned guard borrows `self`, only one element can be held
+    /// at a time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is out of bounds for `locks`.
+    pub async fn wait_lock_indexed<'a, NewLock: LockAfter<L> + 'a + AsyncMutexLockLevel>(
+        &'a mut self,
+        locks: &'a [NewLock::Mutex],
+        i: usize,
+    ) -> <NewLock::Mutex as AsyncMutexLock>::Guard<'a>
+    where
+        NewLock::Mutex: Sized,
+    {
+        self.wait_lock::<NewLock>(&locks[i]).await
+    }
+
+    /// Asynchronously provides access to an [AsyncMutexLock]'s state, along
+    /// with how long the acquisition was pending.
+    ///
+    /// The returned [`Duration`](std::time::Duration) measures the wall-clock
+    /// time between starting the acquisition and the lock being granted,
+    /// including any time the task spent waiting for other holders to release
+    /// the lock.
+    #[cfg(feature = "std")]
+    pub async fn wait_lock_timed<'a, NewLock: LockAfter<L> + 'a + AsyncMutexLockLevel>(
+        &'a mut self,
+        t: &'a NewLock::Mutex,
+    ) -> (
+        <NewLock::Mutex as AsyncMutexLock>::Guard<'a>,
+        std::time::Duration,
+    ) {
+        let start = std::time::Instant::now();
+        let guard = self.wait_lock::<NewLock>(t).await;
+        (guard, start.elapsed())
+    }
+
+    /// Repeatedly locks an [AsyncMutexLock] until `pred` produces a value.
+    ///
+    /// Each attempt waits for the lock and calls `pred` with the guard. If it
+    /// returns `None`, `changed` is called while the lock is still held, the
+    /// lock is released, and the task waits for the future returned by
+    /// `changed` before trying again. The first `Some` is returned.
+    ///
+    /// `changed` should return a future that completes once the state might
+    /// have changed, like `|| notify.notified()` for a `tokio::sync::Notify`
+    /// that writers notify. Since the future is created before the lock is
+    /// released, a notification sent by the next holder of the lock isn't
+    /// missed as long as the future is registered when it is created. There
+    /// is no polling: a future that completes immediately makes this spin.
+    ///
+    /// Writing this loop by hand with a result borrowed from the guard runs
+    /// into borrow checker limitations around conditionally returning a
+    /// borrow of `self`. Since the value produced by `pred` can't borrow from
+    /// the guard, no such workaround is needed here.
+    pub async fn wait_lock_until<'a, NewLock, R, F>(
+        &mut self,
+        t: &'a NewLock::Mutex,
+        mut pred: impl FnMut(&mut <NewLock::Mutex as AsyncMutexLock>::Guard<'_>) -> Option<R>,
+        mut changed: impl FnMut() -> F,
+    ) -> R
+    where
+        NewLock: LockAfter<L> + 'a + AsyncMutexLockLevel,
+        F: core::future::Future,
+    {
+        loop {
+            let mut guard = self.wait_lock::<NewLock>(t).await;
+            if let Some(value) = pred(&mut guard) {
+                return value;
+            }
+            let changed = changed();
+            drop(guard);
+            let _ = changed.await;
+        }
+    }
+}
+
+impl<L: MutexLockLevel> LockedAt<'_, L> {
+    /// Waits on a condition variable paired with the mutex for level `L`.
+    ///
+    /// `guard` must be the guard for the mutex at the current level `L`. The
+    /// mutex is atomically released while waiting on `cvar` and reacquired
+    /// each time the thread is woken, until `pred` returns `true` for the
+    /// protected value. The returned guard is for the same mutex, so the
+    /// current level is unchanged.
+    ///
+    /// Since this requires `&mut self`, no locks for levels after `L` can be
+    /// held while waiting.
+    pub fn wait_cvar<'g, C: CondvarLock<L::Mutex> + ?Sized>(
+        &mut self,
+        mut guard: LevelGuard<<L::Mutex as MutexLock>::Guard<'g>>,
+        cvar: &C,
+        mut pred: impl FnMut(&<L::Mutex as MutexLockData>::Data) -> bool,
+    ) -> Result<LevelGuard<<L::Mutex as MutexLock>::Guard<'g>>, C::WaitError<'g>>
+    where
+        L::Mutex: MutexLockData,
+    {
+        // Like `std`'s `Condvar::wait_while`, which handles spurious wakeups
+        // by checking the predicate again.
+        while !pred(L::Mutex::data(backend_guard_mut(&mut guard))) {
+            guard = try_map_level_guard(guard, |guard| cvar.wait(guard))?;
+        }
+        Ok(guard)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<L> LockedAt<'_, L> {
+    /// Acquires a [`std::sync::Mutex`] at `NewLock`, even if it is poisoned.
+    ///
+    /// Unlike [`LockedAt::with_lock`], the new `LockedAt` is returned either
+    /// way, so recovery code can keep acquiring levels after `NewLock`. The
+    /// guard is `Err` if the lock was poisoned; the state it protects may be
+    /// inconsistent, and [`PoisonError::into_inner`](std::sync::PoisonError::into_inner)
+    /// produces the guard anyway.
+    #[allow(clippy::type_complexity)]
+    pub fn with_lock_recover<'a, NewLock, T: ?Sized>(
+        &'a mut self,
+        t: &'a std::sync::Mutex<T>,
+    ) -> (
+        LockedAt<'a, NewLock>,
+        Result<
+            LevelGuard<std::sync::MutexGuard<'a, T>>,
+            std::sync::PoisonError<LevelGuard<std::sync::MutexGuard<'a, T>>>,
+        >,
+    )
+    where
+        NewLock: LockAfter<L> + MutexLockLevel<Mutex = std::sync::Mutex<T>> + 'a,
+    {
+        check_acquire::<NewLock>();
+        let guard = match crate::instrument::lock(core::any::type_name::<NewLock>(), t) {
+            Ok(guard) => Ok(level_guard::<NewLock, _>(guard)),
+            Err(e) => Err(std::sync::PoisonError::new(level_guard::<NewLock, _>(
+                e.into_inner(),
+            ))),
+        };
+        (LockedAt(PhantomData), guard)
+    }
+
+    /// Writes to a [`GenerationRwLock`] and then reads back a consistent view.
+    ///
+    /// `std` locks can't atomically downgrade a write lock to a read lock, so
+    /// this emulates it: `f` is called with write access, the write lock is
+    /// released, and a read lock is acquired. If the lock's
+    /// [generation](crate::lock::GenerationRwLock::generation) shows that another writer
+    /// got in between, the whole operation is retried, including calling `f`
+    /// again. On success, the returned read guard observes the state exactly
+    /// as `f` left it, along with `f`'s result from the final attempt.
+    ///
+    /// [`GenerationRwLock`]: crate::lock::GenerationRwLock
+    #[allow(clippy::type_complexity)]
+    pub fn write_then_read_consistent<'a, NewLock, T: ?Sized, R>(
+        &'a mut self,
+        t: &'a crate::lock::GenerationRwLock<T>,
+        f: impl FnMut(&mut T) -> R,
+    ) -> Result<
+        (LevelGuard<std::sync::RwLockReadGuard<'a, T>>, R),
+        RwError<
+            std::sync::PoisonError<std::sync::RwLockReadGuard<'a, T>>,
+            std::sync::PoisonError<std::sync::RwLockWriteGuard<'a, T>>,
+        >,
+    >
+    where
+        NewLock: LockAfter<L> + RwLockLevel<RwLock = crate::lock::GenerationRwLock<T>> + 'a,
+    {
+        check_acquire::<NewLock>();
+        let (guard, result) =
+            crate::instrument::acquire(core::any::type_name::<NewLock>(), || {
+                write_then_read_retrying(t, f, || {})
+            })?;
+        Ok((level_guard::<NewLock, _>(guard), result))
+    }
+}
+
+/// The loop behind [`LockedAt::write_then_read_consistent`], which calls
+/// `between` after releasing each write lock and before acquiring the read
+/// lock.
+#[cfg(feature = "std")]
+#[allow(clippy::type_complexity)]
+fn write_then_read_retrying<'a, T: ?Sized, R>(
+    t: &'a crate::lock::GenerationRwLock<T>,
+    mut f: impl FnMut(&mut T) -> R,
+    mut between: impl FnMut(),
+) -> Result<
+    (std::sync::RwLockReadGuard<'a, T>, R),
+    RwError<
+        std::sync::PoisonError<std::sync::RwLockReadGuard<'a, T>>,
+        std::sync::PoisonError<std::sync::RwLockWriteGuard<'a, T>>,
+    >,
+> {
+    loop {
+        let mut guard = RwLock::write(t).map_err(RwError::Write)?;
+        let result = f(&mut guard);
+        let generation = t.generation();
+        drop(guard);
+
+        between();
+        let guard = RwLock::read(t).map_err(RwError::Read)?;
+        if t.generation() == generation {
+            return Ok((guard, result));
+        }
+    }
+}
+
+// Convenience methods for applications that report errors with `anyhow`.
+#[cfg(feature = "anyhow")]
+impl<L> LockedAt<'_, L> {
+    /// Provides access to a [MutexLock]'s state, reporting errors via
+    /// [`anyhow`].
+    ///
+    /// Like [`LockedAt::lock`], but on failure the acquisition error is
+    /// converted to an [`anyhow::Error`] with context naming the level that
+    /// couldn't be acquired.
+    pub fn lock_ctx<'a, NewLock: LockAfter<L> + 'a + MutexLockLevel>(
+        &'a mut self,
+        t: &'a NewLock::Mutex,
+    ) -> anyhow::Result<LevelGuard<<NewLock::Mutex as MutexLock>::Guard<'a>>>
+    where
+        <NewLock::Mutex as MutexLock>::Error<'a>: core::fmt::Display,
+    {
+        self.lock::<NewLock>(t)
+            .map_err(acquisition_error::<NewLock, _>)
+    }
+
+    /// Provides read access to a [RwLock]'s state, reporting errors via
+    /// [`anyhow`].
+    ///
+    /// Like [`LockedAt::read_lock`], but on failure the acquisition error is
+    /// converted to an [`anyhow::Error`] with context naming the level that
+    /// couldn't be acquired.
+    pub fn read_ctx<'a, NewLock: LockAfter<L> + RwLockLevel + 'a>(
+        &'a mut self,
+        t: &'a NewLock::RwLock,
+    ) -> anyhow::Result<LevelGuard<<NewLock::RwLock as RwLock>::ReadGuard<'a>>>
+    where
+        <NewLock::RwLock as RwLock>::ReadError<'a>: core::fmt::Display,
+    {
+        self.read_lock::<NewLock>(t)
+            .map_err(acquisition_error::<NewLock, _>)
+    }
+
+    /// Provides read/write access to a [RwLock]'s state, reporting errors via
+    /// [`anyhow`].
+    ///
+    /// Like [`LockedAt::write_lock`], but on failure the acquisition error is
+    /// converted to an [`anyhow::Error`] with context naming the level that
+    /// couldn't be acquired.
+    pub fn write_ctx<'a, NewLock: LockAfter<L> + RwLockLevel + 'a>(
+        &'a mut self,
+        t: &'a NewLock::RwLock,
+    ) -> anyhow::Result<LevelGuard<<NewLock::RwLock as RwLock>::WriteGuard<'a>>>
+    where
+        <NewLock::RwLock as RwLock>::WriteError<'a>: core::fmt::Display,
+    {
+        self.write_lock::<NewLock>(t)
+            .map_err(acquisition_error::<NewLock, _>)
+    }
+}
+
+// Acquisition methods that report where a failed acquisition was made.
+impl<L> LockedAt<'_, L> {
+    /// Attempts to acquire a lock on `NewLock` state, recording the call site
+    /// on failure.
+    ///
+    /// Like [`LockedAt::with_lock`], but errors are wrapped in [`Located`].
+    /// With the `debug-locations` feature enabled, that includes the location
+    /// of the call; otherwise nothing extra is recorded.
+    #[track_caller]
+    #[allow(clippy::type_complexity)]
+    pub fn with_lock_located<'a, NewLock: LockAfter<L> + MutexLockLevel>(
+        &'a mut self,
+        t: &'a NewLock::Mutex,
+    ) -> Result<
+        (
+            LockedAt<'a, NewLock>,
+            LevelGuard<<NewLock::Mutex as MutexLock>::Guard<'a>>,
+        ),
+        Located<<NewLock::Mutex as MutexLock>::Error<'a>>,
+    > {
+        // Not `map_err`, since closures don't forward the caller location.
+        match self.with_lock::<NewLock>(t) {
+            Ok(acquired) => Ok(acquired),
+            Err(e) => Err(Located::new(e)),
+        }
+    }
+
+    /// Provides access to a [MutexLock]'s state, recording the call site on
+    /// failure.
+    ///
+    /// Like [`LockedAt::lock`], but errors are wrapped in [`Located`].
+    #[track_caller]
+    #[allow(clippy::type_complexity)]
+    pub fn lock_located<'a, NewLock: LockAfter<L> + 'a + MutexLockLevel>(
+        &'a mut self,
+        t: &'a NewLock::Mutex,
+    ) -> Result<
+        LevelGuard<<NewLock::Mutex as MutexLock>::Guard<'a>>,
+        Located<<NewLock::Mutex as MutexLock>::Error<'a>>,
+    > {
+        match self.with_lock_located::<NewLock>(t) {
+            Ok((_locked, guard)) => Ok(guard),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Converts an error from acquiring a lock for `Level` into an
+/// [`anyhow::Error`].
+///
+/// Acquisition errors often borrow the lock (e.g. to hold a poisoned guard),
+/// so they are formatted rather than wrapped.
+#[cfg(feature = "anyhow")]
+fn acquisition_error<Level, E: core::fmt::Display>(e: E) -> anyhow::Error {
+    anyhow::anyhow!("{e}").context(format!("acquiring {}", core::any::type_name::<Level>()))
+}
+
+#[cfg(feature = "stream")]
+impl<L> LockedAt<'_, L> {
+    /// Asynchronously acquires each lock produced by a [`Stream`], in order.
+    ///
+    /// For each item yielded by `locks`, waits to acquire the lock and then
+    /// calls `f` with a `LockedAt` for `NewLock` and the guard. The guard and
+    /// `LockedAt` are dropped before the next item is requested, so at most
+    /// one lock at level `NewLock` is held at a time.
+    ///
+    /// A `Stream` can't yield values that borrow from `self`, which is why
+    /// this takes a callback instead of producing a stream of guards:
+    /// yielding them would allow multiple locks at the same level to be held
+    /// at once.
+    ///
+    /// [`Stream`]: futures_core::Stream
+    pub async fn wait_lock_each<'s, NewLock, S, F>(&mut self, locks: S, mut f: F)
+    where
+        NewLock: LockAfter<L> + AsyncMutexLockLevel + 's,
+        S: futures_core::Stream<Item = &'s NewLock::Mutex>,
+        F: for<'g> FnMut(&mut LockedAt<'g, NewLock>, <NewLock::Mutex as AsyncMutexLock>::Guard<'g>),
+    {
+        let mut locks = core::pin::pin!(locks);
+        while let Some(t) = core::future::poll_fn(|cx| locks.as_mut().poll_next(cx)).await {
+            let (mut locked, guard) = self.wait_for_lock::<NewLock>(t).await;
+            f(&mut locked, guard);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::sync::{Mutex, RwLock};
+
+    use super::*;
+    use crate::{
+        lock::{MutexLockLevel, RwLockLevel},
+        LockLevel,
+    };
+
+    struct LockA;
+    struct LockB;
+    struct LockC;
+
+    impl LockAfter<Unlocked> for LockA {}
+    impl LockAfter<Unlocked> for LockB {}
+    impl LockAfter<LockA> for LockB {}
+    impl LockAfter<Unlocked> for LockC {}
+    impl LockAfter<LockA> for LockC {}
+    impl LockAfter<LockB> for LockC {}
+
+    impl LockLevel for LockA {
+        type Method = MutualExclusion;
+    }
+    impl MutexLockLevel for LockA {
+        type Mutex = Mutex<usize>;
+    }
+
+    impl LockLevel for LockB {
+        type Method = MutualExclusion;
+    }
+    impl MutexLockLevel for LockB {
+        type Mutex = Mutex<u32>;
+    }
+
+    impl LockLevel for LockC {
+        type Method = ReadWrite;
+    }
+    impl RwLockLevel for LockC {
+        type RwLock = RwLock<u32>;
+    }
+
+    #[test]
+    fn derived_token_borrow_ends_at_last_use() {
+        let (a, b) = (Mutex::new(0), Mutex::new(0));
+        let mut locked = LockedAt::new();
+
+        let (mut locked_a, guard_a) = locked.with_lock::<LockA>(&a).unwrap();
+        *locked_a.lock::<LockB>(&b).unwrap() += 1;
+        drop(guard_a);
+
+        // `locked_a` is still in scope, but since tokens don't need to be
+        // dropped, `locked` can be used again after its last use, with or
+        // without `debug_checks`.
+        *locked.lock::<LockB>(&b).unwrap() += 1;
+        assert_eq!(*b.lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn lock_named() {
+        let a = Mutex::new(5);
+        let mut locked = LockedAt::new();
+
+        let (mut guard, name) = locked.lock_named::<LockA>(&a).unwrap();
+        assert_eq!(name, core::any::type_name::<LockA>());
+        assert!(name.ends_with("LockA"));
+
+        *guard += 1;
+        drop(guard);
+        assert_eq!(*a.lock().unwrap(), 6);
+    }
+
+    #[test]
+    fn lock_path_propagates_with_question_mark() {
+        struct Recovering;
+        impl LockAfter<LockA> for Recovering {}
+        impl LockLevel for Recovering {
+            type Method = MutualExclusion;
+        }
+        impl MutexLockLevel for Recovering {
+            type Mutex = crate::lock::IgnorePoison<Mutex<u32>>;
+        }
+
+        fn bump(
+            locked: &mut LockedAt<'_, Unlocked>,
+            a: &Mutex<usize>,
+            r: &crate::lock::IgnorePoison<Mutex<u32>>,
+        ) -> Result<u32, core::convert::Infallible> {
+            crate::lock_path!(
+                let (_locked, (_a, mut r)) = *locked;
+                (LockA, a),
+                (Recovering, r)?,
+            );
+            *r += 1;
+            Ok(*r)
+        }
+
+        let a = Mutex::new(0);
+        let r = crate::lock::IgnorePoison::new(Mutex::new(1));
+        assert_eq!(bump(&mut LockedAt::new(), &a, &r), Ok(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "PoisonError")]
+    fn lock_path_panics_with_error() {
+        let a = Mutex::new(0);
+        let _ = std::thread::scope(|s| {
+            s.spawn(|| {
+                let _guard = a.lock().unwrap();
+                panic!("poison the lock");
+            })
+            .join()
+        });
+
+        let mut locked = LockedAt::new();
+        crate::lock_path!(let (_locked, (_a,)) = locked; (LockA, &a));
+    }
+
+    #[test]
+    fn relock_read_keeps_level() {
+        let c = RwLock::new(1);
+        let mut locked = LockedAt::new();
+
+        let (mut locked, mut guard) = locked.with_write_lock_relockable::<LockC>(&c).unwrap();
+        *guard += 1;
+        let guard = locked.relock_read(guard).unwrap();
+
+        assert_eq!(*guard, 2);
+        assert_eq!(*c.try_read().unwrap(), 2);
+    }
+
+    #[test]
+    fn lock_pair_holds_both() {
+        let (a, b, c) = (Mutex::new(1), Mutex::new(2), RwLock::new(3));
+        let mut locked = LockedAt::new();
+
+        let (mut locked, (mut a_guard, b_guard)) =
+            locked.lock_pair::<LockA, LockB>(&a, &b).unwrap();
+        assert!(a.try_lock().is_err());
+        assert!(b.try_lock().is_err());
+
+        *a_guard += *b_guard as usize + *locked.read_lock::<LockC>(&c).unwrap() as usize;
+        drop((a_guard, b_guard));
+        assert_eq!(*a.lock().unwrap(), 6);
+    }
+
+    #[test]
+    fn lock_scoped_nests() {
+        let (a, b, c) = (Mutex::new(1), Mutex::new(2), RwLock::new(3));
+        let mut locked = LockedAt::new();
+
+        let sum = locked
+            .lock_scoped::<LockA, _>(&a, |locked, a| {
+                locked
+                    .lock_scoped::<LockB, _>(&b, |locked, b| {
+                        *a + *b as usize + *locked.read_lock::<LockC>(&c).unwrap() as usize
+                    })
+                    .unwrap()
+            })
+            .unwrap();
+        assert_eq!(sum, 6);
+        assert!(a.try_lock().is_ok());
+        assert!(b.try_lock().is_ok());
+    }
+
+    #[test]
+    fn lock_child_walks_tree() {
+        struct Node {
+            value: u32,
+            children: Vec<Mutex<Node>>,
+        }
+        struct Tree;
+        impl LockAfter<Unlocked> for Tree {}
+        impl LockLevel for Tree {
+            type Method = MutualExclusion;
+        }
+        impl MutexLockLevel for Tree {
+            type Mutex = Mutex<Node>;
+        }
+        impl TreeLockLevel for Tree {
+            fn children<'n>(node: &'n Node) -> impl Iterator<Item = &'n Mutex<Node>>
+            where
+                Mutex<Node>: 'n,
+            {
+                node.children.iter()
+            }
+        }
+
+        fn sum(
+            locked: &mut LockedAt<'_, Tree>,
+            node: &mut LevelGuard<std::sync::MutexGuard<'_, Node>>,
+        ) -> u32 {
+            let mut total = node.value;
+            for i in 0..node.children.len() {
+                let (mut locked, mut child) = locked.with_lock_child(node, i).unwrap();
+                total += sum(&mut locked, &mut child);
+            }
+            total
+        }
+
+        let leaf = |value| {
+            Mutex::new(Node {
+                value,
+                children: Vec::new(),
+            })
+        };
+        let root = Mutex::new(Node {
+            value: 1,
+            children: vec![
+                leaf(2),
+                Mutex::new(Node {
+                    value: 3,
+                    children: vec![leaf(4)],
+                }),
+            ],
+        });
+
+        let mut locked = LockedAt::new();
+        let (mut locked, mut root) = locked.with_lock::<Tree>(&root).unwrap();
+        assert_eq!(sum(&mut locked, &mut root), 10);
+    }
+
+    #[test]
+    fn lock_recover_continues_past_poison() {
+        let (a, b) = (Mutex::new(1), Mutex::new(2));
+        let _ = std::thread::scope(|s| {
+            s.spawn(|| {
+                let _guard = a.lock().unwrap();
+                panic!("poisoning the lock");
+            })
+            .join()
+        });
+
+        let mut locked = LockedAt::new();
+        let (mut locked, a) = locked.with_lock_recover::<LockA, _>(&a);
+        let mut a = a.unwrap_err().into_inner();
+        *a += *locked.lock::<LockB>(&b).unwrap() as usize;
+        assert_eq!(*a, 3);
+    }
+
+    #[test]
+    fn acquire_order_holds_lock() {
+        struct Invariants;
+        struct Data;
+        impl LockAfter<Unlocked> for Invariants {}
+        impl LockAfter<Invariants> for Data {}
+        impl LockLevel for Invariants {
+            type Method = MutualExclusion;
+        }
+        impl MutexLockLevel for Invariants {
+            type Mutex = Mutex<()>;
+        }
+        impl LockLevel for Data {
+            type Method = MutualExclusion;
+        }
+        impl MutexLockLevel for Data {
+            type Mutex = Mutex<usize>;
+        }
+
+        let (invariants, data) = (Mutex::new(()), Mutex::new(1));
+        let mut locked = LockedAt::new();
+
+        let (mut locked, guard) = locked
+            .with_acquire_order::<Invariants>(&invariants)
+            .unwrap();
+        assert!(invariants.try_lock().is_err());
+        *locked.lock::<Data>(&data).unwrap() += 1;
+        drop(guard);
+        assert!(invariants.try_lock().is_ok());
+    }
+
+    #[test]
+    fn lock_opt_skips_missing_lock() {
+        let (a, b) = (Mutex::new(1), Mutex::new(2));
+        let mut locked = LockedAt::new();
+
+        assert!(locked.with_lock_opt::<LockA>(None).unwrap().is_none());
+
+        let (mut locked, mut a) = locked.with_lock_opt::<LockA>(Some(&a)).unwrap().unwrap();
+        *a += *locked.lock::<LockB>(&b).unwrap() as usize;
+        assert_eq!(*a, 3);
+    }
+
+    #[test]
+    fn suspend_lock_releases_while_suspended() {
+        let (a, c) = (Mutex::new(1), RwLock::new(0));
+        let mut locked = LockedAt::new();
+
+        let (mut locked, guard) = locked.with_lock_suspendable::<LockA>(&a).unwrap();
+        let (read, mut guard) = locked
+            .suspend_lock(guard, |locked| {
+                *a.try_lock().unwrap() += 1;
+                *locked.write_lock::<LockC>(&c).unwrap() += 1;
+                *c.try_read().unwrap()
+            })
+            .unwrap();
+        assert_eq!(read, 1);
+        assert!(a.try_lock().is_err());
+        *guard += 1;
+        drop(guard);
+        assert_eq!(*a.lock().unwrap(), 3);
+    }
+
+    #[test]
+    fn debug_shows_level() {
+        let a = Mutex::new(0);
+        let mut locked = LockedAt::new();
+        assert_eq!(format!("{locked:?}"), "LockedAt(lock_ordering::Unlocked)");
+
+        let (locked, _a) = locked.with_lock::<LockA>(&a).unwrap();
+        assert_eq!(
+            format!("{locked:?}"),
+            "LockedAt(lock_ordering::lockedat::tests::LockA)"
+        );
+    }
+
+    #[test]
+    fn reenter_as_root_for_callback() {
+        fn run(callback: impl FnOnce(LockedAt<'_, Unlocked>)) {
+            let mut locked = LockedAt::new();
+            callback(locked.reenter_as_root());
+        }
+
+        let a = Mutex::new(1);
+        run(|mut locked| *locked.lock::<LockA>(&a).unwrap() += 1);
+        assert_eq!(*a.lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn lock_mut_skips_locking() {
+        let mut a = Mutex::new(1);
+        let mut locked = LockedAt::new();
+
+        *locked.lock_mut::<LockA>(&mut a) += 1;
+        assert_eq!(a.into_inner().unwrap(), 2);
+    }
+
+    #[test]
+    fn lock_pair_releases_first_on_error() {
+        let (a, b) = (Mutex::new(1), Mutex::new(2));
+        let _ = std::thread::scope(|s| {
+            s.spawn(|| {
+                let _guard = b.lock().unwrap();
+                panic!("poisoning the lock");
+            })
+            .join()
+        });
+
+        let mut locked = LockedAt::new();
+        let result = locked.lock_pair::<LockA, LockB>(&a, &b);
+        assert!(matches!(result, Err(PairError::Second(_))));
+        drop(result);
+        assert!(a.try_lock().is_ok());
+    }
+
+    #[test]
+    fn lock_either_chooses_at_runtime() {
+        struct LockD;
+        impl LockAfter<LockA> for LockD {}
+        impl LockAfter<LockB> for LockD {}
+        crate::impl_lock_after_one_of! { OneOf<LockA, LockB> => LockD }
+        impl LockLevel for LockD {
+            type Method = MutualExclusion;
+        }
+        impl MutexLockLevel for LockD {
+            type Mutex = Mutex<u32>;
+        }
+
+        let (a, b, d) = (Mutex::new(1), Mutex::new(2), Mutex::new(0));
+        let mut locked = LockedAt::new();
+
+        for use_a in [true, false] {
+            let lock = if use_a {
+                Either::First(&a)
+            } else {
+                Either::Second(&b)
+            };
+            let (mut locked, guard) = locked.with_lock_either::<LockA, LockB>(lock).unwrap();
+            let value = match &guard {
+                Either::First(a) => **a as u32,
+                Either::Second(b) => **b,
+            };
+            *locked.lock::<LockD>(&d).unwrap() += value;
+        }
+
+        assert_eq!(*d.lock().unwrap(), 3);
+    }
+
+    #[test]
+    #[cfg(feature = "test-util")]
+    fn at_level_for_helper_tests() {
+        fn bump(locked: &mut LockedAt<'_, LockA>, c: &RwLock<u32>) {
+            *locked.write_lock::<LockC>(c).unwrap() += 1;
+        }
+
+        let c = RwLock::new(1);
+        {
+            // SAFETY: nothing else is locked in this test.
+            let mut locked = unsafe { LockedAt::at_level::<LockA>() };
+            bump(&mut locked, &c);
+        }
+
+        assert_eq!(*c.read().unwrap(), 2);
+    }
+
+    #[test]
+    fn into_value_recovers_poison() {
+        let a = Mutex::new(1);
+        let _ = std::thread::scope(|s| {
+            s.spawn(|| {
+                let mut locked = LockedAt::new();
+                let mut guard = locked.lock::<LockA>(&a).unwrap();
+                *guard = 2;
+                panic!("poisoning the lock");
+            })
+            .join()
+        });
+        assert!(a.is_poisoned());
+
+        assert_eq!(LockedAt::new().into_value::<LockA>(a), 2);
+    }
+
+    #[test]
+    fn wait_cvar_producer_consumer() {
+        use std::{collections::VecDeque, sync::Condvar};
+
+        struct Queue;
+        impl LockAfter<Unlocked> for Queue {}
+        impl LockLevel for Queue {
+            type Method = MutualExclusion;
+        }
+        impl MutexLockLevel for Queue {
+            type Mutex = Mutex<VecDeque<u32>>;
+        }
+
+        let queue = Mutex::new(VecDeque::new());
+        let ready = Condvar::new();
+
+        let received = std::thread::scope(|s| {
+            let consumer = s.spawn(|| {
+                let mut locked = LockedAt::new();
+                let mut received = Vec::new();
+                while received.len() < 3 {
+                    let (mut locked, guard) = locked.with_lock::<Queue>(&queue).unwrap();
+                    let mut guard = locked
+                        .wait_cvar(guard, &ready, |queue| !queue.is_empty())
+                        .unwrap();
+                    received.extend(guard.drain(..));
+                }
+                received
+            });
+
+            for i in 0..3 {
+                LockedAt::new().lock::<Queue>(&queue).unwrap().push_back(i);
+                ready.notify_one();
+            }
+            consumer.join().unwrap()
+        });
+
+        assert_eq!(received, [0, 1, 2]);
+    }
+
+    #[test]
+    fn with_pair_ordered_transfers() {
+        const TRANSFERS: usize = 1000;
+        let accounts = [Mutex::new(100), Mutex::new(100)];
+
+        let transfer = |from: &Mutex<usize>, to: &Mutex<usize>| {
+            let mut locked = LockedAt::new();
+            for _ in 0..TRANSFERS {
+                locked
+                    .with_pair_ordered::<LockA, _>(from, to, |from, to| {
+                        if *from > 0 {
+                            *from -= 1;
+                            *to += 1;
+                        }
+                    })
+                    .unwrap();
+            }
+        };
+
+        std::thread::scope(|s| {
+            let [x, y] = &accounts;
+            s.spawn(|| transfer(x, y));
+            s.spawn(|| transfer(y, x));
+        });
+
+        let [x, y] = accounts.map(|a| a.into_inner().unwrap());
+        assert_eq!(x + y, 200);
+    }
+
+    #[cfg(feature = "anyhow")]
+    #[test]
+    fn lock_ctx_names_level() {
+        let a = Mutex::new(1);
+        let _ = std::thread::scope(|s| {
+            s.spawn(|| {
+                let mut locked = LockedAt::new();
+                let _guard = locked.lock::<LockA>(&a).unwrap();
+                panic!("poisoning the lock");
+            })
+            .join()
+        });
+
+        let error = LockedAt::new().lock_ctx::<LockA>(&a).unwrap_err();
+        let message = format!("{error:#}");
+        assert!(
+            message.contains(&format!("acquiring {}", core::any::type_name::<LockA>())),
+            "{message}"
+        );
+        assert!(message.contains("poisoned"), "{message}");
+    }
+
+    #[test]
+    fn write_then_read_consistent_sees_own_write() {
+        use crate::lock::GenerationRwLock;
+
+        struct Versioned;
+        impl LockAfter<Unlocked> for Versioned {}
+        impl LockLevel for Versioned {
+            type Method = ReadWrite;
+        }
+        impl RwLockLevel for Versioned {
+            type RwLock = GenerationRwLock<usize>;
+        }
+
+        const THREADS: usize = 4;
+        const ROUNDS: usize = 100;
+
+        let value = GenerationRwLock::new(0);
+        std::thread::scope(|s| {
+            for id in 1..=THREADS {
+                let value = &value;
+                s.spawn(move || {
+                    for _ in 0..ROUNDS {
+                        let mut locked = LockedAt::new();
+                        let (guard, ()) = locked
+                            .write_then_read_consistent::<Versioned, _, _>(value, |value| {
+                                *value = id
+                            })
+                            .unwrap();
+                        assert_eq!(*guard, id);
+                    }
+                });
+            }
+        });
+        assert!(value.generation() >= (THREADS * ROUNDS) as u64);
+    }
+
+    #[test]
+    fn write_then_read_consistent_retries_after_other_write() {
+        use core::cell::Cell;
+
+        use crate::lock::GenerationRwLock;
+
+        let value = GenerationRwLock::new(0);
+        let attempts = Cell::new(0);
+        let (guard, attempt) = write_then_read_retrying(
+            &value,
+            |value| {
+                attempts.set(attempts.get() + 1);
+                *value = 1;
+                attempts.get()
+            },
+            || {
+                // Another writer gets in after the first attempt only.
+                if attempts.get() == 1 {
+                    *crate::lock::RwLock::write(&value).unwrap() = 2;
+                }
+            },
+        )
+        .unwrap();
+
+        // The result is from the second attempt, after one retry.
+        assert_eq!((*guard, attempt), (1, 2));
+        drop(guard);
+        assert_eq!(value.generation(), 3);
+    }
+
+    #[test]
+    fn write_all_sorted_shuffled() {
+        const THREADS: usize = 4;
+        const ROUNDS: u32 = 200;
+
+        // Each shard's value starts at a different multiple of `BASE` to
+        // identify it.
+        const BASE: u32 = 1_000_000;
+        let shards = [RwLock::new(0), RwLock::new(BASE), RwLock::new(2 * BASE)];
+        std::thread::scope(|s| {
+            for t in 0..THREADS {
+                let shards = &shards;
+                s.spawn(move || {
+                    // Each thread lists the shards in a different order.
+                    let mut order: Vec<_> = shards.iter().collect();
+                    order.rotate_left(t % shards.len());
+                    if t % 2 == 1 {
+                        order.reverse();
+                    }
+
+                    for _ in 0..ROUNDS {
+                        let mut locked = LockedAt::new();
+                        let (_locked, guards) = locked.write_all_sorted::<LockC>(&order).unwrap();
+                        for (shard, mut guard) in order.iter().zip(guards) {
+                            // Guards are returned in the caller's order.
+                            let index = shards.iter().position(|s| core::ptr::eq(s, *shard));
+                            assert_eq!(Some((*guard / BASE) as usize), index);
+                            *guard += 1;
+                        }
+                    }
+                });
+            }
+        });
+        for (i, shard) in shards.into_iter().enumerate() {
+            assert_eq!(
+                shard.into_inner().unwrap(),
+                i as u32 * BASE + THREADS as u32 * ROUNDS
+            );
+        }
+    }
+
+    #[test]
+    fn for_each_child_mutates_children() {
+        struct Child;
+        impl LockAfter<LockA> for Child {}
+        impl LockLevel for Child {
+            type Method = MutualExclusion;
+        }
+        impl MutexLockLevel for Child {
+            type Mutex = Mutex<usize>;
+        }
+
+        let node = Mutex::new(10);
+        let children = [Mutex::new(1), Mutex::new(2), Mutex::new(3)];
+
+        let mut locked = LockedAt::new();
+        let (mut locked, node) = locked.with_lock::<LockA>(&node).unwrap();
+        locked
+            .for_each_child::<Child, _>(&children, |child| **child += *node)
+            .unwrap();
+        drop(node);
+
+        assert_eq!(children.map(|c| c.into_inner().unwrap()), [11, 12, 13]);
+    }
+
+    #[test]
+    fn touch_waits_for_holder() {
+        const HOLD_TIME: std::time::Duration = std::time::Duration::from_millis(50);
+
+        let a = Mutex::new(0);
+        let (held, wait_held) = std::sync::mpsc::channel();
+        std::thread::scope(|s| {
+            let a = &a;
+            s.spawn(move || {
+                let mut guard = a.lock().unwrap();
+                held.send(()).unwrap();
+                std::thread::sleep(HOLD_TIME);
+                *guard += 1;
+            });
+            wait_held.recv().unwrap();
+
+            let start = std::time::Instant::now();
+            let mut locked = LockedAt::new();
+            let mut locked: LockedAt<'_, Unlocked> = locked.touch::<LockA>(a).unwrap();
+            assert!(start.elapsed() >= HOLD_TIME / 2, "{:?}", start.elapsed());
+
+            // The returned handle is still at `Unlocked`, so `LockA` can be
+            // acquired with it.
+            assert_eq!(*locked.lock::<LockA>(a).unwrap(), 1);
+        });
+    }
+
+    #[test]
+    fn read_cow_clones_small_values() {
+        struct Names;
+        impl LockAfter<Unlocked> for Names {}
+        impl LockLevel for Names {
+            type Method = ReadWrite;
+        }
+        impl RwLockLevel for Names {
+            type RwLock = RwLock<Vec<&'static str>>;
+        }
+
+        let is_small = |names: &Vec<_>| names.len() <= 2;
+        let small = RwLock::new(vec!["a", "b"]);
+        let large = RwLock::new(vec!["a", "b", "c", "d"]);
+        let mut locked = LockedAt::new();
+
+        let names = locked.read_cow::<Names, _>(&small, is_small).unwrap();
+        assert!(matches!(names, CowGuard::Owned(_)));
+        // The copy doesn't hold the lock.
+        small.write().unwrap().push("c");
+        assert_eq!(*names, ["a", "b"]);
+        drop(names);
+
+        let names = locked.read_cow::<Names, _>(&large, is_small).unwrap();
+        assert!(matches!(names, CowGuard::Borrowed(_)));
+        assert!(large.try_write().is_err());
+        assert_eq!(names.len(), 4);
+    }
+
+    #[test]
+    fn lock_ref_handles() {
+        use std::sync::Arc;
+
+        struct Wrapper(Mutex<usize>);
+        impl core::ops::Deref for Wrapper {
+            type Target = Mutex<usize>;
+            fn deref(&self) -> &Mutex<usize> {
+                &self.0
+            }
+        }
+
+        fn increment<R: LockRef<LockA>>(locked: &mut LockedAt<'_, Unlocked>, r: &R) -> usize {
+            let mut guard = locked.lock_ref::<LockA, _>(r).unwrap();
+            *guard += 1;
+            *guard
+        }
+
+        let mut locked = LockedAt::new();
+        let plain = Mutex::new(0);
+        let arc = Arc::new(Mutex::new(10));
+        let wrapper = Wrapper(Mutex::new(20));
+
+        assert_eq!(increment(&mut locked, &&plain), 1);
+        assert_eq!(increment(&mut locked, &arc), 11);
+        assert_eq!(increment(&mut locked, &wrapper), 21);
+    }
+
+    #[test]
+    fn try_lock_distinguishes_contention() {
+        use std::sync::TryLockError;
+
+        let a = Mutex::new(0);
+        let mut locked = LockedAt::new();
+
+        *locked.try_lock::<LockA>(&a).unwrap() += 1;
+
+        let held = a.lock().unwrap();
+        assert!(matches!(
+            locked.try_lock::<LockA>(&a),
+            Err(TryLockError::WouldBlock)
+        ));
+        drop(held);
+
+        let _ = std::panic::catch_unwind(|| {
+            let _guard = a.lock().unwrap();
+            panic!("poisoning the lock");
+        });
+        match locked.with_try_lock::<LockA>(&a) {
+            Err(TryLockError::Poisoned(e)) => assert_eq!(*e.into_inner(), 1),
+            _ => panic!("expected a poisoned lock"),
+        };
+    }
+
+    #[test]
+    fn lock_located_reports_call_site() {
+        let a = Mutex::new(0);
+        let _ = std::panic::catch_unwind(|| {
+            let _guard = a.lock().unwrap();
+            panic!("poisoning the lock");
+        });
+
+        let mut locked = LockedAt::new();
+        let line = line!() + 1;
+        let err = locked.lock_located::<LockA>(&a).unwrap_err();
+        let location = err.location();
+        drop(err);
+
+        if cfg!(feature = "debug-locations") {
+            let location = location.unwrap();
+            assert_eq!((location.file(), location.line()), (file!(), line));
+        } else {
+            assert!(location.is_none());
+        }
+    }
+
+    #[test]
+    fn try_rw_lock_would_block() {
+        use std::sync::TryLockError;
+
+        let a = Mutex::new(1);
+        let c = RwLock::new(2);
+        let mut locked = LockedAt::new();
+
+        {
+            let reader = c.read().unwrap();
+            assert!(matches!(
+                locked.try_write_lock::<LockC>(&c),
+                Err(TryLockError::WouldBlock)
+            ));
+            // Readers can share the lock.
+            assert_eq!(*locked.try_read_lock::<LockC>(&c).unwrap(), *reader);
+        }
+
+        {
+            let writer = c.write().unwrap();
+            assert!(matches!(
+                locked.try_read_lock::<LockC>(&c),
+                Err(TryLockError::WouldBlock)
+            ));
+            drop(writer);
+        }
+
+        // Non-blocking acquisitions nest like blocking ones.
+        let (mut locked, a) = locked.with_lock::<LockA>(&a).unwrap();
+        let (_locked, mut c) = locked.with_try_write_lock::<LockC>(&c).unwrap();
+        *c += *a as u32;
+        assert_eq!(*c, 3);
+    }
+
+    #[test]
+    fn lock_timeout_expires() {
+        use std::{sync::TryLockError, time::Instant};
+
+        const TIMEOUT: Duration = Duration::from_millis(20);
+
+        let a = Mutex::new(0);
+        let c = RwLock::new(0);
+        let mut locked = LockedAt::new();
+
+        let held = a.lock().unwrap();
+        let start = Instant::now();
+        assert!(matches!(
+            locked.lock_timeout::<LockA>(&a, TIMEOUT),
+            Err(TryLockError::WouldBlock)
+        ));
+        assert!(start.elapsed() >= TIMEOUT);
+        drop(held);
+
+        let (mut locked, mut a) = locked.with_lock_timeout::<LockA>(&a, TIMEOUT).unwrap();
+        *a += 1;
+        // The timed acquisition still produces a `LockedAt` for `LockA`.
+        *locked.write_lock::<LockC>(&c).unwrap() += 1;
+    }
+
+    #[test]
+    fn reborrow_for_helpers() {
+        fn add_to_c(mut locked: LockedAt<'_, LockA>, c: &RwLock<u32>, n: u32) {
+            *locked.write_lock::<LockC>(c).unwrap() += n;
+        }
+
+        let a = Mutex::new(0);
+        let c = RwLock::new(0);
+        let mut locked = LockedAt::new();
+
+        let (mut locked, _a) = locked.with_lock::<LockA>(&a).unwrap();
+        add_to_c(locked.reborrow(), &c, 1);
+        add_to_c(locked.reborrow(), &c, 2);
+        assert_eq!(*locked.read_lock::<LockC>(&c).unwrap(), 3);
+    }
+
+    #[test]
+    fn skip_locking_to_lower_level() {
+        let c = RwLock::new(0);
+        let mut locked = LockedAt::new();
+
+        // `LockA` isn't acquired, but `LockC` is still acquired as if it were.
+        let mut locked = locked.skip_locking::<LockA>();
+        *locked.write_lock::<LockC>(&c).unwrap() += 1;
+        assert_eq!(c.into_inner().unwrap(), 1);
+    }
+
+    #[test]
+    fn lock_all_overlapping_slices() {
+        const ROUNDS: usize = 200;
+
+        let shards: Vec<_> = (0..4).map(|_| Mutex::new(0)).collect();
+        std::thread::scope(|s| {
+            for range in [0..3, 1..4, 2..4, 0..4] {
+                let shards = &shards[range];
+                s.spawn(move || {
+                    for _ in 0..ROUNDS {
+                        let mut locked = LockedAt::new();
+                        let (_locked, guards) = locked.lock_all::<LockA>(shards).unwrap();
+                        for mut guard in guards {
+                            *guard += 1;
+                        }
+                    }
+                });
+            }
+        });
+
+        let counts: Vec<_> = shards
+            .into_iter()
+            .map(|m| m.into_inner().unwrap())
+            .collect();
+        assert_eq!(counts, [2, 3, 4, 3].map(|n| n * ROUNDS));
+    }
+
+    #[test]
+    fn rw_lock_const_mode() {
+        let a = Mutex::new(1);
+        let c = RwLock::new(2);
+        let mut locked = LockedAt::new();
+        let (mut locked, _a) = locked.with_lock::<LockA>(&a).unwrap();
+
+        match locked.rw_lock::<LockC, true>(&c).unwrap() {
+            RwGuard::Write(mut guard) => *guard += 1,
+            RwGuard::Read(_) => panic!("expected a write guard"),
+        }
+
+        let guard = locked.rw_lock::<LockC, false>(&c).unwrap();
+        assert!(matches!(guard, RwGuard::Read(_)));
+        assert_eq!(*guard, 3);
+    }
+
+    #[cfg(feature = "tokio")]
+    mod tokio {
+        use std::{
+            sync::atomic::{AtomicBool, Ordering},
+            time::Duration,
+        };
+
+        use tokio::sync::Mutex;
+
+        use crate::{
+            lock::{AsyncMutexLockLevel, TryAsyncMutexLock, TryAsyncMutexLockLevel},
+            relation::LockAfter,
+            LockLevel, LockedAt, MutualExclusion, Unlocked,
+        };
+
+        struct AsyncLockA;
+        struct AsyncLockB;
+
+        impl LockAfter<Unlocked> for AsyncLockA {}
+        impl LockAfter<AsyncLockA> for AsyncLockB {}
+
+        impl LockLevel for AsyncLockA {
+            type Method = MutualExclusion;
+        }
+        impl AsyncMutexLockLevel for AsyncLockA {
+            type Mutex = Mutex<usize>;
+        }
+
+        impl LockLevel for AsyncLockB {
+            type Method = MutualExclusion;
+        }
+        impl AsyncMutexLockLevel for AsyncLockB {
+            type Mutex = Mutex<Vec<usize>>;
+        }
+
+        const HOLD_TIME: Duration = Duration::from_millis(50);
+
+        #[tokio::test]
+        async fn wait_lock_timed() {
+            let a = Mutex::new(0);
+            let mut locked = LockedAt::new();
+
+            let (guard, uncontended) = locked.wait_lock_timed::<AsyncLockA>(&a).await;
+            drop(guard);
+
+            let held = a.lock().await;
+            let (_, contended) = tokio::join!(
+                async move {
+                    tokio::time::sleep(HOLD_TIME).await;
+                    drop(held);
+                },
+                async {
+                    let (mut guard, contended) = locked.wait_lock_timed::<AsyncLockA>(&a).await;
+                    *guard += 1;
+                    contended
+                },
+            );
+
+            assert!(contended >= HOLD_TIME / 2, "{contended:?}");
+            assert!(uncontended < HOLD_TIME / 2, "{uncontended:?}");
+        }
+
+        #[test]
+        fn try_wait_read_and_write() {
+            struct Config;
+            impl LockAfter<Unlocked> for Config {}
+            impl LockLevel for Config {
+                type Method = crate::ReadWrite;
+            }
+            impl crate::lock::AsyncRwLockLevel for Config {
+                type RwLock = tokio::sync::RwLock<u32>;
+            }
+
+            let config = tokio::sync::RwLock::new(1);
+            let mut locked = LockedAt::new();
+
+            let held = config.try_read().unwrap();
+            assert_eq!(locked.try_wait_read::<Config>(&config).as_deref(), Some(&1));
+            assert!(locked.try_wait_write::<Config>(&config).is_none());
+            drop(held);
+
+            *locked.try_wait_write::<Config>(&config).unwrap() += 1;
+            assert_eq!(*config.try_read().unwrap(), 2);
+        }
+
+        #[test]
+        fn try_wait_lock_from_sync_code() {
+            struct Queue;
+            struct Stats;
+            impl LockAfter<Unlocked> for Queue {}
+            impl LockAfter<Queue> for Stats {}
+            impl LockLevel for Queue {
+                type Method = MutualExclusion;
+            }
+            impl AsyncMutexLockLevel for Queue {
+                type Mutex = tokio::sync::Mutex<Vec<u32>>;
+            }
+            impl LockLevel for Stats {
+                type Method = MutualExclusion;
+            }
+            impl AsyncMutexLockLevel for Stats {
+                type Mutex = tokio::sync::Mutex<usize>;
+            }
+
+            let (queue, stats) = (tokio::sync::Mutex::new(vec![1]), tokio::sync::Mutex::new(0));
+            let mut locked = LockedAt::new();
+
+            let held = queue.try_lock().unwrap();
+            assert!(locked.try_wait_for_lock::<Queue>(&queue).is_none());
+            drop(held);
+
+            let (mut locked, queue) = locked.try_wait_for_lock::<Queue>(&queue).unwrap();
+            *locked.try_wait_lock::<Stats>(&stats).unwrap() = queue.len();
+            drop(queue);
+            assert_eq!(*stats.try_lock().unwrap(), 1);
+        }
+
+        #[test]
+        fn skipped_level_held_across_await_is_send() {
+            fn assert_send<T: Send>(_: T) {}
+
+            let log = Mutex::new(Vec::new());
+            let mut locked = LockedAt::new();
+            assert_send(async {
+                let mut locked = locked.skip_locking::<AsyncLockA>();
+                tokio::task::yield_now().await;
+                locked.wait_lock::<AsyncLockB>(&log).await.push(1);
+            });
+        }
+
+        #[tokio::test]
+        async fn wait_lock_until_retries() {
+            let (a, queue) = (Mutex::new(0), Mutex::new(Vec::new()));
+            let pushed = tokio::sync::Notify::new();
+            let mut locked = LockedAt::new();
+            let (mut locked, _a) = locked.wait_for_lock::<AsyncLockA>(&a).await;
+
+            let mut checks = 0;
+            let (message, ()) = tokio::join!(
+                locked.wait_lock_until::<AsyncLockB, _, _>(
+                    &queue,
+                    |queue| {
+                        checks += 1;
+                        queue.pop()
+                    },
+                    || pushed.notified(),
+                ),
+                async {
+                    for message in 1..=3 {
+                        tokio::task::yield_now().await;
+                        queue.lock().await.push(message);
+                        pushed.notify_waiters();
+                    }
+                },
+            );
+
+            assert_eq!(message, 1);
+            // Checked once before the first push and once after, without
+            // spinning while the other task yields.
+            assert_eq!(checks, 2);
+            assert_eq!(*queue.lock().await, [2, 3]);
+        }
+
+        #[tokio::test]
+        async fn boxed_future_handler() {
+            use std::{future::Future, pin::Pin};
+
+            /// Pluggable handler that runs while `AsyncLockA` is held.
+            trait Processor {
+                fn process<'a>(
+                    &'a self,
+                    locked: &'a mut LockedAt<'_, AsyncLockA>,
+                    log: &'a Mutex<Vec<usize>>,
+                    value: usize,
+                ) -> Pin<Box<dyn Future<Output = ()> + 'a>>;
+            }
+
+            struct Record;
+            impl Processor for Record {
+                fn process<'a>(
+                    &'a self,
+                    locked: &'a mut LockedAt<'_, AsyncLockA>,
+                    log: &'a Mutex<Vec<usize>>,
+                    value: usize,
+                ) -> Pin<Box<dyn Future<Output = ()> + 'a>> {
+                    Box::pin(async move {
+                        locked.wait_lock::<AsyncLockB>(log).await.push(value);
+                    })
+                }
+            }
+
+            let processors: Vec<Box<dyn Processor>> = vec![Box::new(Record), Box::new(Record)];
+            let a = Mutex::new(7);
+            let log = Mutex::new(Vec::new());
+
+            let mut locked = LockedAt::new();
+            let (mut locked, guard) = locked.wait_for_lock::<AsyncLockA>(&a).await;
+            for processor in &processors {
+                processor.process(&mut locked, &log, *guard).await;
+            }
+            drop(guard);
+
+            assert_eq!(log.into_inner(), [7, 7]);
+        }
+
+        /// A lock that fails once it has been closed.
+        struct Closable {
+            mutex: Mutex<usize>,
+            closed: AtomicBool,
+        }
+
+        impl TryAsyncMutexLock for Closable {
+            type Error<'a> = &'static str;
+            type Guard<'a> = tokio::sync::MutexGuard<'a, usize>;
+
+            async fn lock(&self) -> Result<Self::Guard<'_>, Self::Error<'_>> {
+                if self.closed.load(Ordering::Relaxed) {
+                    return Err("closed");
+                }
+                Ok(self.mutex.lock().await)
+            }
+        }
+
+        struct ClosableLock;
+
+        impl LockAfter<Unlocked> for ClosableLock {}
+
+        impl LockLevel for ClosableLock {
+            type Method = MutualExclusion;
+        }
+        impl TryAsyncMutexLockLevel for ClosableLock {
+            type Mutex = Closable;
+        }
+
+        #[tokio::test]
+        async fn wait_for_lock_fallible() {
+            let lock = Closable {
+                mutex: Mutex::new(1),
+                closed: false.into(),
+            };
+            let mut locked = LockedAt::new();
+
+            let mut guard = locked
+                .wait_lock_fallible::<ClosableLock>(&lock)
+                .await
+                .unwrap();
+            *guard += 1;
+            drop(guard);
+            assert_eq!(*lock.mutex.lock().await, 2);
+
+            lock.closed.store(true, Ordering::Relaxed);
+            assert!(matches!(
+                locked.wait_for_lock_fallible::<ClosableLock>(&lock).await,
+                Err("closed")
+            ));
+        }
+
+        #[tokio::test]
+        async fn owned_guard_moves_to_task() {
+            use std::sync::Arc;
+
+            let a = Arc::new(Mutex::new(1));
+            let b = Arc::new(Mutex::new(Vec::new()));
+
+            let (locked, guard) = LockedAt::new()
+                .wait_for_lock_owned::<AsyncLockA>(Arc::clone(&a))
+                .await;
+            // The guard and the `LockedAt` it is tied to move together.
+            let task = tokio::spawn({
+                let b = Arc::clone(&b);
+                async move {
+                    let (mut locked, mut guard) = (locked, guard);
+                    locked.wait_lock::<AsyncLockB>(&b).await.push(*guard);
+                    *guard += 1;
+                }
+            });
+            task.await.unwrap();
+
+            assert_eq!(*a.lock().await, 2);
+            assert_eq!(*b.lock().await, [1]);
+        }
+
+        #[tokio::test]
+        async fn wait_lock_indexed() {
+            struct Connections;
+            struct Connection;
+
+            impl LockAfter<Unlocked> for Connections {}
+            impl LockAfter<Connections> for Connection {}
+
+            impl LockLevel for Connections {
+                type Method = MutualExclusion;
+            }
+            impl AsyncMutexLockLevel for Connections {
+                type Mutex = Mutex<Vec<Mutex<usize>>>;
+            }
+
+            impl LockLevel for Connection {
+                type Method = MutualExclusion;
+            }
+            impl AsyncMutexLockLevel for Connection {
+                type Mutex = Mutex<usize>;
+            }
+
+            let connections = Mutex::new((0..3).map(Mutex::new).collect());
+            let mut locked = LockedAt::new();
+
+            {
+                let (mut locked, all) = locked.wait_for_lock::<Connections>(&connections).await;
+                for i in [2, 0, 2] {
+                    *locked.wait_lock_indexed::<Connection>(&all, i).await += 10;
+                }
+            }
+
+            let connections: Vec<_> = connections
+                .into_inner()
+                .into_iter()
+                .map(Mutex::into_inner)
+                .collect();
+            assert_eq!(connections, [10, 1, 22]);
+        }
+
+        #[tokio::test]
+        async fn wait_lock_all_sorted_shuffled() {
+            use std::sync::Arc;
+
+            const TASKS: usize = 4;
+            const ROUNDS: usize = 50;
+
+            let shards = Arc::new([Mutex::new(0), Mutex::new(0), Mutex::new(0)]);
+            let tasks: Vec<_> = (0..TASKS)
+                .map(|t| {
+                    let shards = Arc::clone(&shards);
+                    tokio::spawn(async move {
+                        // Each task lists the shards in a different order.
+                        let mut order: Vec<_> = shards.iter().collect();
+                        order.rotate_left(t % shards.len());
+                        if t % 2 == 1 {
+                            order.reverse();
+                        }
+
+                        for _ in 0..ROUNDS {
+                            let mut locked = LockedAt::new();
+                            let (_locked, guards) =
+                                locked.wait_lock_all_sorted::<AsyncLockA>(&order).await;
+                            for mut guard in guards {
+                                *guard += 1;
+                                // Let other tasks run while holding the locks.
+                                tokio::task::yield_now().await;
+                            }
+                        }
+                    })
+                })
+                .collect();
+
+            tokio::time::timeout(Duration::from_secs(10), async {
+                for task in tasks {
+                    task.await.unwrap();
+                }
+            })
+            .await
+            .expect("tasks deadlocked");
+
+            for shard in shards.iter() {
+                assert_eq!(*shard.lock().await, TASKS * ROUNDS);
+            }
+        }
+
+        #[cfg(feature = "stream")]
+        #[tokio::test]
+        async fn wait_lock_each() {
+            let mutexes = [Mutex::new(1), Mutex::new(2), Mutex::new(3)];
+            let mut locked = LockedAt::new();
+
+            let mut seen = Vec::new();
+            locked
+                .wait_lock_each::<AsyncLockA, _, _>(
+                    futures::stream::iter(&mutexes),
+                    |_locked, mut guard| {
+                        *guard *= 10;
+                        seen.push(*guard);
+                    },
+                )
+                .await;
+
+            assert_eq!(seen, [10, 20, 30]);
+            assert_eq!(mutexes.map(Mutex::into_inner), [10, 20, 30]);
+        }
     }
 }
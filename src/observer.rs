@@ -0,0 +1,129 @@
+//! Pluggable hooks for lock acquisition metrics.
+//!
+//! With the `observer` feature enabled, mutex acquisitions through
+//! [`LockedAt`](crate::LockedAt) are reported to the [`LockObserver`]
+//! installed on the current thread with [`set_observer`], if any. Like the
+//! `counters` feature, whether an acquisition blocked is detected by first
+//! trying to acquire the lock without blocking.
+//!
+//! Levels are identified by the name of their type, as produced by
+//! [`core::any::type_name`]. (A [`TypeId`](core::any::TypeId) would require
+//! every level to be `'static`, which acquisitions don't otherwise need.)
+
+use std::{cell::RefCell, rc::Rc, time::Duration};
+
+/// Receives callbacks around lock acquisitions.
+///
+/// Both methods default to doing nothing, so implementations only need to
+/// override the ones they care about.
+pub trait LockObserver {
+    /// Called before the lock for `level` is requested.
+    fn on_acquire_start(&self, level: &'static str) {
+        let _ = level;
+    }
+
+    /// Called once the lock for `level` has been acquired, or has failed to
+    /// be, with whether it was held elsewhere when requested and how long
+    /// the acquisition took.
+    fn on_acquire_end(&self, level: &'static str, blocked: bool, wait: Duration) {
+        let _ = (level, blocked, wait);
+    }
+}
+
+thread_local! {
+    static OBSERVER: RefCell<Option<Rc<dyn LockObserver>>> = const { RefCell::new(None) };
+}
+
+/// Installs `observer` for acquisitions made on the current thread.
+///
+/// The observer stays installed until the returned guard is dropped, at
+/// which point the previously installed one (if any) is restored.
+pub fn set_observer(observer: Rc<dyn LockObserver>) -> ObserverGuard {
+    ObserverGuard {
+        previous: OBSERVER.with_borrow_mut(|current| current.replace(observer)),
+    }
+}
+
+/// Keeps a [`LockObserver`] installed on the current thread.
+///
+/// Returned by [`set_observer`].
+#[must_use = "the observer is uninstalled when the guard is dropped"]
+pub struct ObserverGuard {
+    previous: Option<Rc<dyn LockObserver>>,
+}
+
+impl Drop for ObserverGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        OBSERVER.with_borrow_mut(|current| *current = previous);
+    }
+}
+
+fn current() -> Option<Rc<dyn LockObserver>> {
+    OBSERVER.with_borrow(Option::clone)
+}
+
+pub(crate) fn acquire_start(level: &'static str) {
+    if let Some(observer) = current() {
+        observer.on_acquire_start(level);
+    }
+}
+
+pub(crate) fn acquire_end(level: &'static str, blocked: bool, wait: Duration) {
+    if let Some(observer) = current() {
+        observer.on_acquire_end(level, blocked, wait);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc, sync::Mutex, time::Duration};
+
+    use crate::{
+        lock::MutexLockLevel, relation::LockAfter, LockLevel, LockedAt, MutualExclusion, Unlocked,
+    };
+
+    use super::{set_observer, LockObserver};
+
+    struct Observed;
+
+    impl LockAfter<Unlocked> for Observed {}
+
+    impl LockLevel for Observed {
+        type Method = MutualExclusion;
+    }
+    impl MutexLockLevel for Observed {
+        type Mutex = Mutex<u32>;
+    }
+
+    #[derive(Default)]
+    struct Log(RefCell<Vec<String>>);
+
+    impl LockObserver for Log {
+        fn on_acquire_start(&self, level: &'static str) {
+            self.0.borrow_mut().push(format!("start {level}"));
+        }
+
+        fn on_acquire_end(&self, level: &'static str, blocked: bool, _wait: Duration) {
+            self.0.borrow_mut().push(format!("end {level} {blocked}"));
+        }
+    }
+
+    #[test]
+    fn observer_is_scoped_to_guard() {
+        let observed = Mutex::new(0);
+        let log = Rc::new(Log::default());
+        let level = core::any::type_name::<Observed>();
+
+        {
+            let _guard = set_observer(log.clone());
+            *LockedAt::new().lock::<Observed>(&observed).unwrap() += 1;
+        }
+        *LockedAt::new().lock::<Observed>(&observed).unwrap() += 1;
+
+        assert_eq!(
+            *log.0.borrow(),
+            [format!("start {level}"), format!("end {level} false")]
+        );
+    }
+}
@@ -21,7 +21,405 @@ pub trait LockBefore<Other> {}
 /// `A` is held, a lock with level `B` can be acquired. The trait [`LockBefore`]
 /// is blanket-implemented in terms of this trait, so `B: LockAfter<A>` implies
 /// `A: LockBefore<B>`.
-
 pub trait LockAfter<Other> {}
 
 impl<Before, After> LockBefore<After> for Before where After: LockAfter<Before> {}
+
+/// Indicates that holding `Self` means holding at least `Level`.
+///
+/// The bound `M: LockAtLeast<X>` is satisfied if `M` is `X` or comes directly
+/// after it. It is meant as a precondition for functions that don't acquire
+/// locks themselves but rely on the caller holding some level:
+///
+/// ```
+/// # use lock_ordering::{relation::LockAtLeast, LockedAt};
+/// # struct Accounts;
+/// /// Must be called with `Accounts` (or a level after it) held.
+/// fn audit<M: LockAtLeast<Accounts>>(_locked: &mut LockedAt<'_, M>) {}
+/// ```
+///
+/// `M: LockAfter<X>` implies `M: LockAtLeast<X>`. The reflexive case isn't
+/// blanket-implemented, since it would overlap with that; write
+/// `impl LockAtLeast<X> for X {}` for each level `X` that is used in such a
+/// bound. Like `LockAfter`, this isn't transitive.
+pub trait LockAtLeast<Level> {}
+
+impl<Level, M> LockAtLeast<Level> for M where M: LockAfter<Level> {}
+
+impl LockAtLeast<crate::Unlocked> for crate::Unlocked {}
+
+/// Level for holding exactly one of the levels `B` and `C`.
+///
+/// Produced by [`LockedAt::with_lock_either`](crate::LockedAt::with_lock_either)
+/// when which of two sibling levels to acquire is chosen at runtime. Since
+/// it isn't known which one is held, only levels that come after both `B`
+/// and `C` should be acquired from it. Those are declared with
+/// [`impl_lock_after_one_of!`](crate::impl_lock_after_one_of), which checks
+/// that each level really does come after both.
+pub struct OneOf<B, C>(core::convert::Infallible, core::marker::PhantomData<(B, C)>);
+
+/// Implements [`LockAfter<OneOf<B, C>>`](OneOf) for levels after both `B`
+/// and `C`.
+///
+/// `impl_lock_after_one_of! { OneOf<B, C> => X, Y }` expands to
+/// `impl LockAfter<OneOf<B, C>> for X {}` and likewise for `Y`. Each impl
+/// requires `X: LockAfter<B> + LockAfter<C>`, so listing a level that
+/// doesn't come after both, including `B` or `C` themselves, fails to
+/// compile. Multiple entries can be given, separated by `;`.
+///
+/// ```
+/// use lock_ordering::{
+///     impl_lock_after_one_of, impl_lock_order_dag,
+///     relation::{LockAfter, OneOf},
+///     Unlocked,
+/// };
+///
+/// enum Primary {}
+/// enum Replica {}
+/// enum Stats {}
+///
+/// impl_lock_order_dag! {
+///     Primary: after(Unlocked);
+///     Replica: after(Unlocked);
+///     Stats: after(Unlocked, Primary, Replica);
+/// }
+/// impl_lock_after_one_of! { OneOf<Primary, Replica> => Stats }
+///
+/// fn lock_after<A, B: LockAfter<A>>() {}
+/// lock_after::<OneOf<Primary, Replica>, Stats>();
+/// ```
+///
+/// ```compile_fail
+/// # use lock_ordering::{impl_lock_after_one_of, impl_lock_order_dag, Unlocked};
+/// # enum Primary {}
+/// # enum Replica {}
+/// # impl_lock_order_dag! { Primary: after(Unlocked); Replica: after(Unlocked) }
+/// impl_lock_after_one_of! { OneOf<Primary, Replica> => Replica }
+/// ```
+#[macro_export]
+macro_rules! impl_lock_after_one_of {
+    ($(OneOf<$b:ty, $c:ty> => $($after:ty),+ $(,)?);+ $(;)?) => {
+        $($crate::impl_lock_after_one_of!(@impls $b, $c; $($after),+);)+
+    };
+    (@impls $b:ty, $c:ty; $($after:ty),+) => {
+        $(impl $crate::relation::LockAfter<$crate::relation::OneOf<$b, $c>> for $after
+        where
+            $after: $crate::relation::LockAfter<$b> + $crate::relation::LockAfter<$c>,
+        {
+        })+
+    };
+}
+
+/// Declares marker types for a linear chain of lock levels.
+///
+/// `lock_hierarchy! { Unlocked => A => B => C }` declares `A`, `B`, and `C`
+/// as empty enums and implements [`LockAfter`] so that each level can be
+/// acquired while holding any level to its left. Since `LockAfter` isn't
+/// transitive, that includes the indirect pairs like `C: LockAfter<A>`, so
+/// holding `A` allows skipping straight to `C`.
+///
+/// The first entry is an existing level that roots the chain and isn't
+/// declared. Each declared level can be preceded by attributes and a
+/// visibility:
+///
+/// ```
+/// use std::sync::Mutex;
+///
+/// use lock_ordering::{
+///     lock::MutexLockLevel, lock_hierarchy, LockLevel, LockedAt, MutualExclusion, Unlocked,
+/// };
+///
+/// lock_hierarchy! {
+///     Unlocked
+///         => /// Marker for the accounts table.
+///            pub Accounts
+///         => pub(crate) Ledger
+/// }
+///
+/// impl LockLevel for Accounts {
+///     type Method = MutualExclusion;
+/// }
+/// impl MutexLockLevel for Accounts {
+///     type Mutex = Mutex<u32>;
+/// }
+/// impl LockLevel for Ledger {
+///     type Method = MutualExclusion;
+/// }
+/// impl MutexLockLevel for Ledger {
+///     type Mutex = Mutex<Vec<u32>>;
+/// }
+///
+/// let ledger = Mutex::new(Vec::new());
+/// let mut locked = LockedAt::new();
+/// // `Ledger` can be acquired directly from `Unlocked`.
+/// locked.lock::<Ledger>(&ledger).unwrap().push(1);
+/// ```
+#[macro_export]
+macro_rules! lock_hierarchy {
+    (@after [$($before:ty),+]) => {};
+    (@after [$($before:ty),+] $level:ident $($rest:ident)*) => {
+        $(impl $crate::relation::LockAfter<$before> for $level {})+
+        $crate::lock_hierarchy!(@after [$($before,)+ $level] $($rest)*);
+    };
+    ($root:ty $(=> $(#[$attr:meta])* $vis:vis $level:ident)+) => {
+        $($(#[$attr])* $vis enum $level {})+
+        $crate::lock_hierarchy!(@after [$root] $($level)+);
+    };
+}
+
+/// Implements [`LockAfter`] for levels that each come after several others.
+///
+/// `impl_lock_order_dag! { C: after(A, B) }` expands to
+/// `impl LockAfter<A> for C {}` and `impl LockAfter<B> for C {}`, exactly as
+/// if written by hand. Multiple entries can be given, separated by `;`.
+///
+/// Since `LockAfter` isn't transitive and impls can't be inspected by a
+/// macro, indirect predecessors have to be listed too: if `B` comes after
+/// `A` and `C` after `B`, `C` should list both to allow acquiring it while
+/// holding `A`.
+///
+/// ```
+/// use lock_ordering::{impl_lock_order_dag, relation::LockAfter, Unlocked};
+///
+/// enum Accounts {}
+/// enum Sessions {}
+/// enum Audit {}
+///
+/// impl_lock_order_dag! {
+///     Accounts: after(Unlocked);
+///     Sessions: after(Unlocked);
+///     Audit: after(Unlocked, Accounts, Sessions);
+/// }
+///
+/// fn lock_after<A, B: LockAfter<A>>() {}
+/// lock_after::<Accounts, Audit>();
+/// lock_after::<Sessions, Audit>();
+/// ```
+///
+/// An entry can be prefixed with `for<const N: usize>` to declare the
+/// ordering for every instance of const-generic levels. This gives several
+/// identical subsystems their own levels, which share the ordering within
+/// each subsystem, while the ordering between subsystems is declared per
+/// instance:
+///
+/// ```
+/// use std::sync::Mutex;
+///
+/// use lock_ordering::{
+///     impl_lock_order_dag, lock::MutexLockLevel, LockLevel, LockedAt, MutualExclusion, Unlocked,
+/// };
+///
+/// struct Queue<const N: usize>;
+/// struct Stats<const N: usize>;
+///
+/// impl<const N: usize> LockLevel for Queue<N> {
+///     type Method = MutualExclusion;
+/// }
+/// impl<const N: usize> MutexLockLevel for Queue<N> {
+///     type Mutex = Mutex<Vec<u32>>;
+/// }
+/// impl<const N: usize> LockLevel for Stats<N> {
+///     type Method = MutualExclusion;
+/// }
+/// impl<const N: usize> MutexLockLevel for Stats<N> {
+///     type Mutex = Mutex<usize>;
+/// }
+///
+/// impl_lock_order_dag! {
+///     for<const N: usize> Queue<N>: after(Unlocked);
+///     for<const N: usize> Stats<N>: after(Unlocked, Queue<N>);
+///     // Subsystem 0 is always locked before subsystem 1.
+///     Queue<1>: after(Queue<0>, Stats<0>);
+/// }
+///
+/// let (queues, stats) = ([Mutex::new(vec![1]), Mutex::new(vec![2])], Mutex::new(0));
+/// let mut locked = LockedAt::new();
+/// let (mut locked, first) = locked.with_lock::<Queue<0>>(&queues[0]).unwrap();
+/// let (mut locked, second) = locked.with_lock::<Queue<1>>(&queues[1]).unwrap();
+/// *locked.lock::<Stats<1>>(&stats).unwrap() += first.len() + second.len();
+/// ```
+#[macro_export]
+macro_rules! impl_lock_order_dag {
+    (@entries) => {};
+    (@entries
+        for<$(const $param:ident: $param_ty:ty),+ $(,)?>
+        $level:ty: after($($before:ty),+ $(,)?) $(; $($rest:tt)*)?
+    ) => {
+        $crate::impl_lock_order_dag!(@impls [$(const $param: $param_ty),+] $level; $($before),+);
+        $crate::impl_lock_order_dag!(@entries $($($rest)*)?);
+    };
+    (@entries $level:ty: after($($before:ty),+ $(,)?) $(; $($rest:tt)*)?) => {
+        $crate::impl_lock_order_dag!(@impls [] $level; $($before),+);
+        $crate::impl_lock_order_dag!(@entries $($($rest)*)?);
+    };
+    (@impls $generics:tt $level:ty; $($before:ty),+) => {
+        $($crate::impl_lock_order_dag!(@impl $generics $level, $before);)+
+    };
+    (@impl [$($generics:tt)*] $level:ty, $before:ty) => {
+        impl<$($generics)*> $crate::relation::LockAfter<$before> for $level {}
+    };
+    ($($entries:tt)+) => {
+        $crate::impl_lock_order_dag!(@entries $($entries)+);
+    };
+}
+
+/// Fails to compile unless the listed levels can be acquired in order.
+///
+/// Each `Before => After` entry checks that `After: LockAfter<Before>`, i.e.
+/// that a lock at `After` can be acquired while holding `Before`. This
+/// records intended orderings where they are easy to find, independent of
+/// where the locks are acquired, and catches refactors that drop them.
+///
+/// ```
+/// use lock_ordering::{assert_lock_order, relation::LockAfter, Unlocked};
+///
+/// enum Accounts {}
+/// enum Ledger {}
+/// impl LockAfter<Unlocked> for Accounts {}
+/// impl LockAfter<Accounts> for Ledger {}
+///
+/// assert_lock_order!(Unlocked => Accounts, Accounts => Ledger);
+/// ```
+///
+/// ```compile_fail
+/// # use lock_ordering::{assert_lock_order, relation::LockAfter, Unlocked};
+/// # enum Accounts {}
+/// # enum Ledger {}
+/// # impl LockAfter<Unlocked> for Accounts {}
+/// # impl LockAfter<Accounts> for Ledger {}
+/// assert_lock_order!(Ledger => Accounts);
+/// ```
+#[macro_export]
+macro_rules! assert_lock_order {
+    ($($before:ty => $after:ty),+ $(,)?) => {
+        $(const _: fn() = || {
+            fn lock_after<A, B: ?Sized + $crate::relation::LockAfter<A>>() {}
+            lock_after::<$before, $after>();
+        };)+
+    };
+}
+
+/// Fails to compile if the [`LockAfter`] impls between the listed levels
+/// contain a cycle.
+///
+/// Nothing prevents writing `LockAfter` impls that form a cycle, like
+/// `B: LockAfter<A>` alongside `A: LockAfter<B>`, which would make it possible
+/// to acquire the two levels in either order. This macro takes the levels in
+/// their intended order, from first-acquired to last-acquired, and checks that
+/// no level is `LockAfter` itself or any level listed after it. Any cycle
+/// among the listed levels contains such a backwards edge, so this catches
+/// all of them. Levels that aren't listed aren't checked.
+///
+/// ```
+/// use lock_ordering::{assert_acyclic_lock_order, relation::LockAfter, Unlocked};
+///
+/// enum A {}
+/// enum B {}
+/// impl LockAfter<Unlocked> for A {}
+/// impl LockAfter<A> for B {}
+///
+/// assert_acyclic_lock_order!(Unlocked, A, B);
+/// ```
+///
+/// A violation is reported as an ambiguity error ("type annotations needed")
+/// pointing at the earlier level of the offending pair:
+///
+/// ```compile_fail
+/// # use lock_ordering::{assert_acyclic_lock_order, relation::LockAfter, Unlocked};
+/// # enum A {}
+/// # enum B {}
+/// # impl LockAfter<Unlocked> for A {}
+/// # impl LockAfter<A> for B {}
+/// impl LockAfter<B> for A {}
+///
+/// assert_acyclic_lock_order!(Unlocked, A, B);
+/// ```
+#[macro_export]
+macro_rules! assert_acyclic_lock_order {
+    (@check [$($before:ty),*]) => {};
+    (@check [$($before:ty),*] $level:ty $(, $rest:ty)*) => {
+        $($crate::assert_acyclic_lock_order!(@not_after $before, $level);)*
+        $crate::assert_acyclic_lock_order!(@not_after $level, $level);
+        $crate::assert_acyclic_lock_order!(@check [$($before,)* $level] $($rest),*);
+    };
+    (@not_after $earlier:ty, $later:ty) => {
+        const _: fn() = || {
+            // Resolving `check` is ambiguous exactly when `$earlier` has a
+            // `LockAfter<$later>` impl.
+            trait AmbiguousIfLockAfter<A> {
+                fn check() {}
+            }
+            impl<T: ?Sized> AmbiguousIfLockAfter<()> for T {}
+            struct LockedAfterLaterLevel;
+            impl<T: ?Sized + $crate::relation::LockAfter<$later>>
+                AmbiguousIfLockAfter<LockedAfterLaterLevel> for T
+            {
+            }
+            let _ = <$earlier as AmbiguousIfLockAfter<_>>::check;
+        };
+    };
+    ($($level:ty),+ $(,)?) => {
+        $crate::assert_acyclic_lock_order!(@check [] $($level),+);
+    };
+}
+
+/// Fails to compile unless the listed orderings agree with the levels' ranks.
+///
+/// Each `Before => After` entry checks that `After: LockAfter<Before>` and
+/// that [`RankedLockLevel::RANK`](crate::RankedLockLevel::RANK) is lower for
+/// `Before` than for `After`, so ranks can stand in for the ordering at
+/// runtime.
+///
+/// ```
+/// use lock_ordering::{assert_rank_order, relation::LockAfter, RankedLockLevel, Unlocked};
+///
+/// enum Accounts {}
+/// enum Ledger {}
+/// impl LockAfter<Unlocked> for Accounts {}
+/// impl LockAfter<Accounts> for Ledger {}
+/// impl RankedLockLevel for Accounts {
+///     const RANK: u32 = 10;
+/// }
+/// impl RankedLockLevel for Ledger {
+///     const RANK: u32 = 20;
+/// }
+///
+/// assert_rank_order!(Unlocked => Accounts, Accounts => Ledger);
+/// ```
+///
+/// A rank that doesn't increase is a compile-time error:
+///
+/// ```compile_fail
+/// # use lock_ordering::{assert_rank_order, relation::LockAfter, RankedLockLevel, Unlocked};
+/// # enum Accounts {}
+/// # enum Ledger {}
+/// # impl LockAfter<Unlocked> for Accounts {}
+/// # impl LockAfter<Accounts> for Ledger {}
+/// impl RankedLockLevel for Accounts {
+///     const RANK: u32 = 20;
+/// }
+/// impl RankedLockLevel for Ledger {
+///     const RANK: u32 = 10;
+/// }
+///
+/// assert_rank_order!(Accounts => Ledger);
+/// ```
+#[macro_export]
+macro_rules! assert_rank_order {
+    ($($before:ty => $after:ty),+ $(,)?) => {
+        $(const _: () = {
+            fn lock_after<A, B: ?Sized + $crate::relation::LockAfter<A>>() {}
+            let _ = lock_after::<$before, $after>;
+            assert!(
+                <$before as $crate::RankedLockLevel>::RANK
+                    < <$after as $crate::RankedLockLevel>::RANK,
+                concat!(
+                    "rank of ",
+                    stringify!($after),
+                    " is not higher than rank of ",
+                    stringify!($before)
+                )
+            );
+        };)+
+    };
+}
@@ -0,0 +1,38 @@
+//! Tokens restricting lock acquisition to annotated functions.
+
+use core::marker::PhantomData;
+
+/// Proof that the current function is a declared lock section.
+///
+/// Values of this type are only produced by the [`lock_section`] attribute
+/// macro, which introduces one named `lock_section` into the body of the
+/// annotated function. The lifetime `'s` is tied to a local in that function,
+/// so the token can be passed down to helpers but can't be returned from or
+/// stored beyond the annotated call.
+///
+/// [`LockedAt::with_lock_in_section`](crate::LockedAt::with_lock_in_section)
+/// requires a `&LockSection` argument, which makes it a compile error to call
+/// from code that isn't (transitively) inside a `#[lock_section]` function.
+///
+/// That is the only acquisition method that takes the token. `with_lock`,
+/// `lock` and the others don't, so they can still be called anywhere; the
+/// restriction only covers code that acquires its locks through
+/// `with_lock_in_section`.
+///
+/// [`lock_section`]: macro@crate::lock_section
+pub struct LockSection<'s>(PhantomData<&'s ()>);
+
+impl<'s> LockSection<'s> {
+    /// Creates a new token branded with the lifetime of `brand`.
+    ///
+    /// # Safety
+    ///
+    /// This is intended to be called only by code generated by the
+    /// [`lock_section`](macro@crate::lock_section) attribute. Calling it
+    /// elsewhere circumvents the policy that the token enforces.
+    #[doc(hidden)]
+    pub unsafe fn new_unchecked(brand: &'s ()) -> Self {
+        let _ = brand;
+        Self(PhantomData)
+    }
+}
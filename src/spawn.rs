@@ -0,0 +1,86 @@
+//! Spawning threads and tasks with their own root [`LockedAt`].
+//!
+//! Each thread or task is a separate call tree, and needs exactly one root
+//! `LockedAt` of its own. The helpers here create that root and pass it to the
+//! spawned closure, so it can't be forgotten or created twice.
+
+use std::{io, thread};
+
+use crate::{LockedAt, ThreadToken, Unlocked};
+
+/// Spawns a thread with `builder` and runs `f` with a root `LockedAt` on it.
+///
+/// The root is created from the new thread's [`ThreadToken`], which is held
+/// until `f` returns, so [`LockedAt::new`] panics if called from inside `f`.
+/// Errors from [`thread::Builder::spawn`] are returned as-is.
+pub fn spawn_with_root<F, T>(builder: thread::Builder, f: F) -> io::Result<thread::JoinHandle<T>>
+where
+    F: for<'a> FnOnce(LockedAt<'a, Unlocked>) -> T + Send + 'static,
+    T: Send + 'static,
+{
+    builder.spawn(move || {
+        let mut token = ThreadToken::take().expect("new threads have their token");
+        f(LockedAt::with_thread_token(&mut token))
+    })
+}
+
+/// Spawns a tokio task that runs the future returned by `f` with a root
+/// `LockedAt`.
+///
+/// Tasks can move between threads, so this can't use a [`ThreadToken`]; the
+/// root is created with [`LockedAt::new`] when the task first runs.
+#[cfg(feature = "tokio-rt")]
+pub fn spawn_task_with_root<F, Fut>(f: F) -> tokio::task::JoinHandle<Fut::Output>
+where
+    F: FnOnce(LockedAt<'static, Unlocked>) -> Fut + Send + 'static,
+    Fut: core::future::Future + Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    tokio::spawn(async move { f(LockedAt::new()).await })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::{lock::MutexLockLevel, relation::LockAfter, LockLevel, MutualExclusion};
+
+    struct Counter;
+
+    impl LockAfter<Unlocked> for Counter {}
+    impl LockLevel for Counter {
+        type Method = MutualExclusion;
+    }
+    impl MutexLockLevel for Counter {
+        type Mutex = Mutex<u32>;
+    }
+
+    #[test]
+    fn thread_gets_only_root() {
+        static COUNTER: Mutex<u32> = Mutex::new(0);
+
+        let handle = spawn_with_root(thread::Builder::new(), |mut locked| {
+            *locked.lock::<Counter>(&COUNTER).unwrap() += 1;
+            std::panic::catch_unwind(LockedAt::new).is_err()
+        })
+        .unwrap();
+
+        assert!(handle.join().unwrap());
+        assert_eq!(*COUNTER.lock().unwrap(), 1);
+    }
+
+    #[cfg(feature = "tokio-rt")]
+    #[tokio::test]
+    async fn task_gets_root() {
+        static COUNTER: Mutex<u32> = Mutex::new(0);
+
+        spawn_task_with_root(|mut locked| async move {
+            *locked.lock::<Counter>(&COUNTER).unwrap() += 1;
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(*COUNTER.lock().unwrap(), 1);
+    }
+}
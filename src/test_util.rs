@@ -0,0 +1,51 @@
+//! Helpers for testing lock hierarchies in downstream crates.
+//!
+//! Only available with the `test-util` feature, which should only be enabled
+//! for dev-dependencies.
+
+use std::{fs, path::Path};
+
+/// Checks that each `.rs` file in `dir` fails to compile because of a lock
+/// ordering violation.
+///
+/// Each file is a standalone program that uses the crate's lock levels in an
+/// order that shouldn't be allowed. The files are compiled with [`trybuild`],
+/// which compares the compiler's errors with the `.stderr` file next to each
+/// one; run with `TRYBUILD=overwrite` to create or update them. Afterwards,
+/// every `.stderr` file has to mention [`LockAfter`] or [`LockBefore`], so
+/// that a fixture that stopped compiling for an unrelated reason isn't
+/// mistaken for a rejected ordering.
+///
+/// ```no_run
+/// #[test]
+/// fn lock_order_violations() {
+///     lock_ordering::test_util::assert_lock_order_violations("tests/lock-order");
+/// }
+/// ```
+///
+/// # Panics
+///
+/// Panics if any file compiles, fails with errors other than those recorded,
+/// or fails for a reason unrelated to lock ordering.
+///
+/// [`LockAfter`]: crate::relation::LockAfter
+/// [`LockBefore`]: crate::relation::LockBefore
+pub fn assert_lock_order_violations(dir: impl AsRef<Path>) {
+    let dir = dir.as_ref();
+    {
+        let t = trybuild::TestCases::new();
+        t.compile_fail(dir.join("*.rs"));
+    }
+
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|ext| ext == "stderr") {
+            let stderr = fs::read_to_string(&path).unwrap();
+            assert!(
+                stderr.contains("LockAfter") || stderr.contains("LockBefore"),
+                "{} doesn't report a lock ordering violation",
+                path.display()
+            );
+        }
+    }
+}
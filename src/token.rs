@@ -0,0 +1,75 @@
+//! Per-thread token for creating a root [`LockedAt`](crate::LockedAt).
+
+use core::marker::PhantomData;
+use std::cell::Cell;
+
+std::thread_local! {
+    /// Whether this thread's [`ThreadToken`] is currently checked out.
+    static CHECKED_OUT: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Proof that the holder is the only user of this thread's lock ordering.
+///
+/// Each thread has a single token, which can be taken with
+/// [`ThreadToken::take`] and is returned when dropped. A root `LockedAt`
+/// created from it with
+/// [`LockedAt::with_thread_token`](crate::LockedAt::with_thread_token)
+/// borrows the token exclusively, so safe code can't create two roots from
+/// it at the same time.
+///
+/// The token is neither [`Send`] nor [`Sync`], since it only vouches for the
+/// thread it was taken on.
+#[derive(Debug)]
+pub struct ThreadToken {
+    _not_send: PhantomData<*const ()>,
+}
+
+impl ThreadToken {
+    /// Takes the current thread's token.
+    ///
+    /// Returns `None` if the token is already checked out.
+    pub fn take() -> Option<Self> {
+        CHECKED_OUT.with(|checked_out| {
+            (!checked_out.replace(true)).then(|| Self {
+                _not_send: PhantomData,
+            })
+        })
+    }
+
+    /// Returns whether the current thread's token is checked out.
+    pub fn is_checked_out() -> bool {
+        CHECKED_OUT.with(Cell::get)
+    }
+}
+
+impl Drop for ThreadToken {
+    fn drop(&mut self) {
+        CHECKED_OUT.with(|checked_out| checked_out.set(false));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LockedAt;
+
+    #[test]
+    fn taken_once_per_thread() {
+        let mut token = ThreadToken::take().unwrap();
+        assert!(ThreadToken::take().is_none());
+
+        // Other threads have their own token.
+        std::thread::spawn(|| assert!(ThreadToken::take().is_some()))
+            .join()
+            .unwrap();
+
+        {
+            let _locked = LockedAt::with_thread_token(&mut token);
+            assert!(std::panic::catch_unwind(LockedAt::new).is_err());
+        }
+
+        drop(token);
+        assert!(ThreadToken::take().is_some());
+        let _locked = LockedAt::new();
+    }
+}
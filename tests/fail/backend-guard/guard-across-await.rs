@@ -0,0 +1,38 @@
+use lock_ordering::{
+    lock::MutexLockLevel, relation::LockAfter, LockLevel, LockedAt, MutualExclusion, Unlocked,
+};
+
+struct Counter;
+
+impl LockAfter<Unlocked> for Counter {}
+
+impl LockLevel for Counter {
+    type Method = MutualExclusion;
+}
+impl MutexLockLevel for Counter {
+    type Mutex = std::sync::Mutex<usize>;
+}
+
+fn spawn<F: std::future::Future + Send>(_f: F) {}
+
+fn main() {
+    static COUNTER: std::sync::Mutex<usize> = std::sync::Mutex::new(0);
+
+    // The scoped form drops the guard before the await, so the future is
+    // `Send`.
+    spawn(async {
+        let mut locked = LockedAt::new();
+        locked
+            .lock_scoped::<Counter, _>(&COUNTER, |_, mut count| *count += 1)
+            .unwrap();
+        std::future::ready(()).await;
+    });
+
+    // Holding the `!Send` std guard across the await makes the future `!Send`.
+    spawn(async {
+        let mut locked = LockedAt::new();
+        let mut count = locked.lock::<Counter>(&COUNTER).unwrap();
+        std::future::ready(()).await;
+        *count += 1;
+    });
+}
@@ -0,0 +1,14 @@
+use lock_ordering::{relation::LockAfter, LockedAt, Unlocked};
+
+struct Outer;
+
+impl LockAfter<Unlocked> for Outer {}
+
+fn main() {
+    // Only `Unlocked` roots can be created directly.
+    let _root = LockedAt::new();
+
+    let _mid = LockedAt::<Outer>::new();
+    let _mid = unsafe { LockedAt::<Outer>::new_unchecked() };
+    let _mid: LockedAt<'static, Outer> = Default::default();
+}
@@ -0,0 +1,55 @@
+use lock_ordering::{
+    lock::MutexLockLevel,
+    relation::{LockAfter, LockAtLeast},
+    LockLevel, LockedAt, MutualExclusion, Unlocked,
+};
+
+struct LockA;
+struct LockB;
+struct LockC;
+
+impl LockAfter<Unlocked> for LockA {}
+impl LockAfter<LockA> for LockB {}
+impl LockAfter<LockB> for LockC {}
+
+impl LockAtLeast<LockB> for LockB {}
+
+impl LockLevel for LockA {
+    type Method = MutualExclusion;
+}
+impl MutexLockLevel for LockA {
+    type Mutex = std::sync::Mutex<()>;
+}
+
+impl LockLevel for LockB {
+    type Method = MutualExclusion;
+}
+impl MutexLockLevel for LockB {
+    type Mutex = std::sync::Mutex<()>;
+}
+
+impl LockLevel for LockC {
+    type Method = MutualExclusion;
+}
+impl MutexLockLevel for LockC {
+    type Mutex = std::sync::Mutex<()>;
+}
+
+/// Requires the caller to hold `LockB` or a level after it.
+fn requires_b<M: LockAtLeast<LockB>>(_locked: &mut LockedAt<'_, M>) {}
+
+fn main() {
+    let (a, b, c) = Default::default();
+
+    let mut locked = LockedAt::new();
+    let (mut locked, _a) = locked.with_lock::<LockA>(&a).unwrap();
+    let (mut locked_b, _b) = locked.with_lock::<LockB>(&b).unwrap();
+    requires_b(&mut locked_b);
+    let (mut locked_c, _c) = locked_b.with_lock::<LockC>(&c).unwrap();
+    requires_b(&mut locked_c);
+
+    // Holding only `LockA` isn't enough.
+    let mut locked = LockedAt::new();
+    let (mut locked, _a) = locked.with_lock::<LockA>(&a).unwrap();
+    requires_b(&mut locked);
+}
@@ -0,0 +1,15 @@
+use lock_ordering::{assert_acyclic_lock_order, relation::LockAfter, Unlocked};
+
+enum A {}
+enum B {}
+enum C {}
+
+impl LockAfter<Unlocked> for A {}
+impl LockAfter<A> for B {}
+impl LockAfter<B> for C {}
+// Closes the cycle A -> B -> C -> A.
+impl LockAfter<C> for A {}
+
+assert_acyclic_lock_order!(Unlocked, A, B, C);
+
+fn main() {}
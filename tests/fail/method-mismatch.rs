@@ -0,0 +1,25 @@
+use std::sync::{Mutex, RwLock};
+
+use lock_ordering::{
+    lock::{MutexLockLevel, RwLockLevel},
+    LockLevel, MutualExclusion, ReadWrite,
+};
+
+struct Exclusive;
+struct Shared;
+
+impl LockLevel for Exclusive {
+    type Method = MutualExclusion;
+}
+impl RwLockLevel for Exclusive {
+    type RwLock = RwLock<u32>;
+}
+
+impl LockLevel for Shared {
+    type Method = ReadWrite;
+}
+impl MutexLockLevel for Shared {
+    type Mutex = Mutex<u32>;
+}
+
+fn main() {}
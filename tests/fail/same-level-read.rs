@@ -0,0 +1,27 @@
+use lock_ordering::{
+    lock::RwLockLevel, relation::LockAfter, LockLevel, LockedAt, ReadWrite, Unlocked,
+};
+
+struct Shard;
+
+impl LockAfter<Unlocked> for Shard {}
+
+impl LockLevel for Shard {
+    type Method = ReadWrite;
+}
+impl RwLockLevel for Shard {
+    type RwLock = std::sync::RwLock<usize>;
+}
+
+fn main() {
+    let first = std::sync::RwLock::new(1);
+    let second = std::sync::RwLock::new(2);
+
+    let mut locked = LockedAt::new();
+    let (mut locked, _first_guard) = locked.with_read_lock::<Shard>(&first).unwrap();
+
+    // Only `read_recursive` can acquire another lock at the held level, and
+    // `std::sync::RwLock` doesn't support it.
+    let _second_guard = locked.read_lock::<Shard>(&second);
+    let _second_guard = locked.read_recursive(&second);
+}
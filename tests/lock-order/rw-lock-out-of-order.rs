@@ -0,0 +1,36 @@
+use lock_ordering::{
+    lock::RwLockLevel, relation::LockAfter, LockLevel, LockedAt, ReadWrite, Unlocked,
+};
+
+struct FirstLock;
+struct SecondLock;
+
+impl LockAfter<Unlocked> for FirstLock {}
+impl LockAfter<Unlocked> for SecondLock {}
+impl LockAfter<FirstLock> for SecondLock {}
+
+impl LockLevel for FirstLock {
+    type Method = ReadWrite;
+}
+impl RwLockLevel for FirstLock {
+    type RwLock = std::sync::RwLock<usize>;
+}
+
+impl LockLevel for SecondLock {
+    type Method = ReadWrite;
+}
+impl RwLockLevel for SecondLock {
+    type RwLock = std::sync::RwLock<char>;
+}
+
+fn main() {
+    let first = std::sync::RwLock::new(1234);
+    let second = std::sync::RwLock::new('b');
+
+    let mut locked = LockedAt::new();
+    let (mut locked, _second_guard) = locked.with_read_lock::<SecondLock>(&second).unwrap();
+
+    // Neither mode of `rw_lock` can acquire the first lock after the second.
+    let _first_guard = locked.rw_lock::<FirstLock, false>(&first);
+    let _first_guard = locked.rw_lock::<FirstLock, true>(&first);
+}